@@ -0,0 +1,195 @@
+//! Compares a burst of concurrent downloads through a fresh client per request with the same
+//! burst through one shared, pooled client, the way `ArtifactRegistryResourceAccess` sends them.
+//! Both are built from the same `UpstreamConfiguration`.
+//!
+//! Runs against a local HTTP server and a local HTTPS server (with a self-signed certificate),
+//! so the TLS handshakes the pool saves are included. Set `ARP_BENCH_URL` to also run against a
+//! remote URL, eg. an artifact behind a public Maven repository.
+//!
+//! ```sh
+//! cargo bench --bench upstream_client
+//! ```
+
+use std::env;
+use std::time::Duration;
+
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main, Throughput};
+use native_tls::Identity;
+use openssl::asn1::Asn1Time;
+use openssl::bn::BigNum;
+use openssl::hash::MessageDigest;
+use openssl::pkey::PKey;
+use openssl::rsa::Rsa;
+use openssl::x509::{X509Builder, X509NameBuilder};
+use openssl::x509::extension::SubjectAlternativeName;
+use reqwest::{Certificate, Client};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tokio::runtime::Runtime;
+use tokio::task::JoinSet;
+use tokio_native_tls::TlsAcceptor;
+
+use artifact_registry_proxy::upstream::{send_with_retries, UpstreamConfiguration};
+
+/// The size of every artifact served by the local servers.
+const ARTIFACT_SIZE: usize = 64 * 1024;
+
+/// A certificate for 'localhost', in PEM, and its PKCS #8 key.
+fn self_signed() -> (Vec<u8>, Vec<u8>) {
+    let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+
+    let mut name = X509NameBuilder::new().unwrap();
+    name.append_entry_by_text("CN", "localhost").unwrap();
+    let name = name.build();
+
+    let mut builder = X509Builder::new().unwrap();
+    builder.set_version(2).unwrap();
+    builder.set_serial_number(&BigNum::from_u32(1).unwrap().to_asn1_integer().unwrap()).unwrap();
+    builder.set_subject_name(&name).unwrap();
+    builder.set_issuer_name(&name).unwrap();
+    builder.set_pubkey(&key).unwrap();
+    builder.set_not_before(&Asn1Time::days_from_now(0).unwrap()).unwrap();
+    builder.set_not_after(&Asn1Time::days_from_now(1).unwrap()).unwrap();
+
+    let alternative_names = SubjectAlternativeName::new()
+        .dns("localhost")
+        .build(&builder.x509v3_context(None, None))
+        .unwrap();
+    builder.append_extension(alternative_names).unwrap();
+    builder.sign(&key, MessageDigest::sha256()).unwrap();
+
+    (builder.build().to_pem().unwrap(), key.private_key_to_pem_pkcs8().unwrap())
+}
+
+/// Answers every request on the connection with the artifact, keeping it alive.
+async fn respond<S: AsyncRead + AsyncWrite + Unpin>(mut stream: S) {
+    let mut request = Vec::new();
+    let mut buffer = [0; 4096];
+    let mut response = format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n", ARTIFACT_SIZE).into_bytes();
+    response.extend(vec![b'a'; ARTIFACT_SIZE]);
+
+    loop {
+        match stream.read(&mut buffer).await {
+            Ok(0) | Err(_) => return,
+            Ok(read) => request.extend_from_slice(&buffer[..read])
+        }
+
+        while let Some(end) = request.windows(4).position(|window| window == b"\r\n\r\n") {
+            request.drain(..end + 4);
+
+            if stream.write_all(&response).await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+/// Serves the same artifact to every request, over TLS when given an acceptor.
+async fn serve(tls: Option<TlsAcceptor>) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!(
+        "{}://localhost:{}/com/example/library/1.0.0/library-1.0.0.jar",
+        if tls.is_some() { "https" } else { "http" },
+        listener.local_addr().unwrap().port()
+    );
+
+    tokio::spawn(async move {
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            // Otherwise the TLS records of a response wait on the client's delayed ACKs.
+            stream.set_nodelay(true).unwrap();
+            let tls = tls.clone();
+
+            tokio::spawn(async move {
+                match tls {
+                    Some(tls) => if let Ok(stream) = tls.accept(stream).await {
+                        respond(stream).await
+                    },
+                    None => respond(stream).await
+                }
+            });
+        }
+    });
+
+    url
+}
+
+/// A client built the way ARP builds them, trusting the local server's certificate.
+fn client(configuration: &UpstreamConfiguration, root: Option<&Certificate>) -> Client {
+    let builder = configuration.client_builder().unwrap();
+
+    match root {
+        Some(root) => builder.add_root_certificate(root.clone()),
+        None => builder
+    }.build().unwrap()
+}
+
+async fn download(client: Client, url: String, configuration: UpstreamConfiguration) {
+    let response = send_with_retries(client.get(url), &configuration).await.unwrap();
+
+    assert!(response.status().is_success());
+    response.bytes().await.unwrap();
+}
+
+/// Downloads the artifact `size` times at once, through the shared client or a fresh one each.
+async fn burst(url: &str, size: usize, shared: Option<&Client>, root: Option<&Certificate>, configuration: &UpstreamConfiguration) {
+    let mut downloads = JoinSet::new();
+
+    for _ in 0..size {
+        let client = match shared {
+            Some(client) => client.clone(),
+            None => client(configuration, root)
+        };
+
+        downloads.spawn(download(client, url.to_string(), configuration.clone()));
+    }
+
+    while let Some(download) = downloads.join_next().await {
+        download.unwrap();
+    }
+}
+
+fn bench_burst(criterion: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let configuration = UpstreamConfiguration::default();
+
+    let (certificate, key) = self_signed();
+    let acceptor = native_tls::TlsAcceptor::new(Identity::from_pkcs8(&certificate, &key).unwrap()).unwrap();
+    let root = Certificate::from_pem(&certificate).unwrap();
+
+    let mut targets = vec![
+        ("http", runtime.block_on(serve(None)), None),
+        ("https", runtime.block_on(serve(Some(TlsAcceptor::from(acceptor)))), Some(root)),
+    ];
+
+    if let Ok(url) = env::var("ARP_BENCH_URL") {
+        targets.push(("remote", url, None));
+    }
+
+    for (target, url, root) in &targets {
+        let shared = client(&configuration, root.as_ref());
+
+        let mut group = criterion.benchmark_group(format!("burst/{}", target));
+        group.measurement_time(Duration::from_secs(10));
+
+        for size in [1, 8, 32] {
+            group.throughput(Throughput::Elements(size as u64));
+
+            group.bench_with_input(BenchmarkId::new("fresh client", size), &size, |bencher, &size| {
+                bencher.to_async(&runtime).iter(|| burst(url, size, None, root.as_ref(), &configuration))
+            });
+
+            // Warms the pool up, as it would be after the first requests of a build.
+            runtime.block_on(burst(url, size, Some(&shared), root.as_ref(), &configuration));
+
+            group.bench_with_input(BenchmarkId::new("shared client", size), &size, |bencher, &size| {
+                bencher.to_async(&runtime).iter(|| burst(url, size, Some(&shared), root.as_ref(), &configuration))
+            });
+        }
+
+        group.finish();
+    }
+}
+
+criterion_group!(benches, bench_burst);
+criterion_main!(benches);
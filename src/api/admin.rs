@@ -0,0 +1,332 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Instant;
+
+use log::{info, warn};
+use rocket::{delete, get, post, State};
+use rocket::http::Status;
+use rocket::response::status;
+use rocket::serde::json::Json;
+use rocket::serde::Serialize;
+
+use crate::{ARProxyConfiguration, RepositoryConfiguration};
+use crate::auth::{Admin, Permission};
+use crate::cache::{CacheStats, ResourceCache};
+use crate::config::BackendConfiguration;
+use crate::err::{BasicError, CacheDisabled, IOError, PrincipalNotFound, RepositoryNotFound};
+use crate::health::{check_result, Health};
+use crate::logging::LogFormat;
+use crate::reload::LiveConfiguration;
+use crate::upstream::UpstreamConfiguration;
+
+/// Shown in place of every secret.
+const REDACTED: &str = "<redacted>";
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RepositoryView {
+    pub name: String,
+    pub id: String,
+    pub backend: String,
+    pub deploy_policy: &'static str,
+    pub pom_validation: &'static str,
+    /// Formatted as `keep=<N>;max-age=<D>;drop-stale=true`.
+    pub retention: Option<String>,
+    /// The fingerprints of the keys signatures must be made by.
+    pub keyring: Option<Vec<String>>,
+    /// The fingerprint of the key ARP signs with.
+    pub signing_key: Option<String>,
+}
+
+fn repository_view(name: &str, repository: &RepositoryConfiguration) -> RepositoryView {
+    RepositoryView {
+        name: name.to_string(),
+        id: repository.id.clone(),
+        backend: repository.backend.clone(),
+        deploy_policy: repository.deploy_policy.as_str(),
+        pom_validation: repository.pom_validation.as_str(),
+        retention: repository.retention.as_ref().map(|retention| retention.to_string()),
+        keyring: repository.keyring.as_ref().map(|keyring| keyring.fingerprints()),
+        signing_key: repository.signer.as_ref().map(|signer| signer.fingerprint()),
+    }
+}
+
+fn repository_views(configuration: &ARProxyConfiguration) -> Vec<RepositoryView> {
+    let mut repositories = configuration.repositories.iter()
+        .map(|(name, repository)| repository_view(name, repository))
+        .collect::<Vec<RepositoryView>>();
+
+    repositories.sort_by(|first, second| first.name.cmp(&second.name));
+
+    repositories
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PrincipalView {
+    pub user: String,
+    pub key: &'static str,
+    pub permissions: Vec<Permission>,
+    pub revoked: bool,
+}
+
+fn principal_views(configuration: &ARProxyConfiguration, live: &LiveConfiguration) -> Vec<PrincipalView> {
+    configuration.creds.iter()
+        .map(|creds| PrincipalView {
+            user: creds.user.clone(),
+            key: REDACTED,
+            permissions: creds.permissions.clone(),
+            revoked: live.is_revoked(creds),
+        })
+        .collect()
+}
+
+/// Every configured repository, sorted by name.
+#[get("/admin/repositories")]
+pub async fn list_repositories(
+    _admin: Admin<'_>,
+    configuration: &ARProxyConfiguration,
+) -> Json<Vec<RepositoryView>> {
+    Json(repository_views(configuration))
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CacheView {
+    pub directory: PathBuf,
+    #[serde(flatten)]
+    pub stats: CacheStats,
+}
+
+fn cache(cache: &Option<Arc<ResourceCache>>) -> Result<&ResourceCache, status::Custom<Json<BasicError>>> {
+    cache.as_deref().ok_or_else(|| BasicError::from(Box::new(CacheDisabled)))
+}
+
+#[get("/admin/cache")]
+pub async fn cache_stats(
+    _admin: Admin<'_>,
+    resource_cache: &State<Option<Arc<ResourceCache>>>,
+) -> Result<Json<CacheView>, status::Custom<Json<BasicError>>> {
+    let resource_cache = cache(resource_cache)?;
+
+    let stats = resource_cache.stats()
+        .map_err(|err| BasicError::from(Box::new(IOError(err))))?;
+
+    Ok(Json(CacheView {
+        directory: resource_cache.directory.clone(),
+        stats,
+    }))
+}
+
+#[derive(Serialize)]
+pub struct PurgeReport {
+    /// The paths of the resources removed, by repository id.
+    pub purged: Vec<String>,
+}
+
+/// Removes the cached resources whose path starts with the prefix, eg. `releases/com/example/`
+/// where `releases` is the public name of a repository. Everything is purged when no prefix is
+/// given.
+#[delete("/admin/cache?<prefix>")]
+pub async fn purge_cache(
+    admin: Admin<'_>,
+    prefix: Option<&str>,
+    resource_cache: &State<Option<Arc<ResourceCache>>>,
+    configuration: &ARProxyConfiguration,
+) -> Result<Json<PurgeReport>, status::Custom<Json<BasicError>>> {
+    let resource_cache = cache(resource_cache)?;
+
+    let internal_prefix = match prefix.filter(|prefix| !prefix.is_empty()) {
+        Some(prefix) => {
+            let (repository, rest) = prefix.split_once('/').unwrap_or((prefix, ""));
+
+            let repository = configuration.repositories.get(repository).ok_or(
+                BasicError::from(Box::new(RepositoryNotFound(repository.to_string())))
+            )?;
+
+            format!("{}/{}", repository.id, rest)
+        }
+        None => String::new()
+    };
+
+    let purged = resource_cache.purge(&internal_prefix)
+        .map_err(|err| BasicError::from(Box::new(IOError(err))))?;
+
+    info!("Purged {} cached resource(s) matching: '{}', for: '{}'", purged.len(), prefix.unwrap_or_default(), admin.0.user);
+
+    Ok(Json(PurgeReport {
+        purged,
+    }))
+}
+
+/// Renews the credentials of every backend which has any, responding with `503 Service
+/// Unavailable` when any fails.
+#[post("/admin/token/refresh")]
+pub async fn refresh_tokens(
+    admin: Admin<'_>,
+    live: &State<Arc<LiveConfiguration>>,
+) -> status::Custom<Json<Health>> {
+    let mut checks = BTreeMap::new();
+
+    for (name, backend) in live.backends() {
+        let start = Instant::now();
+
+        if let Some(result) = backend.refresh_credentials().await {
+            checks.insert(format!("backends.{}.token", name), check_result(start, result));
+        }
+    }
+
+    info!("Refreshed the credentials of {} backend(s), for: '{}'", checks.len(), admin.0.user);
+
+    let refreshed = checks.values().all(|check| check.status == "ok");
+
+    status::Custom(
+        if refreshed { Status::Ok } else { Status::ServiceUnavailable },
+        Json(Health {
+            status: if refreshed { "ok" } else { "unavailable" },
+            checks,
+        }),
+    )
+}
+
+/// Every principal, without their keys.
+#[get("/admin/tokens")]
+pub async fn list_tokens(
+    _admin: Admin<'_>,
+    live: &State<Arc<LiveConfiguration>>,
+    configuration: &ARProxyConfiguration,
+) -> Json<Vec<PrincipalView>> {
+    Json(principal_views(configuration, live))
+}
+
+#[derive(Serialize)]
+pub struct Revocation {
+    pub user: String,
+    /// How many of the principal's keys were revoked, keys already revoked aren't counted.
+    pub revoked: usize,
+}
+
+/// Rejects the principal's current keys until ARP restarts. Remove the principal from the
+/// configuration (or rotate its key) to revoke it for good.
+#[delete("/admin/tokens/<user>")]
+pub async fn revoke_token(
+    admin: Admin<'_>,
+    user: &str,
+    live: &State<Arc<LiveConfiguration>>,
+    configuration: &ARProxyConfiguration,
+) -> Result<Json<Revocation>, status::Custom<Json<BasicError>>> {
+    if !configuration.creds.iter().any(|creds| creds.user == user) {
+        return Err(BasicError::from(Box::new(PrincipalNotFound(user.to_string()))));
+    }
+
+    let revoked = live.revoke(user);
+
+    warn!("Revoked {} key(s) of principal: '{}', for: '{}'", revoked, user, admin.0.user);
+
+    Ok(Json(Revocation {
+        user: user.to_string(),
+        revoked,
+    }))
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConfigurationView {
+    pub backends: BTreeMap<String, BackendConfiguration>,
+    pub repositories: Vec<RepositoryView>,
+    pub principals: Vec<PrincipalView>,
+    pub cache_directory: Option<PathBuf>,
+    pub cache_mutable_ttl: u64,
+    pub search_index_interval: u64,
+    pub retention_dry_run: bool,
+    pub retention_interval: u64,
+    pub watch_interval: u64,
+    pub tracing_endpoint: Option<String>,
+    pub log_level: String,
+    pub log_format: LogFormat,
+    pub log_file: Option<PathBuf>,
+    pub audit_file: Option<PathBuf>,
+    pub hold_directory: PathBuf,
+    pub hold_max_size_mb: u64,
+    pub upstream: UpstreamView,
+}
+
+/// How backends are reached, named as in the configuration file's `[upstream]` section.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UpstreamView {
+    pub connect_timeout: u64,
+    pub read_timeout: u64,
+    pub request_timeout: u64,
+    pub max_retries: u32,
+    pub initial_backoff_ms: u64,
+    pub max_backoff_ms: u64,
+    pub pool_max_idle_per_host: usize,
+    pub pool_idle_timeout: u64,
+    pub http2: bool,
+    /// 0 when keepalive probes are disabled.
+    pub tcp_keepalive: u64,
+    pub proxy: Option<String>,
+    pub breaker_threshold: u32,
+    pub breaker_cooldown: u64,
+}
+
+impl From<&UpstreamConfiguration> for UpstreamView {
+    fn from(upstream: &UpstreamConfiguration) -> Self {
+        UpstreamView {
+            connect_timeout: upstream.connect_timeout.as_secs(),
+            read_timeout: upstream.read_timeout.as_secs(),
+            request_timeout: upstream.request_timeout.as_secs(),
+            max_retries: upstream.max_retries,
+            initial_backoff_ms: upstream.initial_backoff.as_millis() as u64,
+            max_backoff_ms: upstream.max_backoff.as_millis() as u64,
+            pool_max_idle_per_host: upstream.pool_max_idle_per_host,
+            pool_idle_timeout: upstream.pool_idle_timeout.as_secs(),
+            http2: upstream.http2,
+            tcp_keepalive: upstream.tcp_keepalive.map(|interval| interval.as_secs()).unwrap_or_default(),
+            proxy: upstream.proxy.as_deref().map(redact_url),
+            breaker_threshold: upstream.breaker_threshold,
+            breaker_cooldown: upstream.breaker_cooldown.as_secs(),
+        }
+    }
+}
+
+/// Hides the password of a URL, eg. `https://user:<redacted>@collector`.
+fn redact_url(url: &str) -> String {
+    match reqwest::Url::parse(url) {
+        Ok(mut url) if url.password().is_some() => {
+            let _ = url.set_password(Some(REDACTED));
+            url.to_string()
+        }
+        _ => url.to_string()
+    }
+}
+
+/// The configuration ARP is running with, with every key left out. Durations are in seconds.
+#[get("/admin/config")]
+pub async fn effective_configuration(
+    _admin: Admin<'_>,
+    live: &State<Arc<LiveConfiguration>>,
+    configuration: &ARProxyConfiguration,
+) -> Json<ConfigurationView> {
+    Json(ConfigurationView {
+        backends: configuration.backends.clone().into_iter().collect(),
+        repositories: repository_views(configuration),
+        principals: principal_views(configuration, live),
+        cache_directory: configuration.cache_directory.clone(),
+        cache_mutable_ttl: configuration.cache_mutable_ttl.as_secs(),
+        search_index_interval: configuration.search_index_interval.as_secs(),
+        retention_dry_run: configuration.retention_dry_run,
+        retention_interval: configuration.retention_interval.as_secs(),
+        watch_interval: configuration.watch_interval.as_secs(),
+        tracing_endpoint: configuration.tracing_endpoint.as_deref().map(redact_url),
+        log_level: configuration.logging.level.to_string().to_lowercase(),
+        log_format: configuration.logging.format,
+        log_file: configuration.logging.file.clone(),
+        audit_file: configuration.audit_file.clone(),
+        hold_directory: configuration.hold_directory.clone(),
+        hold_max_size_mb: configuration.hold_max_size / (1024 * 1024),
+        upstream: UpstreamView::from(&configuration.upstream),
+    })
+}
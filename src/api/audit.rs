@@ -0,0 +1,82 @@
+use rocket::{get, State};
+use rocket::http::ContentType;
+use rocket::response::status;
+use rocket::serde::json::Json;
+
+use crate::audit::{AuditAction, AuditEntry, AuditLog, AuditVerification};
+use crate::auth::Admin;
+use crate::err::{AuditLogDisabled, AuditLogFailed, BasicError, InvalidAuditAction};
+
+/// The most entries a query returns when no limit is given.
+const DEFAULT_LIMIT: usize = 100;
+
+async fn entries(audit: &AuditLog) -> Result<Vec<AuditEntry>, status::Custom<Json<BasicError>>> {
+    if !audit.is_enabled() {
+        return Err(BasicError::from(Box::new(AuditLogDisabled)));
+    }
+
+    audit.entries().await.map_err(|err| BasicError::from(Box::new(AuditLogFailed(err))))
+}
+
+/// The newest entries matching every given filter, oldest first. `since` and `until` are RFC 3339
+/// timestamps.
+#[allow(clippy::too_many_arguments)]
+#[get("/audit?<repository>&<principal>&<action>&<since>&<until>&<limit>")]
+pub async fn query_audit_log(
+    _admin: Admin<'_>,
+    repository: Option<&str>,
+    principal: Option<&str>,
+    action: Option<&str>,
+    since: Option<&str>,
+    until: Option<&str>,
+    limit: Option<usize>,
+    audit: &State<AuditLog>,
+) -> Result<Json<Vec<AuditEntry>>, status::Custom<Json<BasicError>>> {
+    let action = action
+        .map(|action| AuditAction::try_from(action)
+            .map_err(|_| BasicError::from(Box::new(InvalidAuditAction(action.to_string())))))
+        .transpose()?;
+
+    let mut matching = entries(audit).await?.into_iter()
+        .filter(|entry| repository.map(|repository| entry.event.repository == repository).unwrap_or(true))
+        .filter(|entry| principal.map(|principal| entry.event.principal == principal).unwrap_or(true))
+        .filter(|entry| action.map(|action| entry.event.action == action).unwrap_or(true))
+        // RFC 3339 timestamps in UTC sort lexicographically.
+        .filter(|entry| since.map(|since| entry.timestamp.as_str() >= since).unwrap_or(true))
+        .filter(|entry| until.map(|until| entry.timestamp.as_str() < until).unwrap_or(true))
+        .collect::<Vec<AuditEntry>>();
+
+    let limit = limit.unwrap_or(DEFAULT_LIMIT);
+    matching.drain(..matching.len().saturating_sub(limit));
+
+    Ok(Json(matching))
+}
+
+/// Every entry as JSON Lines.
+#[get("/audit/export")]
+pub async fn export_audit_log(
+    _admin: Admin<'_>,
+    audit: &State<AuditLog>,
+) -> Result<(ContentType, String), status::Custom<Json<BasicError>>> {
+    let lines = entries(audit).await?.iter()
+        .map(|entry| format!("{}\n", serde_json::to_string(entry).unwrap()))
+        .collect::<String>();
+
+    Ok((ContentType::new("application", "x-ndjson"), lines))
+}
+
+/// Checks every entry follows the chain, up to the recorded head.
+#[get("/audit/verify")]
+pub async fn verify_audit_log(
+    _admin: Admin<'_>,
+    audit: &State<AuditLog>,
+) -> Result<Json<AuditVerification>, status::Custom<Json<BasicError>>> {
+    if !audit.is_enabled() {
+        return Err(BasicError::from(Box::new(AuditLogDisabled)));
+    }
+
+    audit.verify()
+        .await
+        .map(Json)
+        .map_err(|err| BasicError::from(Box::new(AuditLogFailed(err))))
+}
@@ -0,0 +1,103 @@
+use std::fs::File;
+use std::path::PathBuf;
+
+use log::info;
+use rocket::{get, Responder};
+use rocket::response::{Redirect, status};
+use rocket::serde::json::Json;
+
+use crate::{ARProxyConfiguration, ManagedResourceAccess};
+use crate::err::{BasicError, IOError, RepositoryNotFound, ResourceNotFound};
+use crate::maven::metadata::fetch_metadata;
+use crate::maven::Segment;
+
+#[derive(Responder)]
+pub enum LatestResource {
+    Redirect(Box<Redirect>),
+    File(File),
+}
+
+/// Resolves the latest release (or snapshot, if `snapshot` is set) of an artifact from its
+/// metadata, and redirects to its file. When `redirect` is false the file is served directly.
+#[allow(clippy::too_many_arguments)]
+#[get("/latest/<repository>/<group>/<artifact>?<classifier>&<ext>&<snapshot>&<redirect>")]
+pub async fn latest(
+    repository: &str,
+    group: &str,
+    artifact: &str,
+    classifier: Option<&str>,
+    ext: Option<&str>,
+    snapshot: Option<bool>,
+    redirect: Option<bool>,
+    resource_access: &ManagedResourceAccess,
+    configuration: &ARProxyConfiguration,
+) -> Result<LatestResource, status::Custom<Json<BasicError>>> {
+    let public_name = repository;
+    let repository = &configuration.repositories.get(repository).ok_or(
+        BasicError::from(Box::new(RepositoryNotFound(repository.to_string())))
+    )?.id;
+
+    let group = Segment::parse("group", group).map_err(BasicError::from)?.as_str();
+    let artifact = Segment::parse("artifact", artifact).map_err(BasicError::from)?.as_str();
+    let classifier = classifier.map(|classifier| Segment::parse("classifier", classifier))
+        .transpose()
+        .map_err(BasicError::from)?
+        .map(|classifier| classifier.as_str());
+    let extension = Segment::parse("ext", ext.unwrap_or("jar")).map_err(BasicError::from)?.as_str();
+
+    let artifact_path = group.split('.')
+        .chain([artifact])
+        .collect::<PathBuf>();
+
+    let metadata = fetch_metadata(
+        resource_access.as_ref(),
+        PathBuf::from(repository).join(&artifact_path).join("maven-metadata.xml"),
+    ).await.map_err(BasicError::from)?;
+
+    let version = if snapshot.unwrap_or(false) {
+        metadata.latest_version()
+    } else {
+        metadata.latest_release()
+    }.ok_or_else(|| BasicError::from(Box::new(ResourceNotFound(format!("a version of {}:{}", group, artifact)))))?;
+
+    // The metadata is uploaded by clients, so its versions are checked like any other input.
+    let version = Segment::parse("version", version).map_err(BasicError::from)?.as_str();
+    let version_path = artifact_path.join(version);
+
+    let file_version = if version.ends_with("-SNAPSHOT") {
+        fetch_metadata(
+            resource_access.as_ref(),
+            PathBuf::from(repository).join(&version_path).join("maven-metadata.xml"),
+        ).await
+            .map_err(BasicError::from)?
+            .snapshot_file_version(version, classifier, extension)
+            .unwrap_or(version.to_string())
+    } else {
+        version.to_string()
+    };
+
+    let file_name = match classifier {
+        Some(classifier) => format!("{}-{}-{}.{}", artifact, file_version, classifier, extension),
+        None => format!("{}-{}.{}", artifact, file_version, extension),
+    };
+
+    let path = version_path.join(Segment::parse("file", &file_name).map_err(BasicError::from)?.as_str());
+
+    info!("Resolved latest version of {}:{} to: '{}'", group, artifact, path.to_str().unwrap());
+
+    if redirect.unwrap_or(true) {
+        return Ok(LatestResource::Redirect(Box::new(Redirect::found(format!(
+            "/{}/{}",
+            public_name,
+            path.to_str().unwrap()
+        )))));
+    }
+
+    let file = resource_access.get_resource(
+        PathBuf::from(repository).join(path)
+    ).await.map_err(BasicError::from)?;
+
+    Ok(LatestResource::File(
+        File::open(file).map_err(|e| BasicError::from(Box::new(IOError(e))))?
+    ))
+}
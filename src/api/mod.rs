@@ -0,0 +1,6 @@
+pub mod admin;
+pub mod audit;
+pub mod latest;
+pub mod promote;
+pub mod retention;
+pub mod search;
@@ -0,0 +1,74 @@
+use std::net::IpAddr;
+use std::sync::Arc;
+
+use rocket::{post, State};
+use rocket::response::status;
+use rocket::serde::json::Json;
+
+use crate::{ARProxyConfiguration, ManagedResourceAccess};
+use crate::audit::{AuditAction, AuditEvent, AuditLog};
+use crate::auth::Deployer;
+use crate::err::{BasicError, RepositoryNotFound};
+use crate::index::SearchIndex;
+use crate::promote::{promote, PromotionReport, PromotionRequest};
+
+/// Copies every file of a version from one repository to another, see `PromotionRequest`.
+#[post("/promote", data = "<request>")]
+pub async fn promote_version(
+    deployer: Deployer<'_>,
+    request: Json<PromotionRequest>,
+    client_ip: Option<IpAddr>,
+    resource_access: &ManagedResourceAccess,
+    index: &State<Arc<SearchIndex>>,
+    audit: &State<AuditLog>,
+    configuration: &ARProxyConfiguration,
+) -> Result<Json<PromotionReport>, status::Custom<Json<BasicError>>> {
+    let result = promote_to(&request, resource_access, index, configuration).await;
+
+    let path = format!(
+        "{}/{}/{}",
+        request.group_id.replace('.', "/"),
+        request.artifact_id,
+        request.target_version.as_ref().unwrap_or(&request.version)
+    );
+
+    audit.record(AuditEvent {
+        principal: deployer.0.user.clone(),
+        source_ip: client_ip.map(|ip| ip.to_string()),
+        action: AuditAction::Promote,
+        repository: request.to.clone(),
+        path,
+        size: None,
+        sha256: None,
+        signature: None,
+        status: 200,
+        error: None,
+    }.outcome(&result)).await;
+
+    result
+}
+
+async fn promote_to(
+    request: &PromotionRequest,
+    resource_access: &ManagedResourceAccess,
+    index: &State<Arc<SearchIndex>>,
+    configuration: &ARProxyConfiguration,
+) -> Result<Json<PromotionReport>, status::Custom<Json<BasicError>>> {
+    let from = configuration.repositories.get(&request.from).ok_or(
+        BasicError::from(Box::new(RepositoryNotFound(request.from.clone())))
+    )?;
+    let to = configuration.repositories.get(&request.to).ok_or(
+        BasicError::from(Box::new(RepositoryNotFound(request.to.clone())))
+    )?;
+
+    let report = promote(
+        resource_access.as_ref(),
+        from,
+        to,
+        request,
+    ).await.map_err(BasicError::from)?;
+
+    index.record(&request.to, report.coordinates.clone());
+
+    Ok(Json(report))
+}
@@ -0,0 +1,65 @@
+use std::net::IpAddr;
+use std::sync::Arc;
+
+use rocket::{post, State};
+use rocket::response::status;
+use rocket::serde::json::Json;
+
+use crate::{ARProxyConfiguration, ManagedResourceAccess};
+use crate::audit::{Actor, AuditLog};
+use crate::auth::Deleter;
+use crate::err::{BasicError, NoRetentionPolicy, RepositoryNotFound};
+use crate::index::SearchIndex;
+use crate::retention::{apply_retention, RetentionReport};
+
+/// Applies the repository's snapshot retention policy now. Defaults to a dry run which only
+/// reports what would be deleted, pass `dry_run=false` to delete. Each deletion is recorded in
+/// the audit log as the deleter's.
+#[allow(clippy::too_many_arguments)]
+#[post("/retention/<repository>?<dry_run>")]
+pub async fn run_retention(
+    deleter: Deleter<'_>,
+    repository: &str,
+    dry_run: Option<bool>,
+    client_ip: Option<IpAddr>,
+    resource_access: &ManagedResourceAccess,
+    index: &State<Arc<SearchIndex>>,
+    audit: &State<AuditLog>,
+    configuration: &ARProxyConfiguration,
+) -> Result<Json<RetentionReport>, status::Custom<Json<BasicError>>> {
+    let actor = Actor {
+        principal: deleter.0.user.clone(),
+        source_ip: client_ip.map(|ip| ip.to_string()),
+    };
+
+    retain(repository, dry_run.unwrap_or(true), resource_access, index, (audit, &actor), configuration).await
+}
+
+async fn retain(
+    repository: &str,
+    dry_run: bool,
+    resource_access: &ManagedResourceAccess,
+    index: &State<Arc<SearchIndex>>,
+    audit: (&AuditLog, &Actor),
+    configuration: &ARProxyConfiguration,
+) -> Result<Json<RetentionReport>, status::Custom<Json<BasicError>>> {
+    let public_name = repository;
+    let repository = configuration.repositories.get(repository).ok_or(
+        BasicError::from(Box::new(RepositoryNotFound(repository.to_string())))
+    )?;
+
+    let policy = repository.retention.as_ref().ok_or(
+        BasicError::from(Box::new(NoRetentionPolicy(public_name.to_string())))
+    )?;
+
+    apply_retention(
+        resource_access.as_ref(),
+        index,
+        audit,
+        (public_name, &repository.id),
+        policy,
+        dry_run,
+    ).await
+        .map(Json)
+        .map_err(BasicError::from)
+}
@@ -0,0 +1,16 @@
+use std::sync::Arc;
+
+use rocket::{get, State};
+use rocket::serde::json::Json;
+
+use crate::index::{IndexedArtifact, SearchIndex};
+
+/// Searches every configured repository for artifact versions matching the coordinate fragments
+/// in the query, eg. 'com.example:lib' or 'lib 1.2'.
+#[get("/search?<q>")]
+pub async fn search(
+    q: &str,
+    index: &State<Arc<SearchIndex>>,
+) -> Json<Vec<IndexedArtifact>> {
+    Json(index.search(q))
+}
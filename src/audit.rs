@@ -0,0 +1,503 @@
+use std::ffi::OsString;
+use std::fs;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use chrono::{SecondsFormat, Utc};
+use log::{error, info};
+use metrics::gauge;
+use rocket::response::status;
+use rocket::serde::{Deserialize, Serialize};
+use rocket::serde::json::Json;
+use sha2::{Digest, Sha256};
+use tokio::task;
+
+use crate::err::{BasicError, SerializableError};
+
+/// The hash the first entry is chained to.
+const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditAction {
+    Put,
+    Delete,
+    Promote,
+}
+
+impl TryFrom<&str> for AuditAction {
+    type Error = ();
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "put" => Ok(AuditAction::Put),
+            "delete" => Ok(AuditAction::Delete),
+            "promote" => Ok(AuditAction::Promote),
+            _ => Err(())
+        }
+    }
+}
+
+/// A write operation, as it is recorded.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditEvent {
+    pub principal: String,
+    pub source_ip: Option<String>,
+    pub action: AuditAction,
+    /// The public name of the repository written to.
+    pub repository: String,
+    pub path: String,
+    pub size: Option<u64>,
+    pub sha256: Option<String>,
+    /// The outcome of checking an upload's signature, for repositories which require them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+    /// The status code ARP responded with.
+    pub status: u16,
+    pub error: Option<String>,
+}
+
+/// Who an operation writing several paths (eg. a retention run) is recorded as.
+#[derive(Debug, Clone)]
+pub struct Actor {
+    pub principal: String,
+    pub source_ip: Option<String>,
+}
+
+/// The status a write operation succeeded with, as it is recorded.
+pub trait AuditStatus {
+    fn status(&self) -> u16 {
+        200
+    }
+}
+
+impl AuditStatus for () {}
+
+impl<T> AuditStatus for Json<T> {}
+
+impl AuditEvent {
+    /// The deletion of a path on behalf of the actor, with the error it failed with (if any).
+    pub fn deletion(actor: &Actor, repository: &str, path: &Path, result: &Result<(), Box<dyn SerializableError>>) -> AuditEvent {
+        AuditEvent {
+            principal: actor.principal.clone(),
+            source_ip: actor.source_ip.clone(),
+            action: AuditAction::Delete,
+            repository: repository.to_string(),
+            path: path.to_str().unwrap().to_string(),
+            size: None,
+            sha256: None,
+            signature: None,
+            status: result.as_ref().map(|_| 200).unwrap_or_else(|err| err.status()),
+            error: result.as_ref().err().map(|err| err.message()),
+        }
+    }
+
+    /// Takes the status and error from the response of the operation.
+    pub fn outcome<T: AuditStatus>(self, result: &Result<T, status::Custom<Json<BasicError>>>) -> AuditEvent {
+        match result {
+            Ok(response) => AuditEvent {
+                status: response.status(),
+                error: None,
+                ..self
+            },
+            Err(err) => AuditEvent {
+                status: err.0.code,
+                error: Some(err.1.message().to_string()),
+                ..self
+            }
+        }
+    }
+}
+
+/// The size and SHA-256 of a file.
+pub fn file_digest(path: &Path) -> io::Result<(u64, String)> {
+    let mut hasher = Sha256::new();
+    let size = io::copy(&mut File::open(path)?, &mut hasher)?;
+
+    Ok((size, hex::encode(hasher.finalize())))
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditEntry {
+    pub sequence: u64,
+    pub timestamp: String,
+    #[serde(flatten)]
+    pub event: AuditEvent,
+    pub previous_hash: String,
+    /// The SHA-256 of the entry (serialized without this field), which the next entry holds as
+    /// its `previous_hash`.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub hash: String,
+}
+
+impl AuditEntry {
+    fn digest(&self) -> String {
+        let unhashed = AuditEntry {
+            hash: String::new(),
+            ..self.clone()
+        };
+
+        hex::encode(Sha256::digest(serde_json::to_vec(&unhashed).unwrap()))
+    }
+}
+
+/// The newest entry of the log, kept in a file of its own next to it. The chain can't show its
+/// newest entries being cut off, but they no longer match the head.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditHead {
+    pub sequence: u64,
+    pub hash: String,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditVerification {
+    pub valid: bool,
+    pub entries: u64,
+    /// The sequence number of the first entry which doesn't match the chain.
+    pub first_invalid: Option<u64>,
+    pub reason: Option<String>,
+    /// The head the log was checked against, compare it with the one ARP logged at startup.
+    pub head: Option<AuditHead>,
+}
+
+struct Chain {
+    file: File,
+    sequence: u64,
+    last_hash: String,
+}
+
+/// An append-only log of write operations, kept as JSON Lines. Every entry holds the hash of the
+/// one before it, so an entry being changed, removed or inserted breaks the chain, and the head
+/// is recorded separately so the newest entries being removed is detected too. The file is only
+/// written and read on blocking threads, so handlers don't stall the runtime on it. Clones share
+/// the chain.
+#[derive(Clone)]
+pub struct AuditLog {
+    path: Option<PathBuf>,
+    chain: Option<Arc<Mutex<Chain>>>,
+}
+
+/// The file the head of the log at the given path is kept in, eg. 'audit.jsonl.head'.
+fn head_path(path: &Path) -> PathBuf {
+    let mut head = OsString::from(path.as_os_str());
+    head.push(".head");
+    PathBuf::from(head)
+}
+
+fn read_head(path: &Path) -> Result<Option<AuditHead>, String> {
+    match fs::read(head_path(path)) {
+        Ok(head) => serde_json::from_slice(&head)
+            .map(Some)
+            .map_err(|err| format!("Malformed head, {}", err)),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err.to_string())
+    }
+}
+
+/// Replaces the head, through a rename so it's never half written.
+fn write_head(path: &Path, head: &AuditHead) -> io::Result<()> {
+    let head_path = head_path(path);
+    let mut temporary = OsString::from(head_path.as_os_str());
+    temporary.push(".tmp");
+
+    fs::write(&temporary, serde_json::to_vec(head).unwrap())?;
+    fs::rename(&temporary, &head_path)
+}
+
+fn read_entries(path: &Path) -> Result<Vec<AuditEntry>, String> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err.to_string())
+    };
+
+    contents.lines()
+        .enumerate()
+        .map(|(line, entry)| serde_json::from_str(entry)
+            .map_err(|err| format!("Malformed entry on line {}, {}", line + 1, err)))
+        .collect()
+}
+
+/// Appends an entry for the event to the log, chained to the one before it.
+fn append(path: &Path, chain: &Mutex<Chain>, event: AuditEvent) {
+    let mut chain = chain.lock().unwrap();
+
+    let mut entry = AuditEntry {
+        sequence: chain.sequence,
+        timestamp: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+        event,
+        previous_hash: chain.last_hash.clone(),
+        hash: String::new(),
+    };
+    entry.hash = entry.digest();
+
+    let line = format!("{}\n", serde_json::to_string(&entry).unwrap());
+
+    match chain.file.write_all(line.as_bytes()).and_then(|_| chain.file.sync_data()) {
+        Ok(_) => {
+            let head = AuditHead {
+                sequence: entry.sequence,
+                hash: entry.hash.clone(),
+            };
+
+            if let Err(err) = write_head(path, &head) {
+                error!("Failed to write audit log head: {:?}, {}", head, err);
+            }
+            gauge!("arp_audit_log_sequence").set(head.sequence as f64);
+
+            chain.sequence += 1;
+            chain.last_hash = entry.hash;
+        }
+        Err(err) => error!("Failed to write audit entry: {:?}, {}", entry, err)
+    }
+}
+
+impl AuditLog {
+    pub fn disabled() -> AuditLog {
+        AuditLog {
+            path: None,
+            chain: None,
+        }
+    }
+
+    /// Opens the log at the given path, continuing its chain. When entries recorded by the head
+    /// are missing, the chain continues from the head, so the log stays invalid.
+    pub fn open(path: &Path) -> Result<AuditLog, String> {
+        let last = read_entries(path)?.pop().map(|entry| AuditHead {
+            sequence: entry.sequence,
+            hash: entry.hash,
+        });
+        let recorded = read_head(path)?;
+
+        let head = match (last, recorded) {
+            (Some(last), Some(recorded)) if last.sequence < recorded.sequence => {
+                error!(
+                    "Audit log: '{}' ends at sequence: {}, but its head is at sequence: {}, entries were removed",
+                    path.to_str().unwrap(), last.sequence, recorded.sequence
+                );
+                Some(recorded)
+            }
+            (None, Some(recorded)) => {
+                error!("Audit log: '{}' is empty, but its head is at sequence: {}, entries were removed", path.to_str().unwrap(), recorded.sequence);
+                Some(recorded)
+            }
+            (last, _) => last
+        };
+
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|err| err.to_string())?;
+
+        match &head {
+            Some(head) => {
+                info!("Audit log: '{}' head at sequence: {}, hash: {}", path.to_str().unwrap(), head.sequence, head.hash);
+                write_head(path, head).map_err(|err| err.to_string())?;
+                gauge!("arp_audit_log_sequence").set(head.sequence as f64);
+            }
+            None => info!("Audit log: '{}' is empty", path.to_str().unwrap())
+        }
+
+        Ok(AuditLog {
+            path: Some(path.to_path_buf()),
+            chain: Some(Arc::new(Mutex::new(Chain {
+                file,
+                sequence: head.as_ref().map(|head| head.sequence + 1).unwrap_or_default(),
+                last_hash: head.map(|head| head.hash).unwrap_or(GENESIS_HASH.to_string()),
+            }))),
+        })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.chain.is_some()
+    }
+
+    /// The log's path and chain, to be used on a blocking thread, when it is enabled.
+    fn handles(&self) -> Option<(PathBuf, Arc<Mutex<Chain>>)> {
+        match (&self.path, &self.chain) {
+            (Some(path), Some(chain)) => Some((path.clone(), Arc::clone(chain))),
+            _ => None
+        }
+    }
+
+    pub async fn record(&self, event: AuditEvent) {
+        let (path, chain) = match self.handles() {
+            Some(handles) => handles,
+            None => return
+        };
+
+        if let Err(err) = task::spawn_blocking(move || append(&path, &chain, event)).await {
+            error!("Failed to write audit entry, {}", err);
+        }
+    }
+
+    /// Every entry of the log, oldest first.
+    pub async fn entries(&self) -> Result<Vec<AuditEntry>, String> {
+        let (path, chain) = match self.handles() {
+            Some(handles) => handles,
+            None => return Ok(Vec::new())
+        };
+
+        task::spawn_blocking(move || {
+            // Held so a half written entry is never read.
+            let _chain = chain.lock().unwrap();
+            read_entries(&path)
+        }).await.map_err(|err| err.to_string())?
+    }
+
+    /// Checks every entry follows the chain, and that the entry recorded as the head is there.
+    pub async fn verify(&self) -> Result<AuditVerification, String> {
+        let (entries, head) = match self.handles() {
+            Some((path, chain)) => task::spawn_blocking(move || {
+                let _chain = chain.lock().unwrap();
+                Ok::<_, String>((read_entries(&path)?, read_head(&path)?))
+            }).await.map_err(|err| err.to_string())??,
+            None => (Vec::new(), None)
+        };
+        let mut previous_hash = GENESIS_HASH.to_string();
+
+        for (position, entry) in entries.iter().enumerate() {
+            let reason = if entry.sequence != position as u64 {
+                Some(format!("Expected sequence: {}, found: {}", position, entry.sequence))
+            } else if entry.previous_hash != previous_hash {
+                Some("Does not follow the previous entry".to_string())
+            } else if entry.hash != entry.digest() {
+                Some("Does not match its hash".to_string())
+            } else {
+                None
+            };
+
+            if reason.is_some() {
+                return Ok(AuditVerification {
+                    valid: false,
+                    entries: entries.len() as u64,
+                    first_invalid: Some(position as u64),
+                    reason,
+                    head,
+                });
+            }
+
+            previous_hash = entry.hash.clone();
+        }
+
+        // An entry written after the head (by a crash before the head was replaced) is fine.
+        if let Some(head) = &head {
+            let reason = match entries.get(head.sequence as usize) {
+                None => Some(format!("Ends at: {} entries, but the head is at sequence: {}", entries.len(), head.sequence)),
+                Some(entry) if entry.hash != head.hash => Some("Does not match the head".to_string()),
+                Some(_) => None
+            };
+
+            if reason.is_some() {
+                return Ok(AuditVerification {
+                    valid: false,
+                    entries: entries.len() as u64,
+                    first_invalid: Some(head.sequence.min(entries.len() as u64)),
+                    reason,
+                    head: Some(head.clone()),
+                });
+            }
+        }
+
+        Ok(AuditVerification {
+            valid: true,
+            entries: entries.len() as u64,
+            first_invalid: None,
+            reason: None,
+            head,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use tempfile::tempdir;
+
+    use crate::audit::{AuditAction, AuditEvent, AuditLog};
+
+    fn event(path: &str) -> AuditEvent {
+        AuditEvent {
+            principal: "ci".to_string(),
+            source_ip: Some("127.0.0.1".to_string()),
+            action: AuditAction::Put,
+            repository: "releases".to_string(),
+            path: path.to_string(),
+            size: Some(3),
+            sha256: None,
+            signature: None,
+            status: 200,
+            error: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_hash_chain() {
+        let directory = tempdir().unwrap();
+        let path = directory.path().join("audit.jsonl");
+
+        let log = AuditLog::open(&path).unwrap();
+        log.record(event("com/example/lib/1.0/lib-1.0.jar")).await;
+        log.record(event("com/example/lib/1.0/lib-1.0.pom")).await;
+
+        // Reopening continues the chain.
+        let log = AuditLog::open(&path).unwrap();
+        log.record(event("com/example/lib/1.0/lib-1.0.module")).await;
+
+        let verification = log.verify().await.unwrap();
+        assert!(verification.valid);
+        assert_eq!(verification.entries, 3);
+        assert_eq!(log.entries().await.unwrap()[2].sequence, 2);
+
+        let tampered = fs::read_to_string(&path).unwrap().replacen("lib-1.0.pom", "lib-2.0.pom", 1);
+        fs::write(&path, tampered).unwrap();
+
+        let verification = log.verify().await.unwrap();
+        assert!(!verification.valid);
+        assert_eq!(verification.first_invalid, Some(1));
+
+        let removed = fs::read_to_string(&path).unwrap().lines().skip(1).collect::<Vec<&str>>().join("\n");
+        fs::write(&path, removed).unwrap();
+
+        assert_eq!(log.verify().await.unwrap().first_invalid, Some(0));
+    }
+
+    #[tokio::test]
+    async fn test_truncation() {
+        let directory = tempdir().unwrap();
+        let path = directory.path().join("audit.jsonl");
+
+        let log = AuditLog::open(&path).unwrap();
+        for file in ["lib-1.0.jar", "lib-1.0.pom", "lib-1.0.module"] {
+            log.record(event(file)).await;
+        }
+
+        let verification = log.verify().await.unwrap();
+        assert!(verification.valid);
+        assert_eq!(verification.head.unwrap().sequence, 2);
+
+        // Cutting off the newest entry leaves a valid chain, but not the head.
+        let truncated = fs::read_to_string(&path).unwrap().lines().take(2).map(|line| format!("{}\n", line)).collect::<String>();
+        fs::write(&path, truncated).unwrap();
+
+        let verification = log.verify().await.unwrap();
+        assert!(!verification.valid);
+        assert_eq!(verification.first_invalid, Some(2));
+
+        // Reopening continues from the head, so the gap stays.
+        let log = AuditLog::open(&path).unwrap();
+        log.record(event("lib-1.0-sources.jar")).await;
+
+        assert_eq!(log.entries().await.unwrap()[2].sequence, 3);
+        assert!(!log.verify().await.unwrap().valid);
+    }
+}
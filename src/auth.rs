@@ -4,13 +4,44 @@ use rocket::{async_trait, Request};
 use rocket::http::Status;
 use rocket::outcome::Outcome::Forward;
 use rocket::request::{FromRequest, Outcome};
+use std::sync::Arc;
+
+use rocket::serde::{Deserialize, Serialize};
 use crate::ARProxyConfiguration;
+use crate::reload::LiveConfiguration;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Permission {
+    Deploy,
+    Delete,
+    /// Access to the administrative API, eg. the audit log.
+    Admin,
+}
 
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
 pub struct ApiCredentials {
     pub user: String,
     pub key: String,
+    pub permissions: Vec<Permission>,
+}
+
+impl ApiCredentials {
+    pub fn has_permission(&self, permission: Permission) -> bool {
+        self.permissions.contains(&permission)
+    }
 }
 
+/// Credentials which are allowed to put resources into repositories.
+pub struct Deployer<'r>(pub &'r ApiCredentials);
+
+/// Credentials which are allowed to delete resources from repositories.
+pub struct Deleter<'r>(pub &'r ApiCredentials);
+
+/// Credentials which are allowed to use the administrative API.
+pub struct Admin<'r>(pub &'r ApiCredentials);
+
 
 #[async_trait]
 impl<'r> FromRequest<'r> for &'r ApiCredentials {
@@ -48,12 +79,54 @@ impl<'r> FromRequest<'r> for &'r ApiCredentials {
 
         key = &key[1..];
 
-        let config = request.rocket().state::<ARProxyConfiguration>().unwrap();
+        let config = match request.guard::<&ARProxyConfiguration>().await {
+            Outcome::Success(config) => config,
+            _ => return Forward(Status::InternalServerError)
+        };
 
-        if config.creds.user.as_bytes() == user && config.creds.key.as_bytes() == key {
-            Outcome::Success(&config.creds)
-        } else {
-            Forward(Status::Unauthorized)
+        let live = request.rocket().state::<Arc<LiveConfiguration>>();
+
+        match config.creds.iter()
+            .filter(|creds| live.map(|live| !live.is_revoked(creds)).unwrap_or(true))
+            .find(|creds| creds.user.as_bytes() == user && creds.key.as_bytes() == key) {
+            Some(creds) => Outcome::Success(creds),
+            None => Forward(Status::Unauthorized)
         }
     }
-}
\ No newline at end of file
+}
+
+async fn authorize<'r>(request: &'r Request<'_>, permission: Permission) -> Outcome<&'r ApiCredentials, ()> {
+    match request.guard::<&ApiCredentials>().await {
+        Outcome::Success(creds) if creds.has_permission(permission) => Outcome::Success(creds),
+        Outcome::Success(_) => Forward(Status::Forbidden),
+        Outcome::Error(err) => Outcome::Error(err),
+        Forward(status) => Forward(status),
+    }
+}
+
+#[async_trait]
+impl<'r> FromRequest<'r> for Deployer<'r> {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        authorize(request, Permission::Deploy).await.map(Deployer)
+    }
+}
+
+#[async_trait]
+impl<'r> FromRequest<'r> for Deleter<'r> {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        authorize(request, Permission::Delete).await.map(Deleter)
+    }
+}
+
+#[async_trait]
+impl<'r> FromRequest<'r> for Admin<'r> {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        authorize(request, Permission::Admin).await.map(Admin)
+    }
+}
@@ -0,0 +1,195 @@
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use log::{info, warn};
+use metrics::gauge;
+use rocket::async_trait;
+use tempfile::TempPath;
+
+use crate::err::{CircuitOpen, SerializableError};
+use crate::maven::Coordinates;
+use crate::resource_access::{Check, ResourceAccess, ResourceEntry};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum State {
+    Closed { failures: u32 },
+    /// Requests fail fast until the cooldown has passed.
+    Open { until: Instant },
+    /// One trial request was let through, and decides whether the circuit closes again. Another
+    /// trial is let through if it hasn't completed by `until`.
+    HalfOpen { until: Instant },
+}
+
+/// Stops sending requests to a backend after `threshold` consecutive failures, so requests fail
+/// fast instead of piling up while it is down. After `cooldown` a single trial request is let
+/// through, and closes the circuit again when it succeeds.
+pub struct CircuitBreaker {
+    threshold: u32,
+    cooldown: Duration,
+    state: Mutex<State>,
+}
+
+impl CircuitBreaker {
+    /// A `threshold` of 0 never opens the circuit.
+    pub fn new(threshold: u32, cooldown: Duration) -> CircuitBreaker {
+        CircuitBreaker {
+            threshold,
+            cooldown,
+            state: Mutex::new(State::Closed { failures: 0 }),
+        }
+    }
+
+    /// Whether a request may be sent now, or else how long until the next trial.
+    fn acquire(&self, now: Instant) -> Result<(), Duration> {
+        let mut state = self.state.lock().unwrap();
+
+        match *state {
+            State::Closed { .. } => Ok(()),
+            State::Open { until } | State::HalfOpen { until } if now < until => Err(until - now),
+            State::Open { .. } | State::HalfOpen { .. } => {
+                *state = State::HalfOpen { until: now + self.cooldown };
+                Ok(())
+            }
+        }
+    }
+
+    /// Records the outcome of a request, returning whether the circuit opened or closed.
+    fn record(&self, success: bool, now: Instant) -> Option<bool> {
+        let mut state = self.state.lock().unwrap();
+
+        let (next, changed) = match (*state, success) {
+            (State::Closed { .. }, true) => (State::Closed { failures: 0 }, None),
+            (State::Closed { failures }, false) if self.threshold == 0 || failures + 1 < self.threshold => {
+                (State::Closed { failures: failures + 1 }, None)
+            }
+            (State::Closed { .. }, false) => (State::Open { until: now + self.cooldown }, Some(true)),
+            (State::HalfOpen { .. }, true) => (State::Closed { failures: 0 }, Some(false)),
+            (State::HalfOpen { .. }, false) => (State::Open { until: now + self.cooldown }, None),
+            // A request sent before the circuit opened.
+            (State::Open { .. }, _) => (*state, None),
+        };
+
+        *state = next;
+        changed
+    }
+}
+
+/// Whether the error means the backend is unavailable, rather than the request being invalid or
+/// the resource missing.
+fn is_failure(err: &dyn SerializableError) -> bool {
+    err.status() >= 500
+}
+
+/// Guards every operation on a backend with a circuit breaker. Health checks and credential
+/// refreshes always reach the backend.
+pub struct CircuitBreakingResourceAccess {
+    /// The name of the backend in the configuration.
+    pub backend: String,
+    pub breaker: CircuitBreaker,
+    pub inner: Arc<dyn ResourceAccess + Send + Sync>,
+}
+
+impl CircuitBreakingResourceAccess {
+    fn acquire(&self) -> Result<(), Box<dyn SerializableError>> {
+        self.breaker.acquire(Instant::now())
+            .map_err(|remaining| Box::new(CircuitOpen(self.backend.clone(), remaining.as_secs_f64().ceil() as u64)) as Box<dyn SerializableError>)
+    }
+
+    fn record<T>(&self, result: Result<T, Box<dyn SerializableError>>) -> Result<T, Box<dyn SerializableError>> {
+        let success = result.as_ref().map(|_| true).unwrap_or_else(|err| !is_failure(err.as_ref()));
+
+        match self.breaker.record(success, Instant::now()) {
+            Some(true) => {
+                warn!("Opened the circuit of backend: '{}' after repeated failures", self.backend);
+                gauge!("arp_circuit_breaker_open", "backend" => self.backend.clone()).set(1.0);
+            }
+            Some(false) => {
+                info!("Closed the circuit of backend: '{}'", self.backend);
+                gauge!("arp_circuit_breaker_open", "backend" => self.backend.clone()).set(0.0);
+            }
+            None => {}
+        }
+
+        result
+    }
+}
+
+#[async_trait]
+impl ResourceAccess for CircuitBreakingResourceAccess {
+    async fn get_resource(&self, path: PathBuf) -> Result<TempPath, Box<dyn SerializableError>> {
+        self.acquire()?;
+        self.record(self.inner.get_resource(path).await)
+    }
+
+    async fn exists(&self, path: PathBuf) -> Result<bool, Box<dyn SerializableError>> {
+        self.acquire()?;
+        self.record(self.inner.exists(path).await)
+    }
+
+    async fn put_resource(&self, path: PathBuf, file: TempPath) -> Result<(), Box<dyn SerializableError>> {
+        self.acquire()?;
+        self.record(self.inner.put_resource(path, file).await)
+    }
+
+    async fn delete_resource(&self, path: PathBuf) -> Result<(), Box<dyn SerializableError>> {
+        self.acquire()?;
+        self.record(self.inner.delete_resource(path).await)
+    }
+
+    async fn list(&self, path: PathBuf) -> Result<Vec<ResourceEntry>, Box<dyn SerializableError>> {
+        self.acquire()?;
+        self.record(self.inner.list(path).await)
+    }
+
+    async fn list_versions(&self, repository: &str) -> Result<BTreeSet<Coordinates>, Box<dyn SerializableError>> {
+        self.acquire()?;
+        self.record(self.inner.list_versions(repository).await)
+    }
+
+    async fn check(&self) -> Vec<Check> {
+        self.inner.check().await
+    }
+
+    async fn refresh_credentials(&self) -> Option<Result<(), Box<dyn SerializableError>>> {
+        self.inner.refresh_credentials().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use crate::breaker::CircuitBreaker;
+
+    #[test]
+    fn test_circuit_breaker() {
+        let breaker = CircuitBreaker::new(3, Duration::from_secs(30));
+        let start = Instant::now();
+
+        assert_eq!(breaker.record(false, start), None);
+        assert_eq!(breaker.record(false, start), None);
+        assert_eq!(breaker.record(true, start), None);
+
+        assert_eq!(breaker.record(false, start), None);
+        assert_eq!(breaker.record(false, start), None);
+        assert_eq!(breaker.record(false, start), Some(true));
+
+        assert_eq!(breaker.acquire(start + Duration::from_secs(10)), Err(Duration::from_secs(20)));
+
+        // Only one trial is let through once the cooldown has passed.
+        let trial = start + Duration::from_secs(30);
+        assert_eq!(breaker.acquire(trial), Ok(()));
+        assert_eq!(breaker.acquire(trial), Err(Duration::from_secs(30)));
+
+        assert_eq!(breaker.record(false, trial), None);
+        assert!(breaker.acquire(trial).is_err());
+
+        let trial = trial + Duration::from_secs(30);
+        assert_eq!(breaker.acquire(trial), Ok(()));
+        assert_eq!(breaker.record(true, trial), Some(false));
+        assert_eq!(breaker.acquire(trial), Ok(()));
+        assert_eq!(breaker.acquire(trial), Ok(()));
+    }
+}
@@ -0,0 +1,400 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::io;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use log::{debug, warn};
+use metrics::counter;
+use rocket::async_trait;
+use rocket::serde::Serialize;
+use tempfile::{NamedTempFile, TempPath};
+
+use crate::err::SerializableError;
+use crate::maven::{Coordinates, is_maven_metadata, is_snapshot};
+use crate::resource_access::{ResourceAccess, ResourceEntry};
+use crate::trace::in_sync_span;
+
+/// A disk cache of resources fetched from a backend. Release artifacts never change and so are
+/// cached indefinitely, metadata and snapshots are only served while younger than `mutable_ttl`.
+pub struct ResourceCache {
+    pub directory: PathBuf,
+    pub mutable_ttl: Duration,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct CacheStats {
+    pub entries: u64,
+    pub bytes: u64,
+    /// Lookups since ARP started.
+    pub hits: u64,
+    pub misses: u64,
+}
+
+/// Every file below the directory, by its path relative to `root`.
+fn files(root: &Path, directory: &Path, found: &mut Vec<(PathBuf, u64)>) -> io::Result<()> {
+    let entries = match fs::read_dir(directory) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err)
+    };
+
+    for entry in entries {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+
+        if metadata.is_dir() {
+            files(root, &entry.path(), found)?;
+        } else if let Ok(path) = entry.path().strip_prefix(root) {
+            found.push((path.to_path_buf(), metadata.len()));
+        }
+    }
+
+    Ok(())
+}
+
+impl ResourceCache {
+    pub fn new(directory: PathBuf, mutable_ttl: Duration) -> ResourceCache {
+        ResourceCache {
+            directory,
+            mutable_ttl,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    fn entry_path(&self, path: &Path) -> PathBuf {
+        self.directory.join(path)
+    }
+
+    fn is_fresh(&self, path: &Path, entry: &Path) -> bool {
+        let modified = match fs::metadata(entry).and_then(|metadata| metadata.modified()) {
+            Ok(modified) => modified,
+            Err(_) => return false
+        };
+
+        if !is_maven_metadata(path) && !is_snapshot(path) {
+            return true;
+        }
+
+        modified.elapsed()
+            .map(|age| age < self.mutable_ttl)
+            .unwrap_or(false)
+    }
+
+    pub fn contains(&self, path: &Path) -> bool {
+        let entry = self.entry_path(path);
+
+        entry.is_file() && self.is_fresh(path, &entry)
+    }
+
+    /// Copies the cached resource into a temporary file, if it is present and fresh.
+    pub fn get(&self, path: &Path) -> Option<TempPath> {
+        if !self.contains(path) {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+            return None;
+        }
+
+        self.hits.fetch_add(1, Ordering::Relaxed);
+
+        self.copy(path)
+    }
+
+    /// Copies the cached resource into a temporary file, even if it has expired. Used to keep
+    /// serving resources while their backend is unavailable.
+    pub fn get_stale(&self, path: &Path) -> Option<TempPath> {
+        if !self.entry_path(path).is_file() {
+            return None;
+        }
+
+        counter!("arp_cache_requests_total", "result" => "stale").increment(1);
+
+        self.copy(path)
+    }
+
+    fn copy(&self, path: &Path) -> Option<TempPath> {
+        let copy = NamedTempFile::new()
+            .and_then(|file| {
+                fs::copy(self.entry_path(path), file.path())?;
+                Ok(file.into_temp_path())
+            });
+
+        match copy {
+            Ok(file) => Some(file),
+            Err(err) => {
+                warn!("Failed to read cached resource: '{}', {}", path.to_str().unwrap(), err);
+                None
+            }
+        }
+    }
+
+    pub fn store(&self, path: &Path, file: &Path) -> io::Result<()> {
+        let entry = self.entry_path(path);
+        let parent = entry.parent().ok_or(ErrorKind::InvalidInput)?;
+
+        fs::create_dir_all(parent)?;
+
+        let temp = NamedTempFile::new_in(parent)?;
+        fs::copy(file, temp.path())?;
+        temp.persist(entry).map_err(|err| err.error)?;
+
+        Ok(())
+    }
+
+    pub fn stats(&self) -> io::Result<CacheStats> {
+        let mut found = Vec::new();
+        files(&self.directory, &self.directory, &mut found)?;
+
+        Ok(CacheStats {
+            entries: found.len() as u64,
+            bytes: found.iter().map(|(_, size)| size).sum(),
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        })
+    }
+
+    /// Removes every cached resource whose path starts with the given prefix, compared by whole
+    /// segments (so `releases/com/example/lib` doesn't match `.../library`), returning the paths
+    /// removed.
+    pub fn purge(&self, prefix: &str) -> io::Result<Vec<String>> {
+        let mut found = Vec::new();
+        files(&self.directory, &self.directory, &mut found)?;
+
+        let mut purged = Vec::new();
+
+        for (path, _) in found {
+            let name = path.iter()
+                .map(|segment| segment.to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+
+            if path.starts_with(prefix) {
+                fs::remove_file(self.entry_path(&path))?;
+                purged.push(name);
+            }
+        }
+
+        purged.sort();
+
+        Ok(purged)
+    }
+
+    /// Removes the resource (or directory of resources) at the given path, along with any
+    /// metadata cached in its parent directories.
+    pub fn invalidate(&self, path: &Path) -> io::Result<()> {
+        let entry = self.entry_path(path);
+
+        let result = if entry.is_dir() {
+            fs::remove_dir_all(&entry)
+        } else {
+            fs::remove_file(&entry)
+        };
+
+        match result {
+            Err(err) if err.kind() != ErrorKind::NotFound => return Err(err),
+            _ => {}
+        }
+
+        for parent in entry.ancestors().skip(1).take_while(|parent| parent.starts_with(&self.directory)) {
+            let entries = match fs::read_dir(parent) {
+                Ok(entries) => entries,
+                Err(_) => continue
+            };
+
+            for child in entries {
+                let child = child?.path();
+                if child.is_file() && is_maven_metadata(&child) {
+                    debug!("Invalidating cached metadata: '{}'", child.to_str().unwrap());
+                    fs::remove_file(child)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+pub struct CachingResourceAccess {
+    pub inner: Arc<dyn ResourceAccess + Send + Sync>,
+    pub cache: Arc<ResourceCache>,
+}
+
+impl CachingResourceAccess {
+    fn invalidate(&self, path: &Path) {
+        if let Err(err) = self.cache.invalidate(path) {
+            warn!("Failed to invalidate cached copies of: '{}', {}", path.to_str().unwrap(), err);
+        }
+    }
+}
+
+#[async_trait]
+impl ResourceAccess for CachingResourceAccess {
+    async fn get_resource(&self, path: PathBuf) -> Result<TempPath, Box<dyn SerializableError>> {
+        if let Some(file) = in_sync_span("cache read", || self.cache.get(&path)) {
+            debug!("Serving resource: '{}' from cache", path.to_str().unwrap());
+            counter!("arp_cache_requests_total", "result" => "hit").increment(1);
+            return Ok(file);
+        }
+
+        counter!("arp_cache_requests_total", "result" => "miss").increment(1);
+
+        let file = self.inner.get_resource(path.clone()).await?;
+
+        if let Err(err) = in_sync_span("cache write", || self.cache.store(&path, &file)) {
+            warn!("Failed to cache resource: '{}', {}", path.to_str().unwrap(), err);
+        }
+
+        Ok(file)
+    }
+
+    /// Always asks the backend, as a cached copy may outlive a resource deleted elsewhere.
+    async fn exists(&self, path: PathBuf) -> Result<bool, Box<dyn SerializableError>> {
+        self.inner.exists(path).await
+    }
+
+    /// The write already happened, so failing to invalidate the cached copies is only logged.
+    async fn put_resource(&self, path: PathBuf, file: TempPath) -> Result<(), Box<dyn SerializableError>> {
+        self.inner.put_resource(path.clone(), file).await?;
+        self.invalidate(&path);
+
+        Ok(())
+    }
+
+    async fn delete_resource(&self, path: PathBuf) -> Result<(), Box<dyn SerializableError>> {
+        self.inner.delete_resource(path.clone()).await?;
+        self.invalidate(&path);
+
+        Ok(())
+    }
+
+    async fn list(&self, path: PathBuf) -> Result<Vec<ResourceEntry>, Box<dyn SerializableError>> {
+        self.inner.list(path).await
+    }
+
+    async fn list_versions(&self, repository: &str) -> Result<BTreeSet<Coordinates>, Box<dyn SerializableError>> {
+        self.inner.list_versions(repository).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use tempfile::tempdir;
+
+    use crate::cache::{CacheStats, CachingResourceAccess, ResourceCache};
+    use crate::resource_access::memory::MemoryResourceAccess;
+    use crate::resource_access::ResourceAccess;
+
+    #[test]
+    fn test_invalidate_version() {
+        let directory = tempdir().unwrap();
+        let cache = ResourceCache::new(directory.path().to_path_buf(), Duration::from_secs(60));
+
+        let source = directory.path().join("source.txt");
+        fs::write(&source, "content").unwrap();
+
+        let jar = Path::new("releases/com/example/lib/1.0/lib-1.0.jar");
+        let metadata = Path::new("releases/com/example/lib/maven-metadata.xml");
+        let other = Path::new("releases/com/example/lib/1.1/lib-1.1.jar");
+
+        cache.store(jar, &source).unwrap();
+        cache.store(metadata, &source).unwrap();
+        cache.store(other, &source).unwrap();
+
+        assert!(cache.contains(jar));
+        assert!(cache.contains(metadata));
+
+        cache.invalidate(Path::new("releases/com/example/lib/1.0")).unwrap();
+
+        assert!(!cache.contains(jar));
+        assert!(!cache.contains(metadata));
+        assert!(cache.contains(other));
+    }
+
+    #[test]
+    fn test_expired_metadata() {
+        let directory = tempdir().unwrap();
+        let cache = ResourceCache::new(directory.path().to_path_buf(), Duration::ZERO);
+
+        let source = directory.path().join("source.txt");
+        fs::write(&source, "content").unwrap();
+
+        let metadata = Path::new("releases/com/example/lib/maven-metadata.xml");
+        cache.store(metadata, &source).unwrap();
+
+        assert!(!cache.contains(metadata));
+        assert!(cache.get(metadata).is_none());
+        assert!(cache.get_stale(metadata).is_some());
+        assert!(cache.get_stale(Path::new("releases/com/example/lib/1.0/lib-1.0.jar")).is_none());
+    }
+
+    #[test]
+    fn test_purge() {
+        let directory = tempdir().unwrap();
+        let cache = ResourceCache::new(directory.path().join("cache"), Duration::from_secs(60));
+
+        let source = directory.path().join("source.txt");
+        fs::write(&source, "content").unwrap();
+
+        for path in [
+            "releases/com/example/lib/1.0/lib-1.0.jar",
+            "releases/com/example/lib/1.0/lib-1.0.pom",
+            "releases/com/example/lib/1.1/lib-1.1.jar",
+            "releases/com/example/library/1.0/library-1.0.jar",
+        ] {
+            cache.store(Path::new(path), &source).unwrap();
+        }
+
+        assert!(cache.get(Path::new("releases/com/example/lib/1.1/lib-1.1.jar")).is_some());
+        assert!(cache.get(Path::new("releases/com/example/lib/1.2/lib-1.2.jar")).is_none());
+
+        assert_eq!(cache.purge("releases/com/example/lib/1.0/").unwrap(), vec![
+            "releases/com/example/lib/1.0/lib-1.0.jar",
+            "releases/com/example/lib/1.0/lib-1.0.pom",
+        ]);
+
+        assert_eq!(cache.stats().unwrap(), CacheStats {
+            entries: 2,
+            bytes: 14,
+            hits: 1,
+            misses: 1,
+        });
+
+        // Prefixes match whole segments, so 'lib' leaves 'library' alone.
+        assert_eq!(cache.purge("releases/com/example/lib").unwrap(), vec![
+            "releases/com/example/lib/1.1/lib-1.1.jar",
+        ]);
+        assert!(cache.contains(Path::new("releases/com/example/library/1.0/library-1.0.jar")));
+    }
+
+    #[tokio::test]
+    async fn test_exists_asks_backend() {
+        let directory = tempdir().unwrap();
+        let cache = Arc::new(ResourceCache::new(directory.path().join("cache"), Duration::from_secs(60)));
+
+        let backend = Arc::new(MemoryResourceAccess::default());
+        backend.insert("releases/com/example/lib/1.0/lib-1.0.jar", "jar");
+
+        let access = CachingResourceAccess {
+            inner: backend.clone(),
+            cache: Arc::clone(&cache),
+        };
+        let jar = PathBuf::from("releases/com/example/lib/1.0/lib-1.0.jar");
+        access.get_resource(jar.clone()).await.unwrap();
+
+        // Deleted without going through ARP, the cached copy doesn't count.
+        backend.files.lock().unwrap().clear();
+        assert!(cache.contains(&jar));
+        assert!(!access.exists(jar).await.unwrap());
+    }
+}
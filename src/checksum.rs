@@ -0,0 +1,26 @@
+use std::path::Path;
+
+use md5::Md5;
+use sha1::Sha1;
+use sha2::{Digest, Sha256, Sha512};
+
+/// The checksum file extensions published next to Maven artifacts.
+pub const CHECKSUM_EXTENSIONS: [&str; 4] = ["md5", "sha1", "sha256", "sha512"];
+
+/// Whether the path points at a checksum of another file, eg. 'lib-1.0.jar.sha1'.
+pub fn is_checksum(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| CHECKSUM_EXTENSIONS.contains(&extension))
+        .unwrap_or(false)
+}
+
+/// The hex encoded checksums of the data, paired with their file extension.
+pub fn checksums(data: &[u8]) -> [(&'static str, String); 4] {
+    [
+        ("md5", hex::encode(Md5::digest(data))),
+        ("sha1", hex::encode(Sha1::digest(data))),
+        ("sha256", hex::encode(Sha256::digest(data))),
+        ("sha512", hex::encode(Sha512::digest(data))),
+    ]
+}
@@ -0,0 +1,150 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use log::debug;
+use metrics::counter;
+use rocket::async_trait;
+use tempfile::{Builder, NamedTempFile, TempPath};
+use tokio::sync::OnceCell;
+
+use crate::err::{IOError, SerializableError, SharedError};
+use crate::maven::Coordinates;
+use crate::resource_access::{ResourceAccess, ResourceEntry};
+
+type Fetch = Result<Arc<TempPath>, SharedError>;
+
+/// Shares a single fetch of a resource between every request for it made while the fetch is in
+/// flight, eg. when a CI matrix of jobs starts resolving the same dependencies at once.
+pub struct CoalescingResourceAccess {
+    pub inner: Arc<dyn ResourceAccess + Send + Sync>,
+    in_flight: Mutex<HashMap<PathBuf, Arc<OnceCell<Fetch>>>>,
+}
+
+impl CoalescingResourceAccess {
+    pub fn new(inner: Arc<dyn ResourceAccess + Send + Sync>) -> CoalescingResourceAccess {
+        CoalescingResourceAccess {
+            inner,
+            in_flight: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+/// Hard links the fetched file to a temporary path of the request's own, so it outlives the
+/// fetch. It is copied instead where it can't be linked.
+fn link(file: &Path) -> io::Result<TempPath> {
+    Builder::new()
+        .make(|path| fs::hard_link(file, path).or_else(|_| fs::copy(file, path).map(|_| ())))
+        .map(NamedTempFile::into_temp_path)
+}
+
+#[async_trait]
+impl ResourceAccess for CoalescingResourceAccess {
+    async fn get_resource(&self, path: PathBuf) -> Result<TempPath, Box<dyn SerializableError>> {
+        let flight = Arc::clone(self.in_flight.lock().unwrap().entry(path.clone()).or_default());
+        let mut fetched = false;
+
+        // Should the request fetching the resource be cancelled, one of those waiting takes over.
+        let result = flight.get_or_init(|| async {
+            fetched = true;
+
+            self.inner.get_resource(path.clone())
+                .await
+                .map(Arc::new)
+                .map_err(|err| SharedError::from(err.as_ref()))
+        }).await;
+
+        if fetched {
+            // Later requests fetch the resource again, rather than reusing this result.
+            self.in_flight.lock().unwrap().remove(&path);
+        } else {
+            debug!("Served resource: '{}' from a concurrent request's fetch", path.to_str().unwrap());
+            counter!("arp_coalesced_requests_total").increment(1);
+        }
+
+        match result {
+            Ok(file) => link(file).map_err(|e| Box::new(IOError(e)) as Box<dyn SerializableError>),
+            Err(err) => Err(Box::new(err.clone()))
+        }
+    }
+
+    async fn exists(&self, path: PathBuf) -> Result<bool, Box<dyn SerializableError>> {
+        self.inner.exists(path).await
+    }
+
+    async fn put_resource(&self, path: PathBuf, file: TempPath) -> Result<(), Box<dyn SerializableError>> {
+        self.inner.put_resource(path, file).await
+    }
+
+    async fn delete_resource(&self, path: PathBuf) -> Result<(), Box<dyn SerializableError>> {
+        self.inner.delete_resource(path).await
+    }
+
+    async fn list(&self, path: PathBuf) -> Result<Vec<ResourceEntry>, Box<dyn SerializableError>> {
+        self.inner.list(path).await
+    }
+
+    async fn list_versions(&self, repository: &str) -> Result<BTreeSet<Coordinates>, Box<dyn SerializableError>> {
+        self.inner.list_versions(repository).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+    use std::sync::Arc;
+    use std::sync::atomic::Ordering;
+    use std::time::Duration;
+
+    use crate::coalesce::CoalescingResourceAccess;
+    use crate::resource_access::memory::MemoryResourceAccess;
+    use crate::resource_access::ResourceAccess;
+
+    #[tokio::test]
+    async fn test_coalesce() {
+        let memory = Arc::new(MemoryResourceAccess {
+            delay: Duration::from_millis(100),
+            ..MemoryResourceAccess::default()
+        });
+        memory.insert("releases/com/example/lib/1.0/lib-1.0.jar", "jar");
+
+        let access = Arc::new(CoalescingResourceAccess::new(memory.clone()));
+
+        let requests = (0..10).map(|_| {
+            let access = Arc::clone(&access);
+            tokio::spawn(async move {
+                access.get_resource(PathBuf::from("releases/com/example/lib/1.0/lib-1.0.jar")).await
+            })
+        }).collect::<Vec<_>>();
+
+        for request in requests {
+            let file = request.await.unwrap().map_err(|err| err.message()).unwrap();
+            assert_eq!(fs::read_to_string(file).unwrap(), "jar");
+        }
+
+        assert_eq!(memory.gets.load(Ordering::Relaxed), 1);
+        assert!(access.in_flight.lock().unwrap().is_empty());
+
+        // Every request waiting on a failed fetch gets its error.
+        let requests = (0..3).map(|_| {
+            let access = Arc::clone(&access);
+            tokio::spawn(async move {
+                access.get_resource(PathBuf::from("releases/com/example/lib/2.0/lib-2.0.jar")).await
+            })
+        }).collect::<Vec<_>>();
+
+        for request in requests {
+            assert_eq!(request.await.unwrap().err().unwrap().status(), 404);
+        }
+
+        assert_eq!(memory.gets.load(Ordering::Relaxed), 2);
+
+        // Requests made after a fetch completed fetch the resource again.
+        access.get_resource(PathBuf::from("releases/com/example/lib/1.0/lib-1.0.jar")).await.map_err(|err| err.message()).unwrap();
+
+        assert_eq!(memory.gets.load(Ordering::Relaxed), 3);
+    }
+}
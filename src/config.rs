@@ -0,0 +1,945 @@
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use log::LevelFilter;
+use rocket::serde::{Deserialize, Serialize};
+
+use crate::{ARProxyConfiguration, RepositoryConfiguration};
+use crate::auth::{ApiCredentials, Permission};
+use crate::filesystem::FilesystemResourceAccess;
+use crate::gcp::gcp_creds;
+use crate::gcp::gcp_resource_access::{ArtifactRegistryResourceAccess, parse_registry_url};
+use crate::logging::{LogFormat, LoggingConfiguration};
+use crate::maven::pom::PomValidation;
+use crate::policy::DeployPolicy;
+use crate::resource_access::ResourceAccess;
+use crate::retention::RetentionPolicy;
+use crate::signature::{Keyring, Signer};
+use crate::upstream::UpstreamConfiguration;
+
+/// The name of the backend configured by the legacy `GAR_API_URL` variable.
+const ENV_BACKEND: &str = "gar";
+
+/// Looks up an environmental variable, passed around so the environment can be replaced in tests.
+pub type Env<'a> = &'a dyn Fn(&str) -> Option<String>;
+
+/// The process' environment.
+pub fn process_env(name: &str) -> Option<String> {
+    env::var(name).ok()
+}
+
+/// The contents of the configuration file. Every section is optional, and settings given through
+/// the environment override the file's.
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConfigFile {
+    pub backends: HashMap<String, BackendConfiguration>,
+    pub repositories: HashMap<String, RepositoryEntry>,
+    pub principals: Vec<PrincipalEntry>,
+    pub cache: CacheSection,
+    pub search: SearchSection,
+    pub retention: RetentionSection,
+    pub signing: SigningSection,
+    pub holds: HoldsSection,
+    pub reload: ReloadSection,
+    pub tracing: TracingSection,
+    pub logging: LoggingSection,
+    pub audit: AuditSection,
+    pub upstream: UpstreamSection,
+}
+
+/// Where the files of repositories are stored.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "kebab-case", deny_unknown_fields)]
+pub enum BackendConfiguration {
+    /// A Google Artifact Registry Maven endpoint, eg. `https://<LOCATION>-maven.pkg.dev/<PROJECT_ID>`.
+    ArtifactRegistry { url: String },
+    /// A directory on the local disk.
+    Filesystem { directory: PathBuf },
+}
+
+impl BackendConfiguration {
+    pub fn connect(&self, upstream: &UpstreamConfiguration) -> Result<Arc<dyn ResourceAccess + Send + Sync>, String> {
+        Ok(match self {
+            BackendConfiguration::ArtifactRegistry { url } => Arc::new(ArtifactRegistryResourceAccess::new(
+                gcp_creds().map_err(|err| format!("Failed to find GCP credentials, {}", err))?,
+                url.clone(),
+                upstream.clone(),
+            ).map_err(|err| format!("Failed to build the HTTP client, {}", err))?),
+            BackendConfiguration::Filesystem { directory } => Arc::new(FilesystemResourceAccess {
+                directory: directory.clone(),
+            }),
+        })
+    }
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct RepositoryEntry {
+    /// The repository's id in its backend, defaults to its public name.
+    pub id: Option<String>,
+    /// The backend holding the repository, may be left out when only one backend is configured.
+    pub backend: Option<String>,
+    pub deploy_policy: DeployPolicy,
+    pub retention: Option<RetentionPolicy>,
+    pub pom_validation: PomValidation,
+    pub keyring: Option<PathBuf>,
+    pub sign: bool,
+}
+
+/// Credentials allowed to modify repositories, the key is given directly or read from a file.
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct PrincipalEntry {
+    pub user: String,
+    pub key: Option<String>,
+    pub key_file: Option<PathBuf>,
+    pub permissions: Vec<Permission>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct CacheSection {
+    pub directory: Option<PathBuf>,
+    /// Seconds.
+    pub mutable_ttl: u64,
+}
+
+impl Default for CacheSection {
+    fn default() -> Self {
+        CacheSection {
+            directory: None,
+            mutable_ttl: 300,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct SearchSection {
+    /// Seconds, 0 disables periodic indexing.
+    pub index_interval: u64,
+}
+
+impl Default for SearchSection {
+    fn default() -> Self {
+        SearchSection {
+            index_interval: 3600,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetentionSection {
+    pub dry_run: bool,
+    /// Seconds.
+    pub interval: u64,
+}
+
+impl Default for RetentionSection {
+    fn default() -> Self {
+        RetentionSection {
+            dry_run: false,
+            interval: 86400,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct ReloadSection {
+    /// Seconds between checks of the configuration file for changes, 0 disables watching.
+    pub watch_interval: u64,
+}
+
+impl Default for ReloadSection {
+    fn default() -> Self {
+        ReloadSection {
+            watch_interval: 30,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoggingSection {
+    /// One of 'error', 'warn', 'info', 'debug', 'trace' or 'off'.
+    pub level: String,
+    pub format: LogFormat,
+    /// A file every line is also written to, only stdout is logged to when not set.
+    pub file: Option<PathBuf>,
+    pub project: Option<String>,
+}
+
+impl Default for LoggingSection {
+    fn default() -> Self {
+        LoggingSection {
+            level: "info".to_string(),
+            format: LogFormat::Text,
+            file: None,
+            project: None,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct TracingSection {
+    /// The OTLP (HTTP) collector spans are exported to, tracing is disabled when not set.
+    pub endpoint: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuditSection {
+    /// The JSON Lines file write operations are recorded to, auditing is disabled when not set.
+    pub file: Option<PathBuf>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct UpstreamSection {
+    /// Seconds.
+    pub connect_timeout: u64,
+    /// Seconds a response may go without receiving any data.
+    pub read_timeout: u64,
+    /// Seconds a whole request may take, including retries of it.
+    pub request_timeout: u64,
+    /// Retries of failed GET and HEAD requests, 0 disables retrying.
+    pub max_retries: u32,
+    pub initial_backoff_ms: u64,
+    pub max_backoff_ms: u64,
+    pub pool_max_idle_per_host: usize,
+    /// Seconds an idle connection is kept open.
+    pub pool_idle_timeout: u64,
+    pub http2: bool,
+    /// Seconds between TCP keepalive probes, 0 disables them.
+    pub tcp_keepalive: u64,
+    /// The proxy requests to backends are sent through, `HTTPS_PROXY` is used when not set.
+    pub proxy: Option<String>,
+    /// Consecutive failures which open a backend's circuit, 0 disables the circuit breaker.
+    pub breaker_threshold: u32,
+    /// Seconds.
+    pub breaker_cooldown: u64,
+}
+
+impl Default for UpstreamSection {
+    fn default() -> Self {
+        UpstreamSection {
+            connect_timeout: 10,
+            read_timeout: 60,
+            request_timeout: 600,
+            max_retries: 3,
+            initial_backoff_ms: 200,
+            max_backoff_ms: 10000,
+            pool_max_idle_per_host: 32,
+            pool_idle_timeout: 90,
+            http2: true,
+            tcp_keepalive: 60,
+            proxy: None,
+            breaker_threshold: 5,
+            breaker_cooldown: 30,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct SigningSection {
+    pub key: Option<String>,
+    pub key_file: Option<PathBuf>,
+    pub passphrase: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct HoldsSection {
+    /// Where files waiting for their signature are kept, one in the temporary directory when not
+    /// set. Instances sharing it accept signatures of files deployed to any of them.
+    pub directory: Option<PathBuf>,
+    /// Megabytes each principal may hold at once.
+    pub max_size_mb: u64,
+}
+
+impl Default for HoldsSection {
+    fn default() -> Self {
+        HoldsSection {
+            directory: None,
+            max_size_mb: 1024,
+        }
+    }
+}
+
+/// A problem with the configuration, along with where it was found (a file, an environmental
+/// variable or a key within the configuration).
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigError {
+    pub location: String,
+    pub message: String,
+}
+
+impl ConfigError {
+    pub(crate) fn new(location: impl Into<String>, message: impl Into<String>) -> ConfigError {
+        ConfigError {
+            location: location.into(),
+            message: message.into(),
+        }
+    }
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.location, self.message)
+    }
+}
+
+/// Parses a comma split, colon paired map from the environmental variable, eg.
+/// 'releases:my-releases,snapshots:my-snapshots'.
+pub(crate) fn parse_repository_map(var: Env, variable: &str, errors: &mut Vec<ConfigError>) -> Vec<(String, String)> {
+    let value = var(variable).unwrap_or_default();
+    let mut map: Vec<(String, String)> = Vec::new();
+
+    for (position, pair) in value.split(',').enumerate().filter(|(_, pair)| !pair.is_empty()) {
+        let location = format!("{} (entry {})", variable, position + 1);
+
+        match pair.split_once(':') {
+            Some((key, value)) if !key.is_empty() && !value.is_empty() => {
+                if map.iter().any(|(existing, _)| existing == key) {
+                    errors.push(ConfigError::new(location, format!("Duplicate repository: '{}'", key)));
+                } else {
+                    map.push((key.to_string(), value.to_string()));
+                }
+            }
+            _ => errors.push(ConfigError::new(location, format!(
+                "Expected a 'name:value' pair, found: '{}'", pair
+            )))
+        }
+    }
+
+    map
+}
+
+/// Replaces every `${NAME}` in the string with the value of the environmental variable.
+fn interpolate(value: &str, var: Env) -> Result<String, String> {
+    let mut result = String::new();
+    let mut rest = value;
+
+    while let Some(start) = rest.find("${") {
+        let end = rest[start..].find('}')
+            .ok_or_else(|| format!("Unterminated '${{' in: '{}'", value))?;
+        let name = &rest[start + 2..start + end];
+
+        result.push_str(&rest[..start]);
+        result.push_str(&var(name).ok_or_else(|| format!("Environmental variable: '{}' is not set", name))?);
+
+        rest = &rest[start + end + 1..];
+    }
+
+    result.push_str(rest);
+
+    Ok(result)
+}
+
+fn interpolate_value(key: &str, value: &mut toml::Value, var: Env, errors: &mut Vec<ConfigError>) {
+    match value {
+        toml::Value::String(string) => match interpolate(string, var) {
+            Ok(interpolated) => *string = interpolated,
+            Err(err) => errors.push(ConfigError::new(key, err))
+        },
+        toml::Value::Array(values) => values.iter_mut()
+            .enumerate()
+            .for_each(|(index, value)| interpolate_value(&format!("{}[{}]", key, index), value, var, errors)),
+        toml::Value::Table(table) => table.iter_mut()
+            .for_each(|(name, value)| interpolate_value(
+                &if key.is_empty() { name.to_string() } else { format!("{}.{}", key, name) },
+                value,
+                var,
+                errors
+            )),
+        _ => {}
+    }
+}
+
+impl ConfigFile {
+    /// Parses a TOML configuration, substituting `${NAME}` in any string value with the
+    /// environmental variable of that name.
+    pub fn parse(toml: &str, var: Env) -> Result<ConfigFile, Vec<ConfigError>> {
+        let mut value = toml.parse::<toml::Table>()
+            .map(toml::Value::Table)
+            .map_err(|err| vec![ConfigError::new("syntax", err.to_string().trim())])?;
+
+        let mut errors = Vec::new();
+        interpolate_value("", &mut value, var, &mut errors);
+
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        value.try_into().map_err(|err: toml::de::Error| vec![ConfigError::new("schema", err.to_string().trim())])
+    }
+
+    pub fn load(path: &Path, var: Env) -> Result<ConfigFile, Vec<ConfigError>> {
+        let location = path.to_str().unwrap();
+
+        let toml = fs::read_to_string(path)
+            .map_err(|err| vec![ConfigError::new(location, format!("Cannot read configuration file, {}", err))])?;
+
+        ConfigFile::parse(&toml, var).map_err(|errors| errors.into_iter()
+            .map(|err| ConfigError::new(format!("{} {}", location, err.location), err.message))
+            .collect())
+    }
+
+    /// The repository an override applies to, which must already be defined by the file or
+    /// by `REPOSITORIES`, so that a misspelled name is reported instead of defining a new one.
+    fn repository(&mut self, variable: &str, name: &str, errors: &mut Vec<ConfigError>) -> Option<&mut RepositoryEntry> {
+        let repository = self.repositories.get_mut(name);
+
+        if repository.is_none() {
+            errors.push(ConfigError::new(format!("{} ({})", variable, name), format!("Unknown repository: '{}'", name)));
+        }
+
+        repository
+    }
+
+    /// Applies the settings given through environmental variables (the original way of
+    /// configuring ARP) on top of the file's, collecting every invalid one.
+    pub fn apply_env(&mut self, var: Env) -> Vec<ConfigError> {
+        let mut errors = Vec::new();
+
+        if let Some(url) = var("GAR_API_URL") {
+            self.backends.insert(ENV_BACKEND.to_string(), BackendConfiguration::ArtifactRegistry { url });
+        }
+
+        for (name, id) in parse_repository_map(var, "REPOSITORIES", &mut errors) {
+            let repository = self.repositories.entry(name).or_default();
+            repository.id = Some(id);
+            if var("GAR_API_URL").is_some() {
+                repository.backend = Some(ENV_BACKEND.to_string());
+            }
+        }
+
+        for (name, policy) in parse_repository_map(var, "DEPLOY_POLICIES", &mut errors) {
+            match DeployPolicy::try_from(policy) {
+                Ok(policy) => if let Some(repository) = self.repository("DEPLOY_POLICIES", &name, &mut errors) {
+                    repository.deploy_policy = policy
+                },
+                Err(err) => errors.push(ConfigError::new(format!("DEPLOY_POLICIES ({})", name), err))
+            }
+        }
+
+        for (name, policy) in parse_repository_map(var, "SNAPSHOT_RETENTION", &mut errors) {
+            match RetentionPolicy::parse(&policy) {
+                Ok(policy) => if let Some(repository) = self.repository("SNAPSHOT_RETENTION", &name, &mut errors) {
+                    repository.retention = Some(policy)
+                },
+                Err(err) => errors.push(ConfigError::new(format!("SNAPSHOT_RETENTION ({})", name), err))
+            }
+        }
+
+        for (name, validation) in parse_repository_map(var, "POM_VALIDATION", &mut errors) {
+            match PomValidation::try_from(validation) {
+                Ok(validation) => if let Some(repository) = self.repository("POM_VALIDATION", &name, &mut errors) {
+                    repository.pom_validation = validation
+                },
+                Err(err) => errors.push(ConfigError::new(format!("POM_VALIDATION ({})", name), err))
+            }
+        }
+
+        for (name, keyring) in parse_repository_map(var, "SIGNATURE_KEYRINGS", &mut errors) {
+            if let Some(repository) = self.repository("SIGNATURE_KEYRINGS", &name, &mut errors) {
+                repository.keyring = Some(PathBuf::from(keyring));
+            }
+        }
+
+        for name in var("SIGNING_REPOSITORIES").unwrap_or_default().split(',').filter(|name| !name.is_empty()) {
+            if let Some(repository) = self.repository("SIGNING_REPOSITORIES", name, &mut errors) {
+                repository.sign = true;
+            }
+        }
+
+        for (variable, permission) in [("CREDENTIALS", Permission::Deploy), ("DELETE_CREDENTIALS", Permission::Delete)] {
+            if let Some(binding) = var(variable) {
+                match binding.split_once(':') {
+                    Some((user, key)) => self.principals.push(PrincipalEntry {
+                        user: user.to_string(),
+                        key: Some(key.to_string()),
+                        key_file: None,
+                        permissions: vec![permission],
+                    }),
+                    None => errors.push(ConfigError::new(variable, "Expected a 'user:key' pair"))
+                }
+            }
+        }
+
+        let mut seconds = |name: &str, target: &mut u64| if let Some(value) = var(name) {
+            match value.parse() {
+                Ok(value) => *target = value,
+                Err(_) => errors.push(ConfigError::new(name, format!("Expected a number of seconds, found: '{}'", value)))
+            }
+        };
+
+        seconds("CACHE_MUTABLE_TTL", &mut self.cache.mutable_ttl);
+        seconds("SEARCH_INDEX_INTERVAL", &mut self.search.index_interval);
+        seconds("RETENTION_INTERVAL", &mut self.retention.interval);
+        seconds("CONFIG_WATCH_INTERVAL", &mut self.reload.watch_interval);
+        seconds("UPSTREAM_CONNECT_TIMEOUT", &mut self.upstream.connect_timeout);
+        seconds("UPSTREAM_READ_TIMEOUT", &mut self.upstream.read_timeout);
+        seconds("UPSTREAM_REQUEST_TIMEOUT", &mut self.upstream.request_timeout);
+
+        if let Some(proxy) = var("UPSTREAM_PROXY") {
+            self.upstream.proxy = Some(proxy);
+        }
+
+        if let Some(value) = var("UPSTREAM_MAX_RETRIES") {
+            match value.parse() {
+                Ok(value) => self.upstream.max_retries = value,
+                Err(_) => errors.push(ConfigError::new("UPSTREAM_MAX_RETRIES", format!("Expected a number of retries, found: '{}'", value)))
+            }
+        }
+
+        if let Some(endpoint) = var("OTEL_EXPORTER_OTLP_ENDPOINT") {
+            self.tracing.endpoint = Some(endpoint);
+        }
+
+        if let Some(level) = var("LOG_LEVEL") {
+            self.logging.level = level;
+        }
+        if let Some(format) = var("LOG_FORMAT") {
+            match LogFormat::try_from(format) {
+                Ok(format) => self.logging.format = format,
+                Err(err) => errors.push(ConfigError::new("LOG_FORMAT", err))
+            }
+        }
+        if let Some(file) = var("LOG_FILE") {
+            self.logging.file = Some(PathBuf::from(file));
+        }
+        if let Some(project) = var("GOOGLE_CLOUD_PROJECT") {
+            self.logging.project = Some(project);
+        }
+
+        if let Some(file) = var("AUDIT_LOG_FILE") {
+            self.audit.file = Some(PathBuf::from(file));
+        }
+
+        if let Some(directory) = var("HOLD_DIRECTORY") {
+            self.holds.directory = Some(PathBuf::from(directory));
+        }
+        if let Some(value) = var("HOLD_MAX_SIZE_MB") {
+            match value.parse() {
+                Ok(value) => self.holds.max_size_mb = value,
+                Err(_) => errors.push(ConfigError::new("HOLD_MAX_SIZE_MB", format!("Expected a number of megabytes, found: '{}'", value)))
+            }
+        }
+
+        if let Some(directory) = var("CACHE_DIRECTORY") {
+            self.cache.directory = Some(PathBuf::from(directory));
+        }
+        if let Some(dry_run) = var("RETENTION_DRY_RUN") {
+            match dry_run.parse() {
+                Ok(dry_run) => self.retention.dry_run = dry_run,
+                Err(_) => errors.push(ConfigError::new("RETENTION_DRY_RUN", format!("Expected 'true' or 'false', found: '{}'", dry_run)))
+            }
+        }
+
+        if let Some(key) = var("SIGNING_KEY") {
+            self.signing.key = Some(key);
+        }
+        if let Some(key_file) = var("SIGNING_KEY_FILE") {
+            self.signing.key_file = Some(PathBuf::from(key_file));
+        }
+        if let Some(passphrase) = var("SIGNING_KEY_PASSPHRASE") {
+            self.signing.passphrase = Some(passphrase);
+        }
+
+        errors
+    }
+
+    /// Loads keys and checks every setting and reference between sections, producing the
+    /// configuration ARP runs with or every problem found.
+    pub fn resolve(self) -> Result<ARProxyConfiguration, Vec<ConfigError>> {
+        let mut errors = Vec::new();
+
+        let level = LevelFilter::from_str(&self.logging.level)
+            .map_err(|_| errors.push(ConfigError::new("logging.level", format!(
+                "Invalid log level: '{}', expected one of: 'error', 'warn', 'info', 'debug', 'trace' or 'off'", self.logging.level
+            ))))
+            .unwrap_or(LevelFilter::Info);
+
+        if let Some(endpoint) = &self.tracing.endpoint {
+            if !reqwest::Url::parse(endpoint).map(|url| url.scheme().starts_with("http")).unwrap_or(false) {
+                errors.push(ConfigError::new("tracing.endpoint", format!("Invalid URL: '{}'", endpoint)));
+            }
+        }
+
+        if let Some(file) = &self.audit.file {
+            let parent = file.parent().filter(|parent| !parent.as_os_str().is_empty()).unwrap_or(Path::new("."));
+
+            if !parent.is_dir() || file.is_dir() {
+                errors.push(ConfigError::new("audit.file", format!(
+                    "Cannot write to: '{}', its directory must exist", file.to_str().unwrap()
+                )));
+            }
+        }
+
+        if let Some(directory) = &self.holds.directory {
+            let parent = directory.parent().filter(|parent| !parent.as_os_str().is_empty()).unwrap_or(Path::new("."));
+
+            if !directory.is_dir() && (!parent.is_dir() || directory.exists()) {
+                errors.push(ConfigError::new("holds.directory", format!(
+                    "Cannot hold files in: '{}', it or its parent must be a directory", directory.to_str().unwrap()
+                )));
+            }
+        }
+
+        for (location, timeout) in [
+            ("upstream.connect_timeout", self.upstream.connect_timeout),
+            ("upstream.read_timeout", self.upstream.read_timeout),
+            ("upstream.request_timeout", self.upstream.request_timeout),
+        ] {
+            if timeout == 0 {
+                errors.push(ConfigError::new(location, "The timeout must be at least one second"));
+            }
+        }
+
+        if self.upstream.initial_backoff_ms > self.upstream.max_backoff_ms {
+            errors.push(ConfigError::new("upstream.initial_backoff_ms", format!(
+                "The initial backoff: {}ms is longer than the maximum: {}ms", self.upstream.initial_backoff_ms, self.upstream.max_backoff_ms
+            )));
+        }
+
+        if let Some(proxy) = &self.upstream.proxy {
+            if reqwest::Proxy::all(proxy).is_err() {
+                errors.push(ConfigError::new("upstream.proxy", format!("Invalid URL: '{}'", proxy)));
+            }
+        }
+
+        if self.backends.is_empty() {
+            errors.push(ConfigError::new("backends", "No backends are configured, set GAR_API_URL or add a backend"));
+        }
+
+        if self.repositories.is_empty() {
+            errors.push(ConfigError::new("repositories", "No repositories are configured, set REPOSITORIES or add a repository"));
+        }
+
+        for (name, backend) in &self.backends {
+            let location = format!("backends.{}", name);
+
+            match backend {
+                BackendConfiguration::ArtifactRegistry { url } => if parse_registry_url(url).is_none() {
+                    errors.push(ConfigError::new(location, format!(
+                        "Invalid URL: '{}', expected 'https://<LOCATION>-maven.pkg.dev/<PROJECT_ID>'", url
+                    )));
+                },
+                BackendConfiguration::Filesystem { directory } => if directory.exists() && !directory.is_dir() {
+                    errors.push(ConfigError::new(location, format!(
+                        "'{}' is not a directory", directory.to_str().unwrap()
+                    )));
+                }
+            }
+        }
+
+        let signing_key = match (&self.signing.key, &self.signing.key_file) {
+            (Some(key), _) => Some(key.clone().into_bytes()),
+            (None, Some(path)) => fs::read(path)
+                .map_err(|err| errors.push(ConfigError::new("signing.key_file", format!(
+                    "Cannot read: '{}', {}", path.to_str().unwrap(), err
+                ))))
+                .ok(),
+            (None, None) => None
+        };
+
+        let signer = signing_key.and_then(|key| Signer::parse(&key, self.signing.passphrase.as_deref().unwrap_or_default())
+            .map(Arc::new)
+            .map_err(|err| errors.push(ConfigError::new("signing", format!("Invalid signing key, {}", err))))
+            .ok());
+
+        let mut creds = Vec::new();
+
+        for (position, principal) in self.principals.into_iter().enumerate() {
+            let location = format!("principals[{}] ({})", position, principal.user);
+
+            let key = match (principal.key, &principal.key_file) {
+                (Some(key), None) => Some(key),
+                (None, Some(path)) => fs::read_to_string(path)
+                    .map(|key| key.trim().to_string())
+                    .map_err(|err| errors.push(ConfigError::new(&location, format!(
+                        "Cannot read key file: '{}', {}", path.to_str().unwrap(), err
+                    ))))
+                    .ok(),
+                _ => {
+                    errors.push(ConfigError::new(&location, "Expected exactly one of 'key' or 'key_file'"));
+                    None
+                }
+            };
+
+            match key {
+                Some(key) if principal.user.is_empty() || key.is_empty() => errors.push(ConfigError::new(&location, "The user and key must not be empty")),
+                Some(key) => creds.push(ApiCredentials {
+                    user: principal.user,
+                    key,
+                    permissions: principal.permissions,
+                }),
+                None => {}
+            }
+        }
+
+        let mut repositories: HashMap<String, RepositoryConfiguration> = HashMap::new();
+        let mut names = self.repositories.keys().cloned().collect::<Vec<String>>();
+        names.sort();
+
+        for name in names {
+            let entry = &self.repositories[&name];
+            let location = format!("repositories.{}", name);
+
+            let backend = match &entry.backend {
+                Some(backend) if self.backends.contains_key(backend) => Some(backend.clone()),
+                Some(backend) => {
+                    errors.push(ConfigError::new(format!("{}.backend", location), format!("Unknown backend: '{}'", backend)));
+                    None
+                }
+                None if self.backends.len() == 1 => self.backends.keys().next().cloned(),
+                None => {
+                    if !self.backends.is_empty() {
+                        errors.push(ConfigError::new(&location, "Must choose one of the configured backends"));
+                    }
+                    None
+                }
+            };
+
+            let id = entry.id.clone().unwrap_or(name.clone());
+
+            if id.is_empty() || id.contains('/') {
+                errors.push(ConfigError::new(format!("{}.id", location), format!("Invalid repository id: '{}'", id)));
+            }
+
+            if let Some((other, _)) = repositories.iter().find(|(_, repository)| repository.id == id && Some(&repository.backend) != backend.as_ref()) {
+                errors.push(ConfigError::new(format!("{}.id", location), format!(
+                    "Repository id: '{}' is already used by: '{}' in another backend", id, other
+                )));
+            }
+
+            let keyring = entry.keyring.as_ref().and_then(|path| Keyring::load(path)
+                .map_err(|err| errors.push(ConfigError::new(format!("{}.keyring", location), format!(
+                    "Invalid keyring: '{}', {}", path.to_str().unwrap(), err
+                ))))
+                .ok());
+
+            if entry.sign && signer.is_none() && self.signing.key.is_none() && self.signing.key_file.is_none() {
+                errors.push(ConfigError::new(format!("{}.sign", location), "No signing key is configured"));
+            }
+
+            if let Some(backend) = backend {
+                repositories.insert(name.clone(), RepositoryConfiguration {
+                    id,
+                    backend,
+                    deploy_policy: entry.deploy_policy,
+                    retention: entry.retention.clone(),
+                    pom_validation: entry.pom_validation,
+                    keyring,
+                    signer: signer.clone().filter(|_| entry.sign),
+                });
+            }
+        }
+
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        Ok(ARProxyConfiguration {
+            repositories,
+            backends: self.backends,
+            creds,
+            cache_directory: self.cache.directory,
+            cache_mutable_ttl: Duration::from_secs(self.cache.mutable_ttl),
+            search_index_interval: Duration::from_secs(self.search.index_interval),
+            retention_dry_run: self.retention.dry_run,
+            retention_interval: Duration::from_secs(self.retention.interval),
+            watch_interval: Duration::from_secs(self.reload.watch_interval),
+            tracing_endpoint: self.tracing.endpoint,
+            logging: LoggingConfiguration {
+                level,
+                format: self.logging.format,
+                file: self.logging.file.filter(|file| !file.as_os_str().is_empty()),
+                project: self.logging.project,
+            },
+            audit_file: self.audit.file,
+            hold_directory: self.holds.directory.unwrap_or_else(|| env::temp_dir().join("arp-held")),
+            hold_max_size: self.holds.max_size_mb * 1024 * 1024,
+            upstream: UpstreamConfiguration {
+                connect_timeout: Duration::from_secs(self.upstream.connect_timeout),
+                read_timeout: Duration::from_secs(self.upstream.read_timeout),
+                request_timeout: Duration::from_secs(self.upstream.request_timeout),
+                max_retries: self.upstream.max_retries,
+                initial_backoff: Duration::from_millis(self.upstream.initial_backoff_ms),
+                max_backoff: Duration::from_millis(self.upstream.max_backoff_ms),
+                pool_max_idle_per_host: self.upstream.pool_max_idle_per_host,
+                pool_idle_timeout: Duration::from_secs(self.upstream.pool_idle_timeout),
+                http2: self.upstream.http2,
+                tcp_keepalive: Some(Duration::from_secs(self.upstream.tcp_keepalive)).filter(|interval| !interval.is_zero()),
+                proxy: self.upstream.proxy,
+                breaker_threshold: self.upstream.breaker_threshold,
+                breaker_cooldown: Duration::from_secs(self.upstream.breaker_cooldown),
+            },
+        })
+    }
+}
+
+/// Reads the configuration file (if any), then applies the environment, returning every problem
+/// found along the way.
+pub fn setup_configuration(path: Option<&Path>) -> Result<ARProxyConfiguration, Vec<ConfigError>> {
+    let mut file = match path {
+        Some(path) => ConfigFile::load(path, &process_env)?,
+        None => ConfigFile::default()
+    };
+
+    let errors = file.apply_env(&process_env);
+
+    match file.resolve() {
+        Ok(configuration) if errors.is_empty() => Ok(configuration),
+        Ok(_) => Err(errors),
+        Err(resolve_errors) => Err(errors.into_iter().chain(resolve_errors).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::time::Duration;
+
+    use chrono::TimeDelta;
+
+    use crate::config::{BackendConfiguration, ConfigError, ConfigFile, Env};
+    use crate::policy::DeployPolicy;
+
+    /// No environmental variables are set.
+    const EMPTY: Env = &|_| None;
+
+    #[test]
+    fn test_parse_config() {
+        let environment = HashMap::from([("DEPLOY_KEY", "secret")]);
+        let var: Env = &|name| environment.get(name).map(|value| value.to_string());
+
+        let file = ConfigFile::parse(r#"
+            [backends.local]
+            type = "filesystem"
+            directory = "/srv/maven"
+
+            [repositories.releases]
+            deploy_policy = "deny"
+            retention = { keep = 5, max_age_days = 30 }
+
+            [[principals]]
+            user = "ci"
+            key = "${DEPLOY_KEY}"
+            permissions = ["deploy", "delete"]
+
+            [cache]
+            directory = "/cache"
+        "#, var).unwrap();
+
+        assert_eq!(file.backends["local"], BackendConfiguration::Filesystem { directory: "/srv/maven".into() });
+        assert_eq!(file.principals[0].key.as_deref(), Some("secret"));
+
+        let configuration = file.resolve().unwrap();
+        let releases = &configuration.repositories["releases"];
+
+        assert_eq!(releases.id, "releases");
+        assert_eq!(releases.backend, "local");
+        assert_eq!(releases.deploy_policy, DeployPolicy::Deny);
+        assert_eq!(releases.retention.as_ref().unwrap().max_age, Some(TimeDelta::days(30)));
+        assert_eq!(configuration.cache_mutable_ttl, Duration::from_secs(300));
+
+        assert!(ConfigFile::parse("[repositories.releases]\ndeploy_policy = \"sometimes\"", var).is_err());
+        assert!(ConfigFile::parse("[cache]\nttl = 5", var).is_err());
+        assert!(ConfigFile::parse("[signing]\nkey = \"${UNSET}\"", var).is_err());
+        assert!(ConfigFile::parse("[repositories.releases]\nbackend = \"other\"", var).unwrap().resolve().is_err());
+    }
+
+    #[test]
+    fn test_apply_env() {
+        let environment = HashMap::from([
+            ("GAR_API_URL", "https://europe-maven.pkg.dev/project"),
+            ("REPOSITORIES", "releases:maven-releases,snapshots:maven-snapshots"),
+            ("DEPLOY_POLICIES", "releases:deny,other,relases:allow"),
+            ("SIGNING_REPOSITORIES", "snapshots,staging"),
+            ("UPSTREAM_READ_TIMEOUT", "soon"),
+            ("CREDENTIALS", "ci:secret"),
+        ]);
+
+        let mut file = ConfigFile::default();
+        let errors = file.apply_env(&|name| environment.get(name).map(|value| value.to_string()));
+
+        let locations = errors.iter().map(|err| err.location.as_str()).collect::<Vec<&str>>();
+        assert_eq!(locations, vec![
+            "DEPLOY_POLICIES (entry 2)",
+            "DEPLOY_POLICIES (relases)",
+            "SIGNING_REPOSITORIES (staging)",
+            "UPSTREAM_READ_TIMEOUT",
+        ]);
+        assert!(!file.repositories.contains_key("relases"));
+        assert!(file.repositories["snapshots"].sign);
+
+        let releases = &file.repositories["releases"];
+        assert_eq!(releases.id.as_deref(), Some("maven-releases"));
+        assert_eq!(releases.backend.as_deref(), Some("gar"));
+        assert_eq!(releases.deploy_policy, DeployPolicy::Deny);
+        assert_eq!(file.principals[0].key.as_deref(), Some("secret"));
+
+        assert!(ConfigFile::default().apply_env(EMPTY).is_empty());
+    }
+
+    #[test]
+    fn test_collects_every_problem() {
+        let errors = ConfigFile::parse(r#"
+            [backends.gar]
+            type = "artifact-registry"
+            url = "https://example.com/project"
+
+            [backends.local]
+            type = "filesystem"
+            directory = "/srv/maven"
+
+            [repositories.releases]
+            backend = "locl"
+            keyring = "/nonexistent/keyring.asc"
+
+            [repositories.snapshots]
+
+            [repositories.mirror]
+            id = "shared"
+            backend = "gar"
+
+            [repositories.other]
+            id = "shared"
+            backend = "local"
+
+            [[principals]]
+            user = "ci"
+            key_file = "/nonexistent/key"
+            permissions = ["deploy"]
+        "#, EMPTY).unwrap().resolve().err().unwrap();
+
+        let locations = errors.iter().map(|err| err.location.as_str()).collect::<Vec<&str>>();
+
+        assert_eq!(locations, vec![
+            "backends.gar",
+            "principals[0] (ci)",
+            "repositories.other.id",
+            "repositories.releases.backend",
+            "repositories.releases.keyring",
+            "repositories.snapshots",
+        ]);
+        assert_eq!(errors[0], ConfigError {
+            location: "backends.gar".to_string(),
+            message: "Invalid URL: 'https://example.com/project', expected 'https://<LOCATION>-maven.pkg.dev/<PROJECT_ID>'".to_string(),
+        });
+    }
+}
@@ -5,7 +5,7 @@ use rocket::response::status;
 use rocket::serde::json::Json;
 use rocket::serde::Serialize;
 
-pub trait SerializableError: Debug {
+pub trait SerializableError: Debug + Send {
     fn name(&self) -> &'static str;
 
     fn message(&self) -> String;
@@ -27,13 +27,17 @@ impl BasicError {
             message: err.message().clone(),
         }))
     }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
 }
 
-impl Into<BasicError> for Error {
-    fn into(self) -> BasicError {
+impl From<Error> for BasicError {
+    fn from(err: Error) -> Self {
         BasicError {
             error_name: "File system exception",
-            message: self.to_string(),
+            message: err.to_string(),
         }
     }
 }
@@ -70,4 +74,345 @@ impl SerializableError for IOError {
     fn status(&self) -> u16 {
         500
     }
-}
\ No newline at end of file
+}
+
+#[derive(Debug)]
+pub struct RedeployNotAllowed(pub String);
+
+impl SerializableError for RedeployNotAllowed {
+    fn name(&self) -> &'static str {
+        "Redeploy not allowed"
+    }
+
+    fn message(&self) -> String {
+        format!("Resource: '{}' already exists and this repository does not allow it to be redeployed", self.0)
+    }
+
+    fn status(&self) -> u16 {
+        409
+    }
+}
+
+
+#[derive(Debug)]
+pub struct ResourceNotFound(pub String);
+
+impl SerializableError for ResourceNotFound {
+    fn name(&self) -> &'static str {
+        "Resource not found"
+    }
+
+    fn message(&self) -> String {
+        format!("Failed to find resource: '{}'", self.0)
+    }
+
+    fn status(&self) -> u16 {
+        404
+    }
+}
+
+
+#[derive(Debug)]
+pub struct MalformedMetadata(pub String, pub String);
+
+impl SerializableError for MalformedMetadata {
+    fn name(&self) -> &'static str {
+        "Malformed metadata"
+    }
+
+    fn message(&self) -> String {
+        format!("Failed to parse metadata: '{}' because: {}", self.0, self.1)
+    }
+
+    fn status(&self) -> u16 {
+        502
+    }
+}
+
+
+#[derive(Debug)]
+pub struct NoRetentionPolicy(pub String);
+
+impl SerializableError for NoRetentionPolicy {
+    fn name(&self) -> &'static str {
+        "No retention policy"
+    }
+
+    fn message(&self) -> String {
+        format!("Repository: '{}' has no snapshot retention policy configured", self.0)
+    }
+
+    fn status(&self) -> u16 {
+        400
+    }
+}
+
+#[derive(Debug)]
+pub struct InvalidModule(pub String, pub String);
+
+impl SerializableError for InvalidModule {
+    fn name(&self) -> &'static str {
+        "Invalid Gradle module"
+    }
+
+    fn message(&self) -> String {
+        format!("Gradle module: '{}' could not be parsed, {}", self.0, self.1)
+    }
+
+    fn status(&self) -> u16 {
+        422
+    }
+}
+
+#[derive(Debug)]
+pub struct InvalidPom(pub String, pub Vec<String>);
+
+impl SerializableError for InvalidPom {
+    fn name(&self) -> &'static str {
+        "Invalid POM"
+    }
+
+    fn message(&self) -> String {
+        format!("POM: '{}' failed validation, {}", self.0, self.1.join("; "))
+    }
+
+    fn status(&self) -> u16 {
+        422
+    }
+}
+
+#[derive(Debug)]
+pub struct InvalidSignature(pub String, pub String);
+
+impl SerializableError for InvalidSignature {
+    fn name(&self) -> &'static str {
+        "Invalid signature"
+    }
+
+    fn message(&self) -> String {
+        format!("Signature: '{}' could not be verified, {}", self.0, self.1)
+    }
+
+    fn status(&self) -> u16 {
+        422
+    }
+}
+
+#[derive(Debug)]
+pub struct UnsignedArtifacts(pub String, pub Vec<String>);
+
+impl SerializableError for UnsignedArtifacts {
+    fn name(&self) -> &'static str {
+        "Unsigned artifacts"
+    }
+
+    fn message(&self) -> String {
+        format!("Version: '{}' has files without a signature: {}", self.0, self.1.join(", "))
+    }
+
+    fn status(&self) -> u16 {
+        422
+    }
+}
+
+#[derive(Debug)]
+pub struct HoldLimitExceeded(pub String, pub u64);
+
+impl SerializableError for HoldLimitExceeded {
+    fn name(&self) -> &'static str {
+        "Hold limit exceeded"
+    }
+
+    fn message(&self) -> String {
+        format!("Principal: '{}' may only hold {} bytes of files waiting for their signatures, deploy those first", self.0, self.1)
+    }
+
+    fn status(&self) -> u16 {
+        507
+    }
+}
+
+#[derive(Debug)]
+pub struct SigningFailed(pub String, pub String);
+
+impl SerializableError for SigningFailed {
+    fn name(&self) -> &'static str {
+        "Signing failed"
+    }
+
+    fn message(&self) -> String {
+        format!("Failed to sign: '{}', {}", self.0, self.1)
+    }
+
+    fn status(&self) -> u16 {
+        500
+    }
+}
+
+#[derive(Debug)]
+pub struct AuditLogDisabled;
+
+impl SerializableError for AuditLogDisabled {
+    fn name(&self) -> &'static str {
+        "Audit log disabled"
+    }
+
+    fn message(&self) -> String {
+        "No audit log is configured".to_string()
+    }
+
+    fn status(&self) -> u16 {
+        404
+    }
+}
+
+#[derive(Debug)]
+pub struct AuditLogFailed(pub String);
+
+impl SerializableError for AuditLogFailed {
+    fn name(&self) -> &'static str {
+        "Audit log failed"
+    }
+
+    fn message(&self) -> String {
+        format!("Failed to read the audit log, {}", self.0)
+    }
+
+    fn status(&self) -> u16 {
+        500
+    }
+}
+
+#[derive(Debug)]
+pub struct InvalidAuditAction(pub String);
+
+impl SerializableError for InvalidAuditAction {
+    fn name(&self) -> &'static str {
+        "Invalid audit action"
+    }
+
+    fn message(&self) -> String {
+        format!("Invalid action: '{}', expected one of: 'put', 'delete', 'promote' or 'retention'", self.0)
+    }
+
+    fn status(&self) -> u16 {
+        400
+    }
+}
+
+#[derive(Debug)]
+pub struct CheckTimedOut(pub u64);
+
+impl SerializableError for CheckTimedOut {
+    fn name(&self) -> &'static str {
+        "Check timed out"
+    }
+
+    fn message(&self) -> String {
+        format!("Did not complete within {} seconds", self.0)
+    }
+
+    fn status(&self) -> u16 {
+        503
+    }
+}
+
+#[derive(Debug)]
+pub struct CircuitOpen(pub String, pub u64);
+
+impl SerializableError for CircuitOpen {
+    fn name(&self) -> &'static str {
+        "Backend unavailable"
+    }
+
+    fn message(&self) -> String {
+        format!("Backend: '{}' is unavailable after repeated failures, retrying in {} seconds", self.0, self.1)
+    }
+
+    fn status(&self) -> u16 {
+        503
+    }
+}
+
+/// A copy of an error, handed to every request waiting on the operation which failed.
+#[derive(Debug, Clone)]
+pub struct SharedError {
+    pub name: &'static str,
+    pub message: String,
+    pub status: u16,
+}
+
+impl SharedError {
+    pub fn from(err: &dyn SerializableError) -> SharedError {
+        SharedError {
+            name: err.name(),
+            message: err.message(),
+            status: err.status(),
+        }
+    }
+}
+
+impl SerializableError for SharedError {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn message(&self) -> String {
+        self.message.clone()
+    }
+
+    fn status(&self) -> u16 {
+        self.status
+    }
+}
+
+#[derive(Debug)]
+pub struct CacheDisabled;
+
+impl SerializableError for CacheDisabled {
+    fn name(&self) -> &'static str {
+        "Cache disabled"
+    }
+
+    fn message(&self) -> String {
+        "No cache directory is configured".to_string()
+    }
+
+    fn status(&self) -> u16 {
+        404
+    }
+}
+
+#[derive(Debug)]
+pub struct PrincipalNotFound(pub String);
+
+impl SerializableError for PrincipalNotFound {
+    fn name(&self) -> &'static str {
+        "Principal not found"
+    }
+
+    fn message(&self) -> String {
+        format!("No principal: '{}' is configured", self.0)
+    }
+
+    fn status(&self) -> u16 {
+        404
+    }
+}
+
+#[derive(Debug)]
+pub struct InvalidSegment(pub &'static str, pub String);
+
+impl SerializableError for InvalidSegment {
+    fn name(&self) -> &'static str {
+        "Invalid coordinate"
+    }
+
+    fn message(&self) -> String {
+        format!("Invalid {}: '{}', it can't be empty or contain '/', '\\' or '..'", self.0, self.1)
+    }
+
+    fn status(&self) -> u16 {
+        400
+    }
+}
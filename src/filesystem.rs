@@ -0,0 +1,139 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use rocket::async_trait;
+use tempfile::{NamedTempFile, TempPath};
+
+use crate::err::{IOError, ResourceNotFound, SerializableError};
+use crate::health::check_writable;
+use crate::resource_access::{Check, ResourceAccess, ResourceEntry};
+
+/// Stores repositories as plain directories on the local disk, mirroring the Maven layout.
+pub struct FilesystemResourceAccess {
+    pub directory: PathBuf,
+}
+
+fn io_error(err: std::io::Error) -> Box<dyn SerializableError> {
+    Box::new(IOError(err))
+}
+
+fn not_found(path: &Path) -> Box<dyn SerializableError> {
+    Box::new(ResourceNotFound(path.to_str().unwrap().to_string()))
+}
+
+#[async_trait]
+impl ResourceAccess for FilesystemResourceAccess {
+    async fn get_resource(&self, path: PathBuf) -> Result<TempPath, Box<dyn SerializableError>> {
+        let source = self.directory.join(&path);
+
+        if !source.is_file() {
+            return Err(not_found(&path));
+        }
+
+        let file = NamedTempFile::new().map_err(io_error)?;
+        fs::copy(source, file.path()).map_err(io_error)?;
+
+        Ok(file.into_temp_path())
+    }
+
+    async fn exists(&self, path: PathBuf) -> Result<bool, Box<dyn SerializableError>> {
+        Ok(self.directory.join(path).is_file())
+    }
+
+    async fn put_resource(&self, path: PathBuf, file: TempPath) -> Result<(), Box<dyn SerializableError>> {
+        let target = self.directory.join(path);
+        let parent = target.parent().unwrap();
+
+        fs::create_dir_all(parent).map_err(io_error)?;
+
+        // Written next to the target first, so readers never see a partial file.
+        let temp = NamedTempFile::new_in(parent).map_err(io_error)?;
+        fs::copy(&file, temp.path()).map_err(io_error)?;
+        temp.persist(target).map_err(|err| io_error(err.error))?;
+
+        Ok(())
+    }
+
+    async fn delete_resource(&self, path: PathBuf) -> Result<(), Box<dyn SerializableError>> {
+        let target = self.directory.join(&path);
+
+        let result = if target.is_dir() {
+            fs::remove_dir_all(target)
+        } else {
+            fs::remove_file(target)
+        };
+
+        match result {
+            Err(err) if err.kind() == ErrorKind::NotFound => Err(not_found(&path)),
+            result => result.map_err(io_error)
+        }
+    }
+
+    async fn list(&self, path: PathBuf) -> Result<Vec<ResourceEntry>, Box<dyn SerializableError>> {
+        let entries = match fs::read_dir(self.directory.join(path)) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(io_error(err))
+        };
+
+        let mut listing = Vec::new();
+
+        for entry in entries {
+            let entry = entry.map_err(io_error)?;
+            let metadata = entry.metadata().map_err(io_error)?;
+
+            listing.push(ResourceEntry {
+                name: entry.file_name().to_string_lossy().to_string(),
+                directory: metadata.is_dir(),
+                size: Some(metadata.len()).filter(|_| metadata.is_file()),
+                updated: metadata.modified()
+                    .ok()
+                    .map(|modified| DateTime::<Utc>::from(modified).to_rfc3339_opts(SecondsFormat::Secs, true)),
+            });
+        }
+
+        listing.sort_by(|first, second| first.name.cmp(&second.name));
+
+        Ok(listing)
+    }
+
+    async fn check(&self) -> Vec<Check> {
+        vec![("directory", check_writable(&self.directory))]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use tempfile::tempdir;
+
+    use crate::filesystem::FilesystemResourceAccess;
+    use crate::promote::temp_file;
+    use crate::resource_access::ResourceAccess;
+
+    #[tokio::test]
+    async fn test_filesystem_backend() {
+        let directory = tempdir().unwrap();
+        let access = FilesystemResourceAccess {
+            directory: directory.path().to_path_buf(),
+        };
+
+        let jar = PathBuf::from("releases/com/example/lib/1.0/lib-1.0.jar");
+        access.put_resource(jar.clone(), temp_file(b"jar").unwrap()).await.unwrap();
+
+        assert!(access.exists(jar.clone()).await.unwrap());
+        assert_eq!(std::fs::read(access.get_resource(jar.clone()).await.unwrap()).unwrap(), b"jar");
+
+        let listing = access.list(PathBuf::from("releases/com/example/lib")).await.unwrap();
+        assert_eq!(listing.len(), 1);
+        assert!(listing[0].directory);
+
+        access.delete_resource(PathBuf::from("releases/com/example/lib/1.0")).await.unwrap();
+        assert!(!access.exists(jar.clone()).await.unwrap());
+        assert_eq!(access.get_resource(jar).await.unwrap_err().status(), 404);
+        assert!(access.list(PathBuf::from("releases/com/example/lib/1.0")).await.unwrap().is_empty());
+    }
+}
@@ -4,12 +4,15 @@ use std::process::Command;
 
 use chrono::{DateTime, FixedOffset};
 use log::{debug, info};
+use metrics::counter;
+use opentelemetry::trace::SpanKind;
 use serde_json::Value;
 use tokio::sync::RwLock;
 
 use GCPTokenError::MalformedJsonCreds;
 
 use crate::gcp::gcp_creds::GCPTokenError::ISOParse;
+use crate::trace::{in_span, in_sync_span};
 
 pub struct ArtifactRegistryCreds {
     pub user: &'static str,
@@ -49,6 +52,10 @@ impl Display for GCPTokenError {
 
 impl ArtifactRegistryCreds {
     pub async fn get_key(&self) -> Result<String, GCPTokenError> {
+        in_span("get_key", SpanKind::Internal, self.current_key()).await
+    }
+
+    async fn current_key(&self) -> Result<String, GCPTokenError> {
         let current_datetime = chrono::Local::now();
 
         let inner = self.inner.read().await;
@@ -59,18 +66,29 @@ impl ArtifactRegistryCreds {
             drop(inner);
             info!("GCP Credentials are about to expire (within 5 minutes), refreshing now.");
 
-            let (key, expiration) = retrieve_creds_internal()?;
-
-            let mut inner = self.inner.write().await;
-
-            inner.key = key;
-            inner.expiration = expiration;
+            self.refresh().await?;
         }
 
         Ok(
             self.inner.read().await.key.clone()
         )
     }
+
+    /// Retrieves a new token, replacing the current one even if it hasn't expired.
+    pub async fn refresh(&self) -> Result<(), GCPTokenError> {
+        let (key, expiration) = in_sync_span("refresh_token", retrieve_creds_internal).inspect_err(|_| {
+            counter!("arp_token_refreshes_total", "result" => "failure").increment(1);
+        })?;
+
+        counter!("arp_token_refreshes_total", "result" => "success").increment(1);
+
+        let mut inner = self.inner.write().await;
+
+        inner.key = key;
+        inner.expiration = expiration;
+
+        Ok(())
+    }
 }
 
 pub fn retrieve_creds() -> Result<ArtifactRegistryCreds, GCPTokenError> {
@@ -106,7 +124,7 @@ fn retrieve_creds_internal() -> Result<(String, DateTime<FixedOffset>), GCPToken
 
     let value: Value = serde_json::from_slice(
         output.stdout.as_slice()
-    ).map_err(|err| GCPTokenError::SerdeError(err))?;
+    ).map_err(GCPTokenError::SerdeError)?;
 
     let creds_value = value.as_object().ok_or(MalformedJsonCreds("Expected object."))
         ?.get("credential").ok_or(MalformedJsonCreds("Failed to find 'credential' in json object."))
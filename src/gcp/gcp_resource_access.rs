@@ -1,25 +1,34 @@
+use std::collections::{BTreeMap, BTreeSet};
 use std::fs::File;
 use std::io::{Read, Write};
-use std::path::PathBuf;
+use std::iter::once;
+use std::path::{Path, PathBuf};
 
 use base64::Engine;
 use base64::prelude::BASE64_STANDARD;
 use log::info;
-use reqwest::{Client, StatusCode};
+use percent_encoding::{NON_ALPHANUMERIC, percent_decode_str, utf8_percent_encode};
+use reqwest::{Client, Method, Response, StatusCode, Url};
 use reqwest::header::HeaderValue;
 use rocket::async_trait;
+use serde_json::Value;
 use tempfile::{Builder, TempPath};
 
 use ArtifactRegistryResourceFetchError::{NonSuccessfulStatus, RequestError, TokenError};
 
 use crate::err::{IOError, SerializableError};
 use crate::gcp::gcp_creds::{ArtifactRegistryCreds, GCPTokenError};
-use crate::gcp::gcp_resource_access::ArtifactRegistryResourceFetchError::InvalidPathBuf;
-use crate::resource_access::ResourceAccess;
+use crate::gcp::gcp_resource_access::ArtifactRegistryResourceFetchError::{InvalidPathBuf, InvalidRegistryUrl};
+use crate::maven::Coordinates;
+use crate::resource_access::{Check, ResourceAccess, ResourceEntry};
+use crate::upstream::{send_with_retries, UpstreamConfiguration};
 
 pub struct ArtifactRegistryResourceAccess {
     pub creds: ArtifactRegistryCreds,
     pub url: String,
+    pub upstream: UpstreamConfiguration,
+    /// Shared by every request, so connections (and TLS sessions) are reused.
+    client: Client,
     // pub cache_path: Path,
 }
 
@@ -29,8 +38,12 @@ pub enum ArtifactRegistryResourceFetchError {
     NonSuccessfulStatus(StatusCode, String),
     TokenError(GCPTokenError),
     InvalidPathBuf,
+    InvalidRegistryUrl(String),
 }
 
+/// The Artifact Registry REST API, used for operations the Maven endpoint doesn't support.
+const API_URL: &str = "https://artifactregistry.googleapis.com/v1";
+
 impl SerializableError for ArtifactRegistryResourceFetchError {
     fn name(&self) -> &'static str {
         match self {
@@ -38,6 +51,7 @@ impl SerializableError for ArtifactRegistryResourceFetchError {
             NonSuccessfulStatus(_, _) => { "Non-200 internal response" }
             TokenError(_) => { "Token error" }
             InvalidPathBuf => { "Invalid path supplied" }
+            InvalidRegistryUrl(_) => { "Invalid registry URL" }
         }
     }
 
@@ -46,7 +60,7 @@ impl SerializableError for ArtifactRegistryResourceFetchError {
             RequestError(err) => {
                 format!(
                     "Failed to request resource, wrapped error: {}",
-                    err.to_string()
+                    err
                 )
             }
             NonSuccessfulStatus(status, body) => {
@@ -56,6 +70,9 @@ impl SerializableError for ArtifactRegistryResourceFetchError {
                 format!("Failed to authenticate with teh Artifact Registry servers! {}", it)
             }
             InvalidPathBuf => { "Given path did no have a valid file ending (eg 'test.txt')".to_string() }
+            InvalidRegistryUrl(url) => {
+                format!("Failed to find the project and location in the registry URL: '{}', expected 'https://<LOCATION>-maven.pkg.dev/<PROJECT_ID>'", url)
+            }
         }
     }
 
@@ -65,12 +82,27 @@ impl SerializableError for ArtifactRegistryResourceFetchError {
             NonSuccessfulStatus(status, _) => { status.as_u16() }
             TokenError(_) => { 500 }
             InvalidPathBuf => { 400 }
+            InvalidRegistryUrl(_) => { 500 }
         }
     }
 }
 
 impl ArtifactRegistryResourceAccess {
-    fn get_url(&self, path: &PathBuf) -> String {
+    /// Fails when the client can't be built, eg. the proxy URL is invalid.
+    pub fn new(
+        creds: ArtifactRegistryCreds,
+        url: String,
+        upstream: UpstreamConfiguration,
+    ) -> Result<ArtifactRegistryResourceAccess, reqwest::Error> {
+        Ok(ArtifactRegistryResourceAccess {
+            creds,
+            url,
+            client: upstream.client()?,
+            upstream,
+        })
+    }
+
+    fn get_url(&self, path: &Path) -> String {
         let cloned_url = self.url.clone();
         format!(
             "{}/{}",
@@ -92,6 +124,156 @@ impl ArtifactRegistryResourceAccess {
 
         Ok(encoded_creds)
     }
+
+    /// The REST API resource name of the given repository, derived from the project and location
+    /// in the Maven endpoint URL.
+    fn api_repository_name(&self, repository: &str) -> Result<String, Box<dyn SerializableError>> {
+        let (location, project) = parse_registry_url(&self.url)
+            .ok_or_else(|| Box::new(InvalidRegistryUrl(self.url.clone())) as Box<dyn SerializableError>)?;
+
+        Ok(format!(
+            "projects/{}/locations/{}/repositories/{}",
+            project,
+            location,
+            repository
+        ))
+    }
+
+    async fn api_request(&self, method: Method, url: &str) -> Result<Response, Box<dyn SerializableError>> {
+        let key = self.creds.get_key()
+            .await
+            .map_err(|err| Box::new(TokenError(err)) as Box<dyn SerializableError>)?;
+
+        let request = self.client.clone()
+            .request(method, url)
+            .bearer_auth(key);
+
+        send_with_retries(request, &self.upstream)
+            .await
+            .map_err(|err| Box::new(RequestError(err)) as Box<dyn SerializableError>)
+    }
+
+    /// Lists (at most) one repository of the project, checking the API can be reached with the
+    /// current credentials.
+    async fn check_registry(&self) -> Result<(), Box<dyn SerializableError>> {
+        let (location, project) = parse_registry_url(&self.url)
+            .ok_or_else(|| Box::new(InvalidRegistryUrl(self.url.clone())) as Box<dyn SerializableError>)?;
+
+        let response = self.api_request(
+            Method::GET,
+            &format!("{}/projects/{}/locations/{}/repositories?pageSize=1", API_URL, project, location),
+        ).await?;
+
+        if !response.status().is_success() {
+            return Err(Box::new(NonSuccessfulStatus(
+                response.status(),
+                response.text().await.unwrap_or("<Failed to unwrap body data>".to_string()))) as Box<dyn SerializableError>
+            );
+        }
+
+        Ok(())
+    }
+
+    async fn api_delete(&self, url: &str) -> Result<(), Box<dyn SerializableError>> {
+        info!("Deleting resource at: '{}'", url);
+
+        let response = self.api_request(Method::DELETE, url).await?;
+
+        if !response.status().is_success() {
+            return Err(Box::new(NonSuccessfulStatus(
+                response.status(),
+                response.text().await.unwrap_or("<Failed to unwrap body data>".to_string()))) as Box<dyn SerializableError>
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Collects every page of a REST API list call, returning the items in the given field.
+    async fn api_list(
+        &self,
+        url: &str,
+        field: &str,
+        query: &[(&str, &str)],
+    ) -> Result<Vec<Value>, Box<dyn SerializableError>> {
+        let mut items = Vec::new();
+        let mut page_token: Option<String> = None;
+
+        loop {
+            let mut page_url = Url::parse(url)
+                .map_err(|_| Box::new(InvalidRegistryUrl(url.to_string())) as Box<dyn SerializableError>)?;
+
+            page_url.query_pairs_mut()
+                .extend_pairs(query)
+                .append_pair("pageSize", "1000");
+
+            if let Some(token) = &page_token {
+                page_url.query_pairs_mut().append_pair("pageToken", token);
+            }
+
+            let response = self.api_request(Method::GET, page_url.as_str()).await?;
+
+            if !response.status().is_success() {
+                return Err(Box::new(NonSuccessfulStatus(
+                    response.status(),
+                    response.text().await.unwrap_or("<Failed to unwrap body data>".to_string()))) as Box<dyn SerializableError>
+                );
+            }
+
+            let body: Value = response.json()
+                .await
+                .map_err(|err| Box::new(RequestError(err)) as Box<dyn SerializableError>)?;
+
+            if let Some(values) = body.get(field).and_then(Value::as_array) {
+                items.extend(values.iter().cloned());
+            }
+
+            match body.get("nextPageToken").and_then(Value::as_str) {
+                Some(token) if !token.is_empty() => page_token = Some(token.to_string()),
+                _ => break
+            }
+        }
+
+        Ok(items)
+    }
+}
+
+/// The location and project of a Maven endpoint URL, eg. 'https://<LOCATION>-maven.pkg.dev/<PROJECT_ID>'.
+pub fn parse_registry_url(url: &str) -> Option<(String, String)> {
+    let url = Url::parse(url).ok()?;
+
+    if url.scheme() != "https" {
+        return None;
+    }
+
+    let location = url.host_str()?.strip_suffix("-maven.pkg.dev")?;
+    let project = url.path_segments()?.find(|segment| !segment.is_empty())?;
+
+    Some((location.to_string(), project.to_string()))
+}
+
+fn encode(segment: &str) -> String {
+    utf8_percent_encode(segment, NON_ALPHANUMERIC).to_string()
+}
+
+/// The (decoded) last segment of a REST API resource name.
+fn resource_id(name: &str) -> String {
+    let id = name.rsplit_once('/').map(|(_, id)| id).unwrap_or(name);
+
+    percent_decode_str(id).decode_utf8_lossy().to_string()
+}
+
+/// The start of the id of every package in or under a directory. The directory is a group (or
+/// part of one), an artifact ('group/artifact') or a version ('group/artifact/version'), so the
+/// ids start with all but its last two segments, and at least its first.
+fn package_prefix(segments: &[&str]) -> String {
+    segments[..segments.len().saturating_sub(2).max(segments.len().min(1))].join(".")
+}
+
+fn string_property(value: &Value, property: &str) -> Option<String> {
+    value.get(property)
+        .and_then(Value::as_str)
+        .map(|str| str.to_string())
 }
 
 #[async_trait]
@@ -100,7 +282,7 @@ impl ResourceAccess for ArtifactRegistryResourceAccess {
         let url = self.get_url(&path);
         info!("Request resource from: '{}'", url);
 
-        let response = Client::new()
+        let request = self.client.clone()
             .get(url)
             .header(
                 "Authorization",
@@ -110,8 +292,9 @@ impl ResourceAccess for ArtifactRegistryResourceAccess {
                         self.encoded_creds().await?
                     ).as_str()
                 ).unwrap(),
-            )
-            .send()
+            );
+
+        let response = send_with_retries(request, &self.upstream)
             .await
             .map_err(|err| Box::new(RequestError(err)) as Box<dyn SerializableError>)?;
 
@@ -137,6 +320,37 @@ impl ResourceAccess for ArtifactRegistryResourceAccess {
         Ok(file.into_temp_path())
     }
 
+    async fn exists(&self, path: PathBuf) -> Result<bool, Box<dyn SerializableError>> {
+        let url = self.get_url(&path);
+        info!("Checking resource exists at: '{}'", url);
+
+        let request = self.client.clone()
+            .head(url)
+            .header(
+                "Authorization",
+                HeaderValue::from_str(
+                    format!(
+                        "Basic {}",
+                        self.encoded_creds().await?
+                    ).as_str()
+                ).unwrap(),
+            );
+
+        let response = send_with_retries(request, &self.upstream)
+            .await
+            .map_err(|err| Box::new(RequestError(err)) as Box<dyn SerializableError>)?;
+
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(false);
+        }
+
+        if !response.status().is_success() {
+            return Err(Box::new(NonSuccessfulStatus(response.status(), "<No body for HEAD requests>".to_string())) as Box<dyn SerializableError>);
+        }
+
+        Ok(true)
+    }
+
     async fn put_resource(
         &self,
         path: PathBuf,
@@ -153,7 +367,7 @@ impl ResourceAccess for ArtifactRegistryResourceAccess {
         let url = self.get_url(&path);
         info!("Put resource to: '{}'", url);
 
-        let response = Client::new()
+        let request = self.client.clone()
             .put(url)
             .body(body)
             .header(
@@ -168,8 +382,9 @@ impl ResourceAccess for ArtifactRegistryResourceAccess {
             .header(
                 "Content-Length",
                 content_length,
-            )
-            .send()
+            );
+
+        let response = send_with_retries(request, &self.upstream)
             .await
             .map_err(|err| Box::new(RequestError(err)) as Box<dyn SerializableError>)?;
 
@@ -182,6 +397,208 @@ impl ResourceAccess for ArtifactRegistryResourceAccess {
 
         Ok(())
     }
+
+    async fn delete_resource(&self, path: PathBuf) -> Result<(), Box<dyn SerializableError>> {
+        let segments = path.iter()
+            .map(|segment| segment.to_str())
+            .collect::<Option<Vec<&str>>>()
+            .ok_or_else(|| Box::new(InvalidPathBuf) as Box<dyn SerializableError>)?;
+
+        let (repository, segments) = segments.split_first()
+            .ok_or_else(|| Box::new(InvalidPathBuf) as Box<dyn SerializableError>)?;
+
+        let repository_url = format!("{}/{}", API_URL, self.api_repository_name(repository)?);
+
+        // A path of the form 'group/artifact/version' may point at a whole version, which Artifact
+        // Registry manages as a single unit.
+        if let [group @ .., artifact, version] = segments {
+            if !group.is_empty() {
+                let version_url = format!(
+                    "{}/packages/{}/versions/{}",
+                    repository_url,
+                    encode(format!("{}:{}", group.join("."), artifact).as_str()),
+                    encode(version)
+                );
+
+                let response = self.api_request(Method::GET, &version_url).await?;
+
+                if response.status().is_success() {
+                    return self.api_delete(&version_url).await;
+                } else if response.status() != StatusCode::NOT_FOUND {
+                    return Err(Box::new(NonSuccessfulStatus(
+                        response.status(),
+                        response.text().await.unwrap_or("<Failed to unwrap body data>".to_string()))) as Box<dyn SerializableError>
+                    );
+                }
+            }
+        }
+
+        self.api_delete(&format!(
+            "{}/files/{}",
+            repository_url,
+            encode(segments.join("/").as_str())
+        )).await
+    }
+
+    async fn list(&self, path: PathBuf) -> Result<Vec<ResourceEntry>, Box<dyn SerializableError>> {
+        let segments = path.iter()
+            .map(|segment| segment.to_str())
+            .collect::<Option<Vec<&str>>>()
+            .ok_or_else(|| Box::new(InvalidPathBuf) as Box<dyn SerializableError>)?;
+
+        let (repository, segments) = segments.split_first()
+            .ok_or_else(|| Box::new(InvalidPathBuf) as Box<dyn SerializableError>)?;
+
+        let repository_url = format!("{}/{}", API_URL, self.api_repository_name(repository)?);
+
+        info!("Listing resources in: '{}'", path.to_str().unwrap());
+
+        let mut entries = BTreeMap::new();
+
+        // Maven packages are named 'group:artifact', so every package maps onto a directory path
+        // of group segments followed by the artifact. Only those which may be in or under the
+        // directory are listed.
+        let prefix = package_prefix(segments);
+        let filter = format!("name=\"{}/packages/{}*\"", self.api_repository_name(repository)?, encode(&prefix));
+        let query = match prefix.is_empty() {
+            true => Vec::new(),
+            false => vec![("filter", filter.as_str())]
+        };
+
+        let packages = self.api_list(&format!("{}/packages", repository_url), "packages", &query).await?;
+
+        for package in packages {
+            let name = match string_property(&package, "name") {
+                Some(name) => name,
+                None => continue
+            };
+
+            let package_id = resource_id(&name);
+
+            let package_path = match package_id.split_once(':') {
+                Some((group, artifact)) => group.split('.')
+                    .chain(once(artifact))
+                    .collect::<Vec<&str>>(),
+                None => continue
+            };
+
+            if package_path.len() > segments.len() && package_path.starts_with(segments) {
+                entries.entry(package_path[segments.len()].to_string())
+                    .or_insert_with(|| ResourceEntry {
+                        name: package_path[segments.len()].to_string(),
+                        directory: true,
+                        size: None,
+                        updated: None,
+                    });
+            } else if package_path == segments {
+                // Artifact Registry generates the artifact's metadata on request.
+                entries.insert("maven-metadata.xml".to_string(), ResourceEntry {
+                    name: "maven-metadata.xml".to_string(),
+                    directory: false,
+                    size: None,
+                    updated: string_property(&package, "updateTime"),
+                });
+
+                let versions = self.api_list(
+                    &format!("{}/packages/{}/versions", repository_url, encode(&package_id)),
+                    "versions",
+                    &[],
+                ).await?;
+
+                for version in versions {
+                    let version_id = match string_property(&version, "name") {
+                        Some(name) => resource_id(&name),
+                        None => continue
+                    };
+
+                    entries.insert(version_id.clone(), ResourceEntry {
+                        name: version_id,
+                        directory: true,
+                        size: None,
+                        updated: string_property(&version, "updateTime"),
+                    });
+                }
+            } else if package_path.len() + 1 == segments.len() && segments.starts_with(&package_path) {
+                let owner = format!("{}/versions/{}", name, segments[package_path.len()]);
+
+                let files = self.api_list(
+                    &format!("{}/files", repository_url),
+                    "files",
+                    &[("filter", format!("owner=\"{}\"", owner).as_str())],
+                ).await?;
+
+                for file in files {
+                    let file_name = match string_property(&file, "name") {
+                        Some(name) => resource_id(&name),
+                        None => continue
+                    };
+                    let file_name = file_name.rsplit('/').next().unwrap_or(&file_name).to_string();
+
+                    entries.insert(file_name.clone(), ResourceEntry {
+                        name: file_name,
+                        directory: false,
+                        size: string_property(&file, "sizeBytes").and_then(|size| size.parse().ok()),
+                        updated: string_property(&file, "updateTime"),
+                    });
+                }
+            }
+        }
+
+        Ok(entries.into_values().collect())
+    }
+
+    /// Lists the packages of the repository once, then the versions of each package, rather
+    /// than listing every directory.
+    async fn list_versions(&self, repository: &str) -> Result<BTreeSet<Coordinates>, Box<dyn SerializableError>> {
+        let repository_url = format!("{}/{}", API_URL, self.api_repository_name(repository)?);
+
+        info!("Listing versions in: '{}'", repository);
+
+        let mut coordinates = BTreeSet::new();
+
+        for package in self.api_list(&format!("{}/packages", repository_url), "packages", &[]).await? {
+            let package_id = match string_property(&package, "name") {
+                Some(name) => resource_id(&name),
+                None => continue
+            };
+
+            let (group_id, artifact_id) = match package_id.split_once(':') {
+                Some(ids) => ids,
+                None => continue
+            };
+
+            let versions = self.api_list(
+                &format!("{}/packages/{}/versions", repository_url, encode(&package_id)),
+                "versions",
+                &[],
+            ).await?;
+
+            coordinates.extend(versions.iter()
+                .filter_map(|version| string_property(version, "name"))
+                .map(|name| Coordinates {
+                    group_id: group_id.to_string(),
+                    artifact_id: artifact_id.to_string(),
+                    version: resource_id(&name),
+                }));
+        }
+
+        Ok(coordinates)
+    }
+
+    async fn check(&self) -> Vec<Check> {
+        let token = self.creds.get_key()
+            .await
+            .map(|_| ())
+            .map_err(|err| Box::new(TokenError(err)) as Box<dyn SerializableError>);
+
+        vec![("token", token), ("registry", self.check_registry().await)]
+    }
+
+    async fn refresh_credentials(&self) -> Option<Result<(), Box<dyn SerializableError>>> {
+        Some(self.creds.refresh()
+            .await
+            .map_err(|err| Box::new(TokenError(err)) as Box<dyn SerializableError>))
+    }
 }
 
 
@@ -195,19 +612,32 @@ mod tests {
 
     use crate::err::SerializableError;
     use crate::gcp::gcp_creds::retrieve_creds;
-    use crate::gcp::gcp_resource_access::ArtifactRegistryResourceAccess;
+    use crate::gcp::gcp_resource_access::{ArtifactRegistryResourceAccess, package_prefix};
     use crate::gcp::gcp_resource_access::ArtifactRegistryResourceFetchError::TokenError;
     use crate::resource_access::ResourceAccess;
-    use crate::setup_logging;
+    use crate::logging::{LoggingConfiguration, setup_logging};
+    use crate::upstream::UpstreamConfiguration;
+
+    #[test]
+    fn test_package_prefix() {
+        assert_eq!(package_prefix(&[]), "");
+        assert_eq!(package_prefix(&["com"]), "com");
+        // Either the package 'com:example' or packages under 'com.example'.
+        assert_eq!(package_prefix(&["com", "example"]), "com");
+        // Also the versions of the package 'com:example'.
+        assert_eq!(package_prefix(&["com", "example", "lib"]), "com");
+        assert_eq!(package_prefix(&["com", "example", "lib", "1.0"]), "com.example");
+    }
 
     #[tokio::test]
     async fn test_resource_get() -> Result<(), Box<dyn SerializableError>> {
-        let access = ArtifactRegistryResourceAccess {
-            creds: retrieve_creds().map_err(|err| {
+        let access = ArtifactRegistryResourceAccess::new(
+            retrieve_creds().map_err(|err| {
                 Box::new(TokenError(err)) as Box<dyn SerializableError>
             })?,
-            url: "https://us-central1-maven.pkg.dev/extframework/maven-snapshots".to_string(),
-        };
+            "https://us-central1-maven.pkg.dev/extframework/maven-snapshots".to_string(),
+            UpstreamConfiguration::default(),
+        ).unwrap();
 
         let resource = access.get_resource(
             PathBuf::from("a/b/a/test.txt"),
@@ -222,13 +652,14 @@ mod tests {
 
     #[tokio::test]
     async fn test_resource_put() -> Result<(), Box<dyn SerializableError>> {
-        setup_logging().unwrap();
-        let access = ArtifactRegistryResourceAccess {
-            creds: retrieve_creds().map_err(|err| {
+        setup_logging(&LoggingConfiguration::default()).unwrap();
+        let access = ArtifactRegistryResourceAccess::new(
+            retrieve_creds().map_err(|err| {
                 Box::new(TokenError(err)) as Box<dyn SerializableError>
             })?,
-            url: "https://us-central1-maven.pkg.dev/extframework/maven-snapshots".to_string(),
-        };
+            "https://us-central1-maven.pkg.dev/extframework/maven-snapshots".to_string(),
+            UpstreamConfiguration::default(),
+        ).unwrap();
 
         let buf = PathBuf::from("a/b/a/test.txt");
 
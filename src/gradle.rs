@@ -0,0 +1,218 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use log::warn;
+use rocket::serde::{Deserialize, Serialize};
+
+use crate::checksum::checksums;
+use crate::err::{IOError, SerializableError};
+use crate::resource_access::ResourceAccess;
+
+/// The subset of a Gradle Module Metadata ('.module') file needed to validate the files it
+/// references, see https://github.com/gradle/gradle/blob/master/platforms/documentation/docs/src/docs/design/gradle-module-metadata-latest-specification.md
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct GradleModule {
+    pub format_version: String,
+    #[serde(default)]
+    pub variants: Vec<Variant>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Variant {
+    pub name: String,
+    #[serde(default)]
+    pub files: Vec<VariantFile>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct VariantFile {
+    /// The location of the file, relative to the '.module' file.
+    pub url: String,
+    pub size: Option<u64>,
+    pub sha256: Option<String>,
+    pub sha512: Option<String>,
+}
+
+#[derive(Serialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ModuleReport {
+    pub module: String,
+    pub files_checked: usize,
+    pub mismatches: Vec<ModuleMismatch>,
+    /// Referenced files which aren't deployed (yet), as the module is commonly deployed before
+    /// some of its files.
+    pub not_deployed: Vec<String>,
+}
+
+/// A file referenced by a variant which differs from what is deployed.
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ModuleMismatch {
+    pub variant: String,
+    pub file: String,
+    /// Which property differs, one of 'size', 'sha256' or 'sha512'.
+    pub property: String,
+    pub expected: String,
+    pub actual: String,
+}
+
+/// Whether the path points at a Gradle Module Metadata file.
+pub fn is_gradle_module(path: &Path) -> bool {
+    path.extension()
+        .map(|extension| extension == "module")
+        .unwrap_or(false)
+}
+
+impl GradleModule {
+    pub fn parse(data: &[u8]) -> Result<GradleModule, String> {
+        let module: GradleModule = serde_json::from_slice(data).map_err(|err| err.to_string())?;
+
+        if !module.format_version.starts_with("1.") {
+            return Err(format!("Unsupported format version: '{}'", module.format_version));
+        }
+
+        // Files are looked up next to the module, so urls leaving its directory are refused.
+        let files = module.variants.iter().flat_map(|variant| &variant.files);
+        for file in files {
+            if file.url.is_empty() || file.url == "." || file.url == ".." || file.url.contains(['/', '\\', '\0']) {
+                return Err(format!("Invalid file url: '{}', expected a file name", file.url));
+            }
+        }
+
+        Ok(module)
+    }
+
+    /// Checks the size and checksums of every file referenced by the module's variants against
+    /// the files deployed in `version_directory`. Files not deployed are only listed.
+    pub async fn validate(
+        &self,
+        resource_access: &(dyn ResourceAccess + Send + Sync),
+        module_path: &Path,
+        version_directory: &Path,
+    ) -> Result<ModuleReport, Box<dyn SerializableError>> {
+        let mut report = ModuleReport {
+            module: module_path.to_str().unwrap().to_string(),
+            ..ModuleReport::default()
+        };
+
+        // Variants commonly share files, each is only fetched once.
+        let mut deployed: HashMap<&str, Option<(u64, String, String)>> = HashMap::new();
+
+        for variant in &self.variants {
+            for file in &variant.files {
+                if !deployed.contains_key(file.url.as_str()) {
+                    let path = version_directory.join(&file.url);
+
+                    let digest = if resource_access.exists(path.clone()).await? {
+                        let data = fs::read(resource_access.get_resource(path).await?)
+                            .map_err(|e| Box::new(IOError(e)) as Box<dyn SerializableError>)?;
+                        let [_, _, (_, sha256), (_, sha512)] = checksums(&data);
+
+                        Some((data.len() as u64, sha256, sha512))
+                    } else {
+                        None
+                    };
+
+                    match digest {
+                        Some(_) => report.files_checked += 1,
+                        None => report.not_deployed.push(file.url.clone()),
+                    }
+                    deployed.insert(&file.url, digest);
+                }
+
+                if let Some(digest) = &deployed[file.url.as_str()] {
+                    report.mismatches.extend(compare(variant, file, digest));
+                }
+            }
+        }
+
+        for mismatch in &report.mismatches {
+            warn!(
+                "Module: '{}' variant: '{}' expects {} of: '{}' to be '{}', found: '{}'",
+                report.module,
+                mismatch.variant,
+                mismatch.property,
+                mismatch.file,
+                mismatch.expected,
+                mismatch.actual
+            );
+        }
+
+        Ok(report)
+    }
+}
+
+fn compare(variant: &Variant, file: &VariantFile, (size, sha256, sha512): &(u64, String, String)) -> Vec<ModuleMismatch> {
+    let mismatch = |property: &str, expected: String, actual: String| ModuleMismatch {
+        variant: variant.name.clone(),
+        file: file.url.clone(),
+        property: property.to_string(),
+        expected,
+        actual,
+    };
+
+    let mut mismatches = Vec::new();
+
+    if let Some(expected) = file.size.filter(|expected| expected != size) {
+        mismatches.push(mismatch("size", expected.to_string(), size.to_string()));
+    }
+
+    for (property, expected, actual) in [("sha256", &file.sha256, sha256), ("sha512", &file.sha512, sha512)] {
+        if let Some(expected) = expected.as_ref().filter(|expected| !expected.eq_ignore_ascii_case(actual)) {
+            mismatches.push(mismatch(property, expected.clone(), actual.clone()));
+        }
+    }
+
+    mismatches
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::checksum::checksums;
+    use crate::gradle::GradleModule;
+    use crate::resource_access::memory::MemoryResourceAccess;
+
+    #[tokio::test]
+    async fn test_validate_module() {
+        let access = MemoryResourceAccess::default();
+        access.insert("rel/com/example/lib/1.0/lib-1.0.jar", "jar");
+        access.insert("rel/com/example/lib/1.0/lib-1.0-sources.jar", "changed");
+
+        let [_, _, (_, jar_sha256), _] = checksums(b"jar");
+
+        let module = GradleModule::parse(format!(r#"{{
+            "formatVersion": "1.1",
+            "component": {{ "group": "com.example", "module": "lib", "version": "1.0" }},
+            "variants": [
+                {{ "name": "apiElements", "files": [{{ "name": "lib-1.0.jar", "url": "lib-1.0.jar", "size": 3, "sha256": "{0}" }}] }},
+                {{ "name": "runtimeElements", "files": [{{ "name": "lib-1.0.jar", "url": "lib-1.0.jar", "size": 3, "sha256": "{0}" }}] }},
+                {{ "name": "sourcesElements", "files": [{{ "name": "lib-1.0-sources.jar", "url": "lib-1.0-sources.jar", "size": 7, "sha256": "{0}" }}] }},
+                {{ "name": "javadocElements", "files": [{{ "name": "lib-1.0-javadoc.jar", "url": "lib-1.0-javadoc.jar", "size": 1 }}] }}
+            ]
+        }}"#, jar_sha256).as_bytes()).unwrap();
+
+        let report = module.validate(
+            &access,
+            Path::new("com/example/lib/1.0/lib-1.0.module"),
+            Path::new("rel/com/example/lib/1.0"),
+        ).await.unwrap();
+
+        assert_eq!(report.files_checked, 2);
+        assert_eq!(report.mismatches.len(), 1);
+        assert_eq!(report.mismatches[0].property, "sha256");
+        assert_eq!(report.mismatches[0].file, "lib-1.0-sources.jar");
+        assert_eq!(report.not_deployed, vec!["lib-1.0-javadoc.jar"]);
+
+        assert!(GradleModule::parse(b"{ \"formatVersion\": \"1.1\", \"variants\": 1 }").is_err());
+        assert!(GradleModule::parse(b"{ \"formatVersion\": \"2.0\" }").is_err());
+
+        for url in ["../other/lib-1.0.jar", "/etc/passwd", "..", ""] {
+            let module = format!(r#"{{ "formatVersion": "1.1", "variants": [{{ "name": "api", "files": [{{ "url": "{}" }}] }}] }}"#, url);
+            assert!(GradleModule::parse(module.as_bytes()).is_err(), "{}", url);
+        }
+    }
+}
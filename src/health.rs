@@ -0,0 +1,142 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use rocket::{get, State};
+use rocket::http::Status;
+use rocket::response::status;
+use rocket::serde::json::Json;
+use rocket::serde::Serialize;
+use tempfile::NamedTempFile;
+
+use crate::ARProxyConfiguration;
+use crate::err::{CheckTimedOut, IOError, SerializableError};
+use crate::reload::{Backend, LiveConfiguration};
+
+/// How long a single check may take before it is failed.
+const CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CheckResult {
+    /// Either 'ok' or 'failed'.
+    pub status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub duration_ms: u64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Health {
+    /// Either 'ok' or 'unavailable'.
+    pub status: &'static str,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub checks: BTreeMap<String, CheckResult>,
+}
+
+/// Checks a file can be created in the directory, creating the directory if it doesn't exist.
+pub fn check_writable(directory: &Path) -> Result<(), Box<dyn SerializableError>> {
+    fs::create_dir_all(directory)
+        .and_then(|_| NamedTempFile::new_in(directory))
+        .map(|_| ())
+        .map_err(|err| Box::new(IOError(err)) as Box<dyn SerializableError>)
+}
+
+/// Checks every backend's dependencies and that the cache directory (if any) is writable. Checks
+/// are named by where they are configured, eg. `backends.<NAME>.token` or `cache`.
+pub async fn readiness(backends: Vec<(String, Backend)>, cache_directory: Option<&Path>) -> Health {
+    let mut checks = BTreeMap::new();
+
+    for (name, backend) in backends {
+        let start = Instant::now();
+
+        match tokio::time::timeout(CHECK_TIMEOUT, backend.check()).await {
+            Ok(results) => for (dependency, result) in results {
+                checks.insert(format!("backends.{}.{}", name, dependency), check_result(start, result));
+            },
+            Err(_) => {
+                checks.insert(format!("backends.{}", name), check_result(start, Err(Box::new(CheckTimedOut(CHECK_TIMEOUT.as_secs())))));
+            }
+        }
+    }
+
+    if let Some(directory) = cache_directory {
+        let start = Instant::now();
+        checks.insert("cache".to_string(), check_result(start, check_writable(directory)));
+    }
+
+    Health {
+        status: match checks.values().all(|check| check.status == "ok") {
+            true => "ok",
+            false => "unavailable"
+        },
+        checks,
+    }
+}
+
+pub fn check_result(start: Instant, result: Result<(), Box<dyn SerializableError>>) -> CheckResult {
+    CheckResult {
+        status: if result.is_ok() { "ok" } else { "failed" },
+        error: result.err().map(|err| format!("{}: {}", err.name(), err.message())),
+        duration_ms: start.elapsed().as_millis() as u64,
+    }
+}
+
+/// Liveness, responds as long as the process is serving requests.
+#[get("/healthz")]
+pub async fn healthz() -> Json<Health> {
+    Json(Health {
+        status: "ok",
+        checks: BTreeMap::new(),
+    })
+}
+
+/// Readiness, responds with `503 Service Unavailable` when any check fails.
+#[get("/readyz")]
+pub async fn readyz(
+    live: &State<Arc<LiveConfiguration>>,
+    configuration: &ARProxyConfiguration,
+) -> status::Custom<Json<Health>> {
+    let health = readiness(live.backends(), configuration.cache_directory.as_deref()).await;
+
+    let status = match health.status {
+        "ok" => Status::Ok,
+        _ => Status::ServiceUnavailable
+    };
+
+    status::Custom(status, Json(health))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::sync::Arc;
+
+    use tempfile::tempdir;
+
+    use crate::filesystem::FilesystemResourceAccess;
+    use crate::health::readiness;
+
+    #[tokio::test]
+    async fn test_readiness() {
+        let directory = tempdir().unwrap();
+        let backends = vec![("local".to_string(), Arc::new(FilesystemResourceAccess {
+            directory: directory.path().join("repo"),
+        }) as _)];
+
+        let health = readiness(backends.clone(), Some(&directory.path().join("cache"))).await;
+        assert_eq!(health.status, "ok");
+        assert_eq!(health.checks.keys().collect::<Vec<&String>>(), vec!["backends.local.directory", "cache"]);
+
+        // A cache "directory" which is a file can't be written to.
+        fs::write(directory.path().join("file"), "").unwrap();
+
+        let health = readiness(backends, Some(&directory.path().join("file"))).await;
+        assert_eq!(health.status, "unavailable");
+        assert_eq!(health.checks["backends.local.directory"].status, "ok");
+        assert_eq!(health.checks["cache"].status, "failed");
+        assert!(health.checks["cache"].error.is_some());
+    }
+}
@@ -0,0 +1,144 @@
+use std::collections::{BTreeSet, HashMap};
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use log::{info, warn};
+use rocket::serde::Serialize;
+
+use crate::maven::Coordinates;
+use crate::reload::LiveConfiguration;
+use crate::resource_access::ResourceAccess;
+
+/// The most results a single search will return.
+const MAX_RESULTS: usize = 200;
+
+#[derive(Serialize, Debug, Clone)]
+pub struct IndexedArtifact {
+    pub repository: String,
+    #[serde(flatten)]
+    pub coordinates: Coordinates,
+}
+
+/// An in memory index of every artifact version in the configured repositories, keyed by the
+/// public repository name.
+#[derive(Default)]
+pub struct SearchIndex {
+    repositories: RwLock<HashMap<String, BTreeSet<Coordinates>>>,
+}
+
+impl SearchIndex {
+    pub fn record(&self, repository: &str, coordinates: Coordinates) {
+        self.repositories.write().unwrap()
+            .entry(repository.to_string())
+            .or_default()
+            .insert(coordinates);
+    }
+
+    /// Removes every version at or below the given (repository relative) path.
+    pub fn forget(&self, repository: &str, path: &Path) {
+        if let Some(versions) = self.repositories.write().unwrap().get_mut(repository) {
+            versions.retain(|coordinates| !coordinates.version_path().starts_with(path));
+        }
+    }
+
+    /// Drops the versions of every repository not named.
+    pub fn retain(&self, repositories: &[&String]) {
+        self.repositories.write().unwrap()
+            .retain(|repository, _| repositories.contains(&repository));
+    }
+
+    pub fn replace(&self, repository: &str, versions: BTreeSet<Coordinates>) {
+        self.repositories.write().unwrap()
+            .insert(repository.to_string(), versions);
+    }
+
+    /// Finds every version whose 'group:artifact:version' contains all fragments of the query.
+    pub fn search(&self, query: &str) -> Vec<IndexedArtifact> {
+        let fragments = query.split(|c: char| c.is_whitespace() || c == ':')
+            .filter(|fragment| !fragment.is_empty())
+            .map(|fragment| fragment.to_lowercase())
+            .collect::<Vec<String>>();
+
+        let repositories = self.repositories.read().unwrap();
+
+        let mut results = repositories.iter()
+            .flat_map(|(repository, versions)| versions.iter().map(move |coordinates| (repository, coordinates)))
+            .filter(|(_, coordinates)| {
+                let gav = format!(
+                    "{}:{}:{}",
+                    coordinates.group_id,
+                    coordinates.artifact_id,
+                    coordinates.version
+                ).to_lowercase();
+
+                fragments.iter().all(|fragment| gav.contains(fragment))
+            })
+            .map(|(repository, coordinates)| IndexedArtifact {
+                repository: repository.clone(),
+                coordinates: coordinates.clone(),
+            })
+            .collect::<Vec<IndexedArtifact>>();
+
+        results.sort_by(|first, second| first.repository.cmp(&second.repository)
+            .then_with(|| first.coordinates.cmp(&second.coordinates)));
+        results.truncate(MAX_RESULTS);
+
+        results
+    }
+}
+
+/// Rebuilds the index of every repository from the backend's listing, once every interval.
+pub async fn refresh_periodically(
+    index: Arc<SearchIndex>,
+    resource_access: Arc<dyn ResourceAccess + Send + Sync>,
+    configuration: Arc<LiveConfiguration>,
+    interval: Duration,
+) {
+    loop {
+        // Read every time, so reloaded repositories are picked up.
+        let configuration = configuration.load();
+        let mut repositories = configuration.repositories.iter()
+            .map(|(name, repository)| (name, &repository.id))
+            .collect::<Vec<(&String, &String)>>();
+        repositories.sort();
+
+        index.retain(&repositories.iter().map(|(name, _)| *name).collect::<Vec<&String>>());
+
+        for (name, id) in repositories {
+            info!("Indexing repository: '{}'", name);
+
+            match resource_access.list_versions(id).await {
+                Ok(versions) => index.replace(name, versions),
+                Err(err) => warn!("Failed to index repository: '{}', {}", name, err.message())
+            }
+        }
+
+        tokio::time::sleep(interval).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::index::SearchIndex;
+    use crate::maven::Coordinates;
+
+    #[test]
+    fn test_search() {
+        let index = SearchIndex::default();
+
+        index.record("releases", Coordinates::from_version_path(Path::new("com/example/lib/1.0")).unwrap());
+        index.record("releases", Coordinates::from_version_path(Path::new("com/example/tool/1.0")).unwrap());
+        index.record("snapshots", Coordinates::from_version_path(Path::new("com/example/lib/1.1-SNAPSHOT")).unwrap());
+
+        assert_eq!(index.search("example:lib").len(), 2);
+        assert_eq!(index.search("TOOL").len(), 1);
+        assert_eq!(index.search("lib 1.1").len(), 1);
+        assert_eq!(index.search("other").len(), 0);
+
+        index.forget("releases", Path::new("com/example/lib"));
+        assert_eq!(index.search("lib").len(), 1);
+    }
+}
@@ -0,0 +1,4 @@
+//! The parts of ARP which don't depend on its configuration, shared with the benchmarks.
+
+pub mod trace;
+pub mod upstream;
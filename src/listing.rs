@@ -0,0 +1,46 @@
+use std::path::{Path, PathBuf};
+
+use crate::resource_access::ResourceEntry;
+
+/// Renders a simple browsable HTML page for the entries of a repository directory.
+pub fn render_listing(repository: &str, path: &Path, entries: &[ResourceEntry]) -> String {
+    let directory = PathBuf::from(repository).join(path);
+    let directory = directory.to_str().unwrap().trim_end_matches('/');
+
+    let mut rows = String::new();
+
+    if !path.as_os_str().is_empty() {
+        rows.push_str("<tr><td><a href=\"../\">../</a></td><td></td><td></td></tr>\n");
+    }
+
+    for entry in entries {
+        let name = if entry.directory {
+            format!("{}/", entry.name)
+        } else {
+            entry.name.clone()
+        };
+
+        rows.push_str(format!(
+            "<tr><td><a href=\"/{}/{}\">{}</a></td><td>{}</td><td>{}</td></tr>\n",
+            escape(directory),
+            escape(&name),
+            escape(&name),
+            entry.updated.as_deref().map(escape).unwrap_or_default(),
+            entry.size.map(|size| size.to_string()).unwrap_or_default(),
+        ).as_str());
+    }
+
+    format!(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n<title>/{0}/</title>\n</head>\n<body>\n<h1>/{0}/</h1>\n<table>\n<tr><th>Name</th><th>Last modified</th><th>Size</th></tr>\n{1}</table>\n</body>\n</html>\n",
+        escape(directory),
+        rows
+    )
+}
+
+pub(crate) fn escape(value: &str) -> String {
+    value.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
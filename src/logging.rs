@@ -0,0 +1,264 @@
+use std::fmt::Arguments;
+use std::path::PathBuf;
+use std::time::Instant;
+
+use chrono::{SecondsFormat, Utc};
+use log::{info, Level, LevelFilter, Record};
+use log::kv::{Error, Key, Value, VisitSource};
+use opentelemetry::trace::TraceContextExt;
+use rocket::{async_trait, Data, Request, Response};
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::serde::{Deserialize, Serialize};
+use serde_json::Map;
+
+use crate::ARProxyConfiguration;
+use crate::auth::ApiCredentials;
+use crate::trace::request_context;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(try_from = "String", rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Human readable lines.
+    Text,
+    /// One JSON object per line, in the structure Cloud Logging parses.
+    Json,
+}
+
+impl TryFrom<String> for LogFormat {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match value.as_str() {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format!("Invalid log format: '{}', expected one of: 'text' or 'json'", value))
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoggingConfiguration {
+    pub level: LevelFilter,
+    pub format: LogFormat,
+    /// A file every line is also written to.
+    pub file: Option<PathBuf>,
+    /// The GCP project traces are reported to, used to link log entries to their trace.
+    pub project: Option<String>,
+}
+
+impl Default for LoggingConfiguration {
+    fn default() -> Self {
+        LoggingConfiguration {
+            level: LevelFilter::Info,
+            format: LogFormat::Text,
+            file: None,
+            project: None,
+        }
+    }
+}
+
+pub(crate) fn setup_logging(configuration: &LoggingConfiguration) -> Result<(), fern::InitError> {
+    let mut dispatch = fern::Dispatch::new()
+        .level(configuration.level)
+        .chain(std::io::stdout());
+
+    dispatch = match configuration.format {
+        LogFormat::Text => dispatch.format(|out, message, record| {
+            out.finish(format_args!(
+                "{} [{}] [{}] {}",
+                chrono::Local::now().format("[%m-%d][%H:%M:%S]"),
+                record.target(),
+                record.level(),
+                message
+            ))
+        }),
+        LogFormat::Json => {
+            let project = configuration.project.clone();
+
+            dispatch.format(move |out, message, record| {
+                out.finish(format_args!("{}", json_entry(message, record, project.as_deref())))
+            })
+        }
+    };
+
+    if let Some(file) = &configuration.file {
+        dispatch = dispatch.chain(fern::log_file(file)?);
+    }
+
+    dispatch.apply()?;
+    Ok(())
+}
+
+fn severity(level: Level) -> &'static str {
+    match level {
+        Level::Error => "ERROR",
+        Level::Warn => "WARNING",
+        Level::Info => "INFO",
+        Level::Debug | Level::Trace => "DEBUG",
+    }
+}
+
+/// Collects the structured fields of a record.
+struct Fields<'a> {
+    entry: &'a mut Map<String, serde_json::Value>,
+    project: Option<&'a str>,
+}
+
+impl<'kvs> VisitSource<'kvs> for Fields<'_> {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), Error> {
+        let value = serde_json::to_value(value).map_err(Error::boxed)?;
+
+        // Cloud Logging links entries to traces with these special fields.
+        let key = match (key.as_str(), self.project, &value) {
+            ("trace" | "span_id", _, serde_json::Value::String(id)) if id.is_empty() => return Ok(()),
+            ("trace", Some(project), serde_json::Value::String(trace)) => {
+                self.entry.insert(
+                    "logging.googleapis.com/trace".to_string(),
+                    format!("projects/{}/traces/{}", project, trace).into(),
+                );
+                return Ok(());
+            }
+            ("span_id", _, _) => "logging.googleapis.com/spanId".to_string(),
+            (key, _, _) => key.to_string()
+        };
+
+        self.entry.insert(key, value);
+        Ok(())
+    }
+}
+
+fn json_entry(message: &Arguments, record: &Record, project: Option<&str>) -> String {
+    let mut entry = Map::new();
+
+    entry.insert("severity".to_string(), severity(record.level()).into());
+    entry.insert("message".to_string(), message.to_string().into());
+    entry.insert("time".to_string(), Utc::now().to_rfc3339_opts(SecondsFormat::Micros, true).into());
+    entry.insert("target".to_string(), record.target().into());
+
+    // A field that fails to serialize is left out rather than losing the whole entry.
+    let _ = record.key_values().visit(&mut Fields { entry: &mut entry, project });
+
+    serde_json::Value::Object(entry).to_string()
+}
+
+/// The `httpRequest` of a Cloud Logging entry.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct HttpRequest {
+    request_method: String,
+    request_url: String,
+    status: u16,
+    /// Cloud Logging expects sizes as strings.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_size: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    user_agent: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    remote_ip: Option<String>,
+    latency: String,
+}
+
+/// When the request was received.
+struct Received(Instant);
+
+/// The trace (and span) id of a request, given by its span or by Cloud Run's
+/// `X-Cloud-Trace-Context: TRACE_ID/SPAN_ID;o=1` header.
+fn trace_ids(request: &Request<'_>) -> Option<(String, String)> {
+    let span_context = request_context(request).span().span_context().clone();
+
+    if span_context.is_valid() {
+        return Some((span_context.trace_id().to_string(), span_context.span_id().to_string()));
+    }
+
+    let (trace, span) = request.headers()
+        .get_one("X-Cloud-Trace-Context")?
+        .split_once('/')?;
+
+    Some((trace.to_string(), span.split(';').next().unwrap_or_default().to_string()))
+}
+
+/// Logs every request once it is responded to.
+pub struct AccessLog;
+
+#[async_trait]
+impl Fairing for AccessLog {
+    fn info(&self) -> Info {
+        Info {
+            name: "Access log",
+            kind: Kind::Request | Kind::Response,
+        }
+    }
+
+    async fn on_request(&self, request: &mut Request<'_>, _: &mut Data<'_>) {
+        request.local_cache(|| Received(Instant::now()));
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let latency = request.local_cache(|| Received(Instant::now())).0.elapsed();
+
+        let repository = match (request.guard::<&ARProxyConfiguration>().await.succeeded(), request.routed_segment(0)) {
+            (Some(configuration), Some(segment)) if configuration.repositories.contains_key(segment) => segment,
+            _ => ""
+        };
+        let path = request.routed_segments(1..).collect::<Vec<&str>>().join("/");
+        let principal = request.guard::<&ApiCredentials>().await
+            .succeeded()
+            .map(|creds| creds.user.as_str())
+            .unwrap_or_default();
+        let (trace, span_id) = trace_ids(request).unwrap_or_default();
+
+        let http_request = HttpRequest {
+            request_method: request.method().to_string(),
+            request_url: request.uri().to_string(),
+            status: response.status().code,
+            response_size: response.body_mut().size().await.map(|size| size.to_string()),
+            user_agent: request.headers().get_one("User-Agent").map(str::to_string),
+            remote_ip: request.client_ip().map(|ip| ip.to_string()),
+            latency: format!("{:.6}s", latency.as_secs_f64()),
+        };
+
+        info!(
+            target: "access",
+            httpRequest:serde = http_request,
+            repository = repository,
+            path = path,
+            principal = principal,
+            trace = trace,
+            span_id = span_id;
+            "{} {} {} {}ms",
+            request.method(),
+            request.uri(),
+            response.status().code,
+            latency.as_millis()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use log::{Level, Record};
+    use serde_json::Value;
+
+    use crate::logging::json_entry;
+
+    #[test]
+    fn test_json_entry() {
+        let fields = [("trace", "4bf92f3577b34da6a3ce929d0e0e4736"), ("repository", "releases")];
+
+        let entry = json_entry(
+            &format_args!("GET /releases/ 200"),
+            &Record::builder()
+                .level(Level::Warn)
+                .target("access")
+                .key_values(&fields)
+                .build(),
+            Some("my-project"),
+        );
+        let entry: Value = serde_json::from_str(&entry).unwrap();
+
+        assert_eq!(entry["severity"], "WARNING");
+        assert_eq!(entry["message"], "GET /releases/ 200");
+        assert_eq!(entry["repository"], "releases");
+        assert_eq!(entry["logging.googleapis.com/trace"], "projects/my-project/traces/4bf92f3577b34da6a3ce929d0e0e4736");
+    }
+}
@@ -2,117 +2,299 @@ extern crate core;
 
 use std::collections::HashMap;
 use std::env;
-use std::env::VarError;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 use std::sync::Arc;
+use std::time::Duration;
 use dotenv::dotenv;
 
-use rocket::{launch, routes, State};
+use rocket::{Build, Rocket, routes, State};
+use rocket::fairing::AdHoc;
 
+use artifact_registry_proxy::{trace, upstream};
+
+use crate::api::admin::{cache_stats, effective_configuration, list_repositories, list_tokens, purge_cache, refresh_tokens, revoke_token};
+use crate::api::audit::{export_audit_log, query_audit_log, verify_audit_log};
+use crate::api::latest::latest;
+use crate::api::promote::promote_version;
+use crate::api::retention::run_retention;
+use crate::api::search::search;
+use crate::audit::AuditLog;
 use crate::auth::ApiCredentials;
-use crate::gcp::gcp_creds;
-use crate::gcp::gcp_resource_access::{ArtifactRegistryResourceAccess, ArtifactRegistryResourceFetchError};
+use crate::cache::{CachingResourceAccess, ResourceCache};
+use crate::coalesce::CoalescingResourceAccess;
+use crate::config::{BackendConfiguration, ConfigError, setup_configuration};
+use crate::health::{healthz, readyz};
+use crate::logging::{AccessLog, LoggingConfiguration, setup_logging};
+use crate::index::{refresh_periodically, SearchIndex};
+use crate::maven::pom::PomValidation;
+use crate::policy::DeployPolicy;
+use crate::prometheus::{export_metrics, RequestMetrics};
+use crate::trace::{RequestTracing, traced};
+use crate::ui::{ui_browse, ui_repositories};
+use crate::upstream::UpstreamConfiguration;
+use crate::reload::{LiveConfiguration, reload_on_hangup, watch_file};
 use crate::resource_access::ResourceAccess;
-use crate::routes::{authenticated, get_repository_resource, home, put_repository_resource, un_authenticated};
+use crate::retention::{apply_periodically, RetentionPolicy};
+use crate::routes::{authenticated, delete_repository_resource, get_repository_resource, home, put_repository_resource, un_authenticated};
+use crate::signature::{HeldUploads, Keyring, Signer};
 
 mod resource_access;
 mod gcp;
 pub mod err;
 mod routes;
 pub mod auth;
+mod policy;
+mod maven;
+mod cache;
+mod coalesce;
+mod listing;
+mod index;
+mod api;
+mod retention;
+mod checksum;
+mod promote;
+mod gradle;
+mod signature;
+mod config;
+mod filesystem;
+mod reload;
+mod prometheus;
+mod logging;
+mod audit;
+mod breaker;
+mod health;
+mod ui;
 
 pub type ManagedResourceAccess = State<Arc<dyn ResourceAccess + Send + Sync>>;
 
 
 struct ARProxyConfiguration {
-    repositories: HashMap<String, String>,
-    url: String,
-    creds: ApiCredentials,
+    repositories: HashMap<String, RepositoryConfiguration>,
+    backends: HashMap<String, BackendConfiguration>,
+    creds: Vec<ApiCredentials>,
+    cache_directory: Option<PathBuf>,
+    cache_mutable_ttl: Duration,
+    search_index_interval: Duration,
+    retention_dry_run: bool,
+    retention_interval: Duration,
+    /// How often the configuration file is checked for changes.
+    watch_interval: Duration,
+    /// The OTLP collector spans are exported to.
+    tracing_endpoint: Option<String>,
+    logging: LoggingConfiguration,
+    /// The file write operations are recorded to.
+    audit_file: Option<PathBuf>,
+    /// Where uploads waiting for their signature are kept.
+    hold_directory: PathBuf,
+    /// Bytes of uploads each principal may have waiting for their signature.
+    hold_max_size: u64,
+    /// How requests to backends are timed out and retried.
+    upstream: UpstreamConfiguration,
 }
 
-fn setup_configuration<'a>() -> ARProxyConfiguration {
-    let url = env::var("GAR_API_URL").expect(
-        "Cannot find the Google Artifact registry API URL (specified by the environmental variable: 'GAR_API_URL')"
-    ).to_string();
-
-    let repository_string = env::var("REPOSITORIES").expect(
-        "Cannot find repository configuration in the environmental variables (formatted: 'public_name:gar_id,...') (specified by environmental variable: 'REPOSITORIES')"
-    ).to_string();
-
-    let mut repositories = HashMap::new();
-
-    repository_string.split(",")
-        .filter(|str| !str.is_empty())
-        .map(|str| str.split(":"))
-        .map(|split| split.collect::<Vec<&str>>())
-        .for_each(|str| {
-            repositories.insert(
-                str.get(0).expect("Key expected for repositories!").to_string(),
-                str.get(1).expect("Value expected for repositories!").to_string(),
-            );
-        });
-
-    let binding = env::var("CREDENTIALS")
-        .or_else(|_| Ok::<String, VarError>(":".to_string()));
-    let (api_user, api_key) =
-        binding
-            .as_ref()
-            .map(|t| t.split_once(":").expect("Invalid CREDENTIALS env specified, should be a string seperated by ':' (user:key).")).unwrap();
+struct RepositoryConfiguration {
+    id: String,
+    /// The name of the backend holding the repository.
+    backend: String,
+    deploy_policy: DeployPolicy,
+    retention: Option<RetentionPolicy>,
+    pom_validation: PomValidation,
+    /// When set, every artifact deployed must be signed by one of the keyring's keys.
+    keyring: Option<Keyring>,
+    /// When set, every artifact deployed is signed by ARP with this key.
+    signer: Option<Arc<Signer>>,
+}
 
-    let creds = ApiCredentials {
-        user: api_user.to_string(),
-        key: api_key.to_string(),
-    };
+fn configuration_path() -> Option<PathBuf> {
+    env::var("CONFIG_FILE").ok().map(PathBuf::from)
+}
 
-    ARProxyConfiguration {
-        repositories,
-        url,
-        creds,
-    }
+fn report(errors: &[ConfigError]) -> String {
+    errors.iter()
+        .map(|err| format!("  {}", err))
+        .collect::<Vec<String>>()
+        .join("\n")
 }
 
-pub(crate) fn setup_logging() -> Result<(), fern::InitError> {
-    fern::Dispatch::new()
-        .format(|out, message, record| {
-            out.finish(format_args!(
-                "{} [{}] [{}] {}",
-                chrono::Local::now().format("[%m-%d][%H:%M:%S]"),
-                record.target(),
-                record.level(),
-                message
-            ))
-        })
-        .level(log::LevelFilter::Info)
-        .chain(std::io::stdout())
-        .chain(fern::log_file("output.log")?)
-        .apply()?;
-    Ok(())
+/// Validates the configuration without connecting to any backend, printing every problem found.
+fn check_config(path: Option<&Path>) -> ExitCode {
+    match setup_configuration(path) {
+        Ok(configuration) => {
+            println!(
+                "Configuration is valid: {} backend(s), {} repositories, {} principal(s)",
+                configuration.backends.len(),
+                configuration.repositories.len(),
+                configuration.creds.len()
+            );
+            ExitCode::SUCCESS
+        }
+        Err(errors) => {
+            eprintln!("Found {} configuration problem(s):\n{}", errors.len(), report(&errors));
+            ExitCode::FAILURE
+        }
+    }
 }
 
-#[launch]
-fn launch() -> _ {
+#[rocket::main]
+async fn main() -> ExitCode {
     #[cfg(debug_assertions)]
     {
         dotenv().unwrap();
     }
-    setup_logging().expect("Failed to init fern logging.");
 
-    let configuration = setup_configuration();
+    let args = env::args().skip(1).collect::<Vec<String>>();
 
-    rocket::build()
-        .manage(Arc::new(
-            ArtifactRegistryResourceAccess {
-                creds: gcp_creds().map_err(|err| {
-                    ArtifactRegistryResourceFetchError::TokenError(err)
-                }).unwrap(),
-                url: configuration.url.clone(),
+    match args.first().map(String::as_str) {
+        Some("check-config") => return check_config(args.get(1).map(PathBuf::from).or_else(configuration_path).as_deref()),
+        Some(command) => {
+            eprintln!("Unknown command: '{}', expected: 'check-config [PATH]'", command);
+            return ExitCode::FAILURE;
+        }
+        None => {}
+    }
+
+    let configuration = setup_configuration(configuration_path().as_deref())
+        .unwrap_or_else(|errors| panic!("Invalid configuration:\n{}", report(&errors)));
+
+    setup_logging(&configuration.logging).expect("Failed to init fern logging.");
+
+    match rocket(configuration, configuration_path()).launch().await {
+        Ok(_) => ExitCode::SUCCESS,
+        Err(err) => {
+            log::error!("Failed to launch, {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn rocket(configuration: ARProxyConfiguration, path: Option<PathBuf>) -> Rocket<Build> {
+    let watch_interval = configuration.watch_interval;
+    let live = Arc::new(LiveConfiguration::new(configuration, path.clone())
+        .unwrap_or_else(|errors| panic!("Invalid configuration:\n{}", report(&errors))));
+    let configuration = live.load();
+
+    let mut resource_access = live.routing() as Arc<dyn ResourceAccess + Send + Sync>;
+
+    let cache = configuration.cache_directory.as_ref()
+        .map(|directory| Arc::new(ResourceCache::new(directory.clone(), configuration.cache_mutable_ttl)));
+
+    if let Some(cache) = &cache {
+        resource_access = Arc::new(CachingResourceAccess {
+            inner: resource_access,
+            cache: Arc::clone(cache),
+        });
+    }
+
+    resource_access = Arc::new(CoalescingResourceAccess::new(resource_access));
+
+    let index = Arc::new(SearchIndex::default());
+
+    let audit = match &configuration.audit_file {
+        Some(file) => AuditLog::open(file)
+            .unwrap_or_else(|err| panic!("Failed to open the audit log: '{}', {}", file.to_str().unwrap(), err)),
+        None => AuditLog::disabled()
+    };
+
+    let held = HeldUploads::open(&configuration.hold_directory, configuration.hold_max_size)
+        .unwrap_or_else(|err| panic!("Failed to hold uploads in: '{}', {}", configuration.hold_directory.to_str().unwrap(), err));
+
+    let indexer = {
+        let index = Arc::clone(&index);
+        let resource_access = Arc::clone(&resource_access);
+        let live = Arc::clone(&live);
+        let interval = configuration.search_index_interval;
+
+        AdHoc::on_liftoff("Search indexer", move |_| Box::pin(async move {
+            if !interval.is_zero() {
+                tokio::spawn(refresh_periodically(index, resource_access, live, interval));
+            }
+        }))
+    };
+
+    let retention_job = {
+        let resource_access = Arc::clone(&resource_access);
+        let index = Arc::clone(&index);
+        let audit = audit.clone();
+        let live = Arc::clone(&live);
+        let dry_run = configuration.retention_dry_run;
+        let interval = configuration.retention_interval;
+
+        AdHoc::on_liftoff("Snapshot retention", move |_| Box::pin(async move {
+            if !interval.is_zero() {
+                tokio::spawn(apply_periodically(resource_access, index, audit, live, dry_run, interval));
             }
-        ) as Arc<dyn ResourceAccess + Send + Sync>)
-        .manage(configuration)
-        .mount("/", routes![
+        }))
+    };
+
+    let reloader = {
+        let live = Arc::clone(&live);
+
+        AdHoc::on_liftoff("Configuration reload", move |_| Box::pin(async move {
+            tokio::spawn(reload_on_hangup(Arc::clone(&live)));
+
+            if let Some(path) = path.filter(|_| !watch_interval.is_zero()) {
+                tokio::spawn(watch_file(live, path, watch_interval));
+            }
+        }))
+    };
+
+    let trace_export = {
+        let provider = configuration.tracing_endpoint.as_deref()
+            .map(|endpoint| trace::install(endpoint).unwrap_or_else(|err| panic!("Failed to set up tracing, {}", err)));
+
+        AdHoc::on_shutdown("Trace export", move |_| Box::pin(async move {
+            if let Some(provider) = provider {
+                // Exports the spans still buffered.
+                if let Ok(Err(err)) = tokio::task::spawn_blocking(move || provider.shutdown()).await {
+                    log::warn!("Failed to export the remaining spans, {}", err);
+                }
+            }
+        }))
+    };
+
+    rocket::build()
+        .manage(resource_access)
+        .manage(index)
+        .manage(live)
+        .manage(audit)
+        .manage(cache)
+        .manage(held)
+        .manage(prometheus::install())
+        .attach(indexer)
+        .attach(retention_job)
+        .attach(reloader)
+        .attach(RequestMetrics)
+        .attach(RequestTracing)
+        .attach(AccessLog)
+        .attach(trace_export)
+        .mount("/", traced(routes![
             get_repository_resource,
             put_repository_resource,
+            delete_repository_resource,
             home,
             un_authenticated,
-            authenticated
-        ])
+            authenticated,
+            export_metrics,
+            healthz,
+            readyz,
+            ui_repositories,
+            ui_browse
+        ]))
+        .mount("/api", traced(routes![
+            search,
+            latest,
+            run_retention,
+            promote_version,
+            query_audit_log,
+            export_audit_log,
+            verify_audit_log,
+            list_repositories,
+            cache_stats,
+            purge_cache,
+            refresh_tokens,
+            list_tokens,
+            revoke_token,
+            effective_configuration
+        ]))
 }
\ No newline at end of file
@@ -0,0 +1,251 @@
+use std::fs;
+use std::path::PathBuf;
+
+use roxmltree::{Document, Node};
+
+use crate::err::{IOError, MalformedMetadata, SerializableError};
+use crate::resource_access::ResourceAccess;
+
+/// The parts of a 'maven-metadata.xml' file used to resolve versions. Artifact level metadata
+/// lists the versions of an artifact, and snapshot version level metadata lists the timestamped
+/// files of the snapshot.
+#[derive(Debug, Default, PartialEq)]
+pub struct MavenMetadata {
+    pub latest: Option<String>,
+    pub release: Option<String>,
+    pub versions: Vec<String>,
+    pub last_updated: Option<String>,
+    pub snapshot: Option<Snapshot>,
+    pub snapshot_versions: Vec<SnapshotVersion>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Snapshot {
+    pub timestamp: Option<String>,
+    pub build_number: Option<String>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct SnapshotVersion {
+    pub classifier: Option<String>,
+    pub extension: String,
+    pub value: String,
+}
+
+impl MavenMetadata {
+    pub fn parse(xml: &str) -> Result<MavenMetadata, String> {
+        let document = Document::parse(xml).map_err(|err| err.to_string())?;
+
+        let root = document.root_element();
+        if root.tag_name().name() != "metadata" {
+            return Err(format!("Expected root element 'metadata', found '{}'", root.tag_name().name()));
+        }
+
+        let versioning = match child(root, "versioning") {
+            Some(versioning) => versioning,
+            None => return Ok(MavenMetadata::default())
+        };
+
+        Ok(MavenMetadata {
+            latest: child_text(versioning, "latest"),
+            release: child_text(versioning, "release"),
+            versions: child(versioning, "versions")
+                .map(|versions| children_text(versions, "version"))
+                .unwrap_or_default(),
+            last_updated: child_text(versioning, "lastUpdated"),
+            snapshot: child(versioning, "snapshot").map(|snapshot| Snapshot {
+                timestamp: child_text(snapshot, "timestamp"),
+                build_number: child_text(snapshot, "buildNumber"),
+            }),
+            snapshot_versions: child(versioning, "snapshotVersions")
+                .map(|versions| versions.children()
+                    .filter(|node| node.has_tag_name("snapshotVersion"))
+                    .filter_map(|node| Some(SnapshotVersion {
+                        classifier: child_text(node, "classifier"),
+                        extension: child_text(node, "extension")?,
+                        value: child_text(node, "value")?,
+                    }))
+                    .collect())
+                .unwrap_or_default(),
+        })
+    }
+
+    /// The newest release version, falling back to the last non-snapshot version listed.
+    pub fn latest_release(&self) -> Option<&str> {
+        self.release.as_deref()
+            .or_else(|| self.versions.iter()
+                .rev()
+                .find(|version| !version.ends_with("-SNAPSHOT"))
+                .map(|version| version.as_str()))
+    }
+
+    /// The newest version, including snapshots.
+    pub fn latest_version(&self) -> Option<&str> {
+        self.latest.as_deref()
+            .or_else(|| self.versions.last().map(|version| version.as_str()))
+    }
+
+    /// Adds a version to artifact level metadata, making it the latest (and, if it isn't a
+    /// snapshot, the release) version. `updated` is formatted as 'yyyyMMddHHmmss'.
+    pub fn add_version(&mut self, version: &str, updated: &str) {
+        if !self.versions.iter().any(|existing| existing == version) {
+            self.versions.push(version.to_string());
+        }
+
+        self.latest = Some(version.to_string());
+        if !version.ends_with("-SNAPSHOT") {
+            self.release = Some(version.to_string());
+        }
+        self.last_updated = Some(updated.to_string());
+    }
+
+    /// Renders this as artifact level metadata of the given artifact.
+    pub fn to_xml(&self, group_id: &str, artifact_id: &str) -> String {
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<metadata>\n");
+
+        xml.push_str(format!("  <groupId>{}</groupId>\n", escape(group_id)).as_str());
+        xml.push_str(format!("  <artifactId>{}</artifactId>\n", escape(artifact_id)).as_str());
+        xml.push_str("  <versioning>\n");
+
+        if let Some(latest) = &self.latest {
+            xml.push_str(format!("    <latest>{}</latest>\n", escape(latest)).as_str());
+        }
+        if let Some(release) = &self.release {
+            xml.push_str(format!("    <release>{}</release>\n", escape(release)).as_str());
+        }
+
+        xml.push_str("    <versions>\n");
+        for version in &self.versions {
+            xml.push_str(format!("      <version>{}</version>\n", escape(version)).as_str());
+        }
+        xml.push_str("    </versions>\n");
+
+        if let Some(last_updated) = &self.last_updated {
+            xml.push_str(format!("    <lastUpdated>{}</lastUpdated>\n", escape(last_updated)).as_str());
+        }
+
+        xml.push_str("  </versioning>\n</metadata>\n");
+        xml
+    }
+
+    /// The version used in the file names of a snapshot's files, eg. '1.0-20240101.120000-1'.
+    /// Returns `None` if the snapshot was not deployed with unique versions.
+    pub fn snapshot_file_version(&self, version: &str, classifier: Option<&str>, extension: &str) -> Option<String> {
+        if let Some(snapshot_version) = self.snapshot_versions.iter()
+            .find(|snapshot_version| snapshot_version.extension == extension && snapshot_version.classifier.as_deref() == classifier) {
+            return Some(snapshot_version.value.clone());
+        }
+
+        let snapshot = self.snapshot.as_ref()?;
+
+        Some(format!(
+            "{}-{}-{}",
+            version.strip_suffix("-SNAPSHOT").unwrap_or(version),
+            snapshot.timestamp.as_ref()?,
+            snapshot.build_number.as_ref()?
+        ))
+    }
+}
+
+/// Fetches and parses the 'maven-metadata.xml' at the given resource path.
+pub async fn fetch_metadata(
+    resource_access: &(dyn ResourceAccess + Send + Sync),
+    path: PathBuf,
+) -> Result<MavenMetadata, Box<dyn SerializableError>> {
+    let file = resource_access.get_resource(path.clone()).await?;
+
+    let xml = fs::read_to_string(file)
+        .map_err(|e| Box::new(IOError(e)) as Box<dyn SerializableError>)?;
+
+    MavenMetadata::parse(&xml)
+        .map_err(|err| Box::new(MalformedMetadata(path.to_str().unwrap().to_string(), err)) as Box<dyn SerializableError>)
+}
+
+pub(crate) fn child<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+    node.children().find(|child| child.has_tag_name(name))
+}
+
+pub(crate) fn child_text(node: Node, name: &str) -> Option<String> {
+    child(node, name)
+        .and_then(|child| child.text())
+        .map(|text| text.trim().to_string())
+}
+
+pub(crate) fn escape(value: &str) -> String {
+    value.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn children_text(node: Node, name: &str) -> Vec<String> {
+    node.children()
+        .filter(|child| child.has_tag_name(name))
+        .filter_map(|child| child.text())
+        .map(|text| text.trim().to_string())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::maven::metadata::MavenMetadata;
+
+    #[test]
+    fn test_artifact_metadata() {
+        let metadata = MavenMetadata::parse(r#"<?xml version="1.0" encoding="UTF-8"?>
+<metadata>
+  <groupId>com.example</groupId>
+  <artifactId>tool</artifactId>
+  <versioning>
+    <latest>1.3-SNAPSHOT</latest>
+    <versions>
+      <version>1.1</version>
+      <version>1.2</version>
+      <version>1.3-SNAPSHOT</version>
+    </versions>
+    <lastUpdated>20240101120000</lastUpdated>
+  </versioning>
+</metadata>"#).unwrap();
+
+        assert_eq!(metadata.latest_release(), Some("1.2"));
+        assert_eq!(metadata.latest_version(), Some("1.3-SNAPSHOT"));
+    }
+
+    #[test]
+    fn test_add_version() {
+        let mut metadata = MavenMetadata::default();
+        metadata.add_version("1.0", "20240101120000");
+        metadata.add_version("1.1-SNAPSHOT", "20240102120000");
+
+        let metadata = MavenMetadata::parse(&metadata.to_xml("com.example", "tool")).unwrap();
+
+        assert_eq!(metadata.versions, vec!["1.0", "1.1-SNAPSHOT"]);
+        assert_eq!(metadata.latest_release(), Some("1.0"));
+        assert_eq!(metadata.latest_version(), Some("1.1-SNAPSHOT"));
+        assert_eq!(metadata.last_updated.as_deref(), Some("20240102120000"));
+    }
+
+    #[test]
+    fn test_snapshot_metadata() {
+        let metadata = MavenMetadata::parse(r#"<metadata modelVersion="1.1.0">
+  <groupId>com.example</groupId>
+  <artifactId>tool</artifactId>
+  <version>1.3-SNAPSHOT</version>
+  <versioning>
+    <snapshot>
+      <timestamp>20240101.120000</timestamp>
+      <buildNumber>4</buildNumber>
+    </snapshot>
+    <snapshotVersions>
+      <snapshotVersion>
+        <classifier>all</classifier>
+        <extension>jar</extension>
+        <value>1.3-20240101.120000-4</value>
+      </snapshotVersion>
+    </snapshotVersions>
+  </versioning>
+</metadata>"#).unwrap();
+
+        assert_eq!(metadata.snapshot_file_version("1.3-SNAPSHOT", Some("all"), "jar"), Some("1.3-20240101.120000-4".to_string()));
+        assert_eq!(metadata.snapshot_file_version("1.3-SNAPSHOT", None, "pom"), Some("1.3-20240101.120000-4".to_string()));
+    }
+}
@@ -0,0 +1,185 @@
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+use rocket::serde::Serialize;
+
+use crate::err::{InvalidSegment, SerializableError};
+use crate::resource_access::ResourceAccess;
+
+pub mod metadata;
+pub mod pom;
+
+/// A single component of a Maven path taken from a request (a group, artifact id, version,
+/// classifier or extension), checked so that joining it onto a directory can't escape it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Segment<'a>(&'a str);
+
+impl<'a> Segment<'a> {
+    /// Validates the value of the named field. Groups are dotted, so only `..` is refused.
+    pub fn parse(field: &'static str, value: &'a str) -> Result<Segment<'a>, Box<dyn SerializableError>> {
+        if value.is_empty() || value == "." || value.contains("..") || value.contains(['/', '\\', '\0']) {
+            return Err(Box::new(InvalidSegment(field, value.to_string())));
+        }
+
+        Ok(Segment(value))
+    }
+
+    pub fn as_str(&self) -> &'a str {
+        self.0
+    }
+}
+
+/// The Maven coordinates of a single version of an artifact.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "camelCase")]
+pub struct Coordinates {
+    pub group_id: String,
+    pub artifact_id: String,
+    pub version: String,
+}
+
+impl Coordinates {
+    /// Parses the coordinates of a version directory path, eg. 'com/example/lib/1.0'.
+    pub fn from_version_path(path: &Path) -> Option<Coordinates> {
+        let segments = path.iter()
+            .map(|segment| segment.to_str())
+            .collect::<Option<Vec<&str>>>()?;
+
+        match segments.as_slice() {
+            [group @ .., artifact, version] if !group.is_empty() => Some(Coordinates {
+                group_id: group.join("."),
+                artifact_id: artifact.to_string(),
+                version: version.to_string(),
+            }),
+            _ => None
+        }
+    }
+
+    /// Parses the coordinates of an artifact file path, eg. 'com/example/lib/1.0/lib-1.0.jar'.
+    /// Files which don't belong to the version they are in (such as metadata) are ignored.
+    pub fn from_file_path(path: &Path) -> Option<Coordinates> {
+        let coordinates = Coordinates::from_version_path(path.parent()?)?;
+        let name = path.file_name()?.to_str()?;
+
+        let prefix = match coordinates.version.strip_suffix("-SNAPSHOT") {
+            Some(base) => format!("{}-{}-", coordinates.artifact_id, base),
+            None => format!("{}-{}", coordinates.artifact_id, coordinates.version),
+        };
+
+        if name.starts_with(&prefix) {
+            Some(coordinates)
+        } else {
+            None
+        }
+    }
+
+    /// The directory holding the files of this version, eg. 'com/example/lib/1.0'.
+    pub fn version_path(&self) -> PathBuf {
+        let mut path: PathBuf = self.group_id.split('.').collect();
+        path.push(&self.artifact_id);
+        path.push(&self.version);
+        path
+    }
+}
+
+/// Whether the path points at a `maven-metadata.xml` file or one of its checksums.
+pub fn is_maven_metadata(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .map(|name| name.starts_with("maven-metadata.xml"))
+        .unwrap_or(false)
+}
+
+/// Whether the path points at a file inside of a `-SNAPSHOT` version directory.
+pub fn is_snapshot(path: &Path) -> bool {
+    path.parent()
+        .and_then(|version| version.file_name())
+        .and_then(|version| version.to_str())
+        .map(|version| version.ends_with("-SNAPSHOT"))
+        .unwrap_or(false)
+}
+
+/// Walks the backend's listing of a repository for every version it contains, one listing per
+/// directory. Directories holding a file of the version they name are versions, and aren't
+/// walked any further.
+pub async fn walk_versions<R: ResourceAccess + Sync + ?Sized>(
+    resource_access: &R,
+    repository: &str,
+) -> Result<BTreeSet<Coordinates>, Box<dyn SerializableError>> {
+    let mut versions = BTreeSet::new();
+    let mut directories = vec![PathBuf::new()];
+
+    while let Some(directory) = directories.pop() {
+        let entries = resource_access.list(
+            PathBuf::from(repository).join(&directory)
+        ).await?;
+
+        let version = entries.iter()
+            .filter(|entry| !entry.directory)
+            .find_map(|entry| Coordinates::from_file_path(&directory.join(&entry.name)));
+
+        match version {
+            Some(version) => {
+                versions.insert(version);
+            }
+            None => directories.extend(entries.into_iter()
+                .filter(|entry| entry.directory)
+                .map(|entry| directory.join(&entry.name)))
+        }
+    }
+
+    Ok(versions)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::maven::{Coordinates, Segment};
+    use crate::resource_access::memory::MemoryResourceAccess;
+    use crate::resource_access::ResourceAccess;
+
+    #[test]
+    fn test_coordinates_from_file_path() {
+        let coordinates = Coordinates::from_file_path(Path::new("com/example/lib/1.2.0/lib-1.2.0-sources.jar")).unwrap();
+
+        assert_eq!(coordinates.group_id, "com.example");
+        assert_eq!(coordinates.artifact_id, "lib");
+        assert_eq!(coordinates.version, "1.2.0");
+        assert_eq!(coordinates.version_path(), Path::new("com/example/lib/1.2.0"));
+
+        let snapshot = Coordinates::from_file_path(Path::new("com/example/lib/1.2.0-SNAPSHOT/lib-1.2.0-20240101.120000-1.jar")).unwrap();
+        assert_eq!(snapshot.version, "1.2.0-SNAPSHOT");
+
+        assert!(Coordinates::from_file_path(Path::new("com/example/lib/maven-metadata.xml")).is_none());
+        assert!(Coordinates::from_file_path(Path::new("com/example/lib/1.2.0/maven-metadata.xml")).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_list_versions() {
+        let backend = MemoryResourceAccess::default();
+
+        // Versions are found without the artifact's metadata.
+        backend.insert("releases/com/example/lib/1.0/lib-1.0.jar", "jar");
+        backend.insert("releases/com/example/lib/1.1/lib-1.1.pom", "pom");
+        backend.insert("releases/com/example/lib/maven-metadata.xml", "metadata");
+        backend.insert("releases/org/other/tool/2.0-SNAPSHOT/tool-2.0-20240101.120000-1.jar", "jar");
+
+        let versions = backend.list_versions("releases").await.unwrap()
+            .into_iter()
+            .map(|coordinates| format!("{}:{}:{}", coordinates.group_id, coordinates.artifact_id, coordinates.version))
+            .collect::<Vec<String>>();
+
+        assert_eq!(versions, vec!["com.example:lib:1.0", "com.example:lib:1.1", "org.other:tool:2.0-SNAPSHOT"]);
+    }
+
+    #[test]
+    fn test_segment() {
+        assert_eq!(Segment::parse("group", "com.example").unwrap().as_str(), "com.example");
+        assert_eq!(Segment::parse("version", "1.0-SNAPSHOT").unwrap().as_str(), "1.0-SNAPSHOT");
+
+        for value in ["", ".", "..", "../other", "a/b", "a\\b", "com..example", "jar/../../x"] {
+            assert_eq!(Segment::parse("artifact", value).unwrap_err().status(), 400, "{}", value);
+        }
+    }
+}
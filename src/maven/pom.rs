@@ -0,0 +1,296 @@
+use rocket::serde::Deserialize;
+use roxmltree::{Document, Node};
+
+use crate::maven::Coordinates;
+use crate::maven::metadata::{child, child_text, escape};
+
+/// How strictly POMs deployed to a repository are checked.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(try_from = "String")]
+pub enum PomValidation {
+    #[default]
+    None,
+    /// The POM must parse and its coordinates must match the path it is deployed to.
+    Coordinates,
+    /// As well as matching coordinates, the POM must have the metadata Maven Central requires.
+    Central,
+}
+
+impl TryFrom<String> for PomValidation {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        PomValidation::parse(&value)
+            .ok_or_else(|| format!("Invalid POM validation: '{}', expected one of: 'none', 'coordinates' or 'central'", value))
+    }
+}
+
+impl PomValidation {
+    pub fn parse(value: &str) -> Option<PomValidation> {
+        match value {
+            "none" => Some(PomValidation::None),
+            "coordinates" => Some(PomValidation::Coordinates),
+            "central" => Some(PomValidation::Central),
+            _ => None
+        }
+    }
+
+    /// The inverse of `parse`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PomValidation::None => "none",
+            PomValidation::Coordinates => "coordinates",
+            PomValidation::Central => "central",
+        }
+    }
+}
+
+/// What a POM says about a project, as shown when browsing.
+#[derive(Debug, Default, PartialEq)]
+pub struct PomDetails {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub url: Option<String>,
+    /// Defaults to 'jar'.
+    pub packaging: String,
+    pub licenses: Vec<String>,
+    pub dependencies: Vec<Dependency>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Dependency {
+    pub group_id: String,
+    pub artifact_id: String,
+    /// Left out when managed by a parent or BOM.
+    pub version: Option<String>,
+    pub scope: Option<String>,
+}
+
+impl PomDetails {
+    pub fn parse(pom: &str) -> Result<PomDetails, String> {
+        let document = Document::parse(pom).map_err(|err| err.to_string())?;
+        let project = document.root_element();
+
+        let dependencies = child(project, "dependencies")
+            .map(|dependencies| dependencies.children()
+                .filter(|dependency| dependency.has_tag_name("dependency"))
+                .filter_map(|dependency| Some(Dependency {
+                    group_id: child_text(dependency, "groupId")?,
+                    artifact_id: child_text(dependency, "artifactId")?,
+                    version: child_text(dependency, "version"),
+                    scope: child_text(dependency, "scope"),
+                }))
+                .collect())
+            .unwrap_or_default();
+
+        let licenses = child(project, "licenses")
+            .map(|licenses| licenses.children()
+                .filter(|license| license.has_tag_name("license"))
+                .filter_map(|license| child_text(license, "name"))
+                .collect())
+            .unwrap_or_default();
+
+        Ok(PomDetails {
+            name: child_text(project, "name").filter(|name| !name.is_empty()),
+            description: child_text(project, "description").filter(|description| !description.is_empty()),
+            url: child_text(project, "url").filter(|url| !url.is_empty()),
+            packaging: child_text(project, "packaging").unwrap_or("jar".to_string()),
+            licenses,
+            dependencies,
+        })
+    }
+}
+
+/// Checks a POM deployed to the version directory of `coordinates`, returning every problem found.
+pub fn validate_pom(pom: &str, coordinates: &Coordinates, validation: PomValidation) -> Vec<String> {
+    if validation == PomValidation::None {
+        return Vec::new();
+    }
+
+    let document = match Document::parse(pom) {
+        Ok(document) => document,
+        Err(err) => return vec![format!("Unparsable XML, {}", err)]
+    };
+
+    let project = document.root_element();
+    if !project.has_tag_name("project") {
+        return vec![format!("Expected a 'project' root element, found: '{}'", project.tag_name().name())];
+    }
+
+    let mut problems = Vec::new();
+
+    // The group and version may be inherited from the parent POM.
+    let parent = child(project, "parent");
+    let inherited = |name: &str| child_text(project, name)
+        .or_else(|| parent.and_then(|parent| child_text(parent, name)));
+
+    for (name, expected, actual) in [
+        ("groupId", &coordinates.group_id, inherited("groupId")),
+        ("artifactId", &coordinates.artifact_id, child_text(project, "artifactId")),
+        ("version", &coordinates.version, inherited("version")),
+    ] {
+        match actual {
+            None => problems.push(format!("Missing '{}'", name)),
+            Some(actual) if &actual != expected => problems.push(format!(
+                "'{}' is '{}' but the path expects '{}'", name, actual, expected
+            )),
+            _ => {}
+        }
+    }
+
+    if validation == PomValidation::Central {
+        for name in ["name", "description", "url"] {
+            if child_text(project, name).map(|text| text.is_empty()).unwrap_or(true) {
+                problems.push(format!("Missing '{}'", name));
+            }
+        }
+
+        for (list, item, required) in [("licenses", "license", "name"), ("developers", "developer", "name")] {
+            let found = child(project, list)
+                .map(|list| list.children()
+                    .filter(|child| child.has_tag_name(item))
+                    .any(|item| has_text(item, required)))
+                .unwrap_or(false);
+
+            if !found {
+                problems.push(format!("Missing a '{}' with a '{}' in '{}'", item, required, list));
+            }
+        }
+
+        if !child(project, "scm").map(|scm| has_text(scm, "url")).unwrap_or(false) {
+            problems.push("Missing an 'scm' with a 'url'".to_string());
+        }
+    }
+
+    problems
+}
+
+fn has_text(node: Node, name: &str) -> bool {
+    child_text(node, name)
+        .map(|text| !text.is_empty())
+        .unwrap_or(false)
+}
+
+/// Replaces the project version of a POM, which must be `from`. A version inherited from the
+/// parent is left alone and the project declares `to` after its `artifactId` instead. Everything
+/// else in the POM (including formatting) is left untouched.
+pub fn rewrite_version(pom: &str, from: &str, to: &str) -> Result<String, String> {
+    let document = Document::parse(pom).map_err(|err| err.to_string())?;
+    let project = document.root_element();
+
+    if let Some(version) = child(project, "version") {
+        let text = version.first_child()
+            .filter(|text| text.is_text() && text.text().map(|text| text.trim()) == Some(from))
+            .ok_or_else(|| format!("'version' is '{}' but '{}' is being promoted", version.text().unwrap_or_default(), from))?;
+
+        let range = text.range();
+        return Ok(format!("{}{}{}", &pom[..range.start], escape(to), &pom[range.end..]));
+    }
+
+    let inherited = child(project, "parent").and_then(|parent| child_text(parent, "version"));
+    if inherited.as_deref() != Some(from) {
+        return Err(format!("'version' is '{}' but '{}' is being promoted", inherited.unwrap_or_default(), from));
+    }
+
+    let artifact_id = child(project, "artifactId").ok_or("Missing 'artifactId'")?;
+
+    // Matches the indentation of the 'artifactId' line.
+    let indent = artifact_id.prev_sibling()
+        .and_then(|whitespace| whitespace.text())
+        .and_then(|whitespace| whitespace.rfind('\n').map(|line| &whitespace[line..]))
+        .unwrap_or_default();
+
+    let end = artifact_id.range().end;
+    Ok(format!("{}{}<version>{}</version>{}", &pom[..end], indent, escape(to), &pom[end..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::maven::Coordinates;
+    use crate::maven::pom::{Dependency, PomDetails, PomValidation, rewrite_version, validate_pom};
+
+    #[test]
+    fn test_pom_details() {
+        let pom = r#"<project xmlns="http://maven.apache.org/POM/4.0.0">
+  <artifactId>lib</artifactId>
+  <name>Example library</name>
+  <licenses><license><name>MIT</name></license></licenses>
+  <dependencies>
+    <dependency><groupId>org.slf4j</groupId><artifactId>slf4j-api</artifactId><version>2.0.13</version></dependency>
+    <dependency><groupId>org.junit</groupId><artifactId>junit-bom</artifactId><scope>test</scope></dependency>
+  </dependencies>
+</project>"#;
+
+        let details = PomDetails::parse(pom).unwrap();
+
+        assert_eq!(details.name.as_deref(), Some("Example library"));
+        assert_eq!(details.packaging, "jar");
+        assert_eq!(details.licenses, vec!["MIT"]);
+        assert_eq!(details.dependencies[1], Dependency {
+            group_id: "org.junit".to_string(),
+            artifact_id: "junit-bom".to_string(),
+            version: None,
+            scope: Some("test".to_string()),
+        });
+    }
+
+    #[test]
+    fn test_rewrite_version() {
+        let pom = r#"<project>
+  <groupId>com.example</groupId>
+  <artifactId>lib</artifactId>
+  <version>1.0-SNAPSHOT</version>
+  <dependencies>
+    <dependency><version>1.0-SNAPSHOT</version></dependency>
+  </dependencies>
+</project>"#;
+
+        let rewritten = rewrite_version(pom, "1.0-SNAPSHOT", "1.0").unwrap();
+
+        assert!(rewritten.contains("<artifactId>lib</artifactId>\n  <version>1.0</version>"));
+        assert!(rewritten.contains("<dependency><version>1.0-SNAPSHOT</version></dependency>"));
+        assert!(rewrite_version(pom, "2.0-SNAPSHOT", "2.0").is_err());
+
+        let child = r#"<project>
+  <parent><groupId>com.example</groupId><artifactId>parent</artifactId><version>1.0-SNAPSHOT</version></parent>
+  <artifactId>lib</artifactId>
+</project>"#;
+
+        let rewritten = rewrite_version(child, "1.0-SNAPSHOT", "1.0").unwrap();
+
+        assert!(rewritten.contains("<artifactId>parent</artifactId><version>1.0-SNAPSHOT</version></parent>"));
+        assert!(rewritten.contains("<artifactId>lib</artifactId>\n  <version>1.0</version>\n</project>"));
+        assert!(rewrite_version("<project><artifactId>lib</artifactId></project>", "1.0-SNAPSHOT", "1.0").is_err());
+    }
+
+    #[test]
+    fn test_validate_pom() {
+        let coordinates = Coordinates::from_version_path(Path::new("com/example/lib/1.0")).unwrap();
+
+        let pom = r#"<project xmlns="http://maven.apache.org/POM/4.0.0">
+      <parent><groupId>com.example</groupId><artifactId>parent</artifactId><version>1.0</version></parent>
+      <artifactId>lib</artifactId>
+      <name>lib</name>
+      <licenses><license><name>MIT</name></license></licenses>
+    </project>"#;
+
+        assert!(validate_pom(pom, &coordinates, PomValidation::Coordinates).is_empty());
+        assert_eq!(validate_pom(pom, &coordinates, PomValidation::Central), vec![
+            "Missing 'description'",
+            "Missing 'url'",
+            "Missing a 'developer' with a 'name' in 'developers'",
+            "Missing an 'scm' with a 'url'",
+        ]);
+
+        let other = Coordinates::from_version_path(Path::new("com/example/tool/1.1")).unwrap();
+        assert_eq!(validate_pom(pom, &other, PomValidation::Coordinates), vec![
+            "'artifactId' is 'lib' but the path expects 'tool'",
+            "'version' is '1.0' but the path expects '1.1'",
+        ]);
+
+        assert_eq!(validate_pom("<project>", &coordinates, PomValidation::Coordinates).len(), 1);
+        assert!(validate_pom("<project>", &coordinates, PomValidation::None).is_empty());
+    }
+}
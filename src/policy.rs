@@ -0,0 +1,84 @@
+use std::path::Path;
+
+use rocket::serde::Deserialize;
+
+use crate::maven::{is_maven_metadata, is_snapshot};
+
+/// Controls whether an existing resource in a repository may be overwritten by a later PUT.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(try_from = "String")]
+pub enum DeployPolicy {
+    #[default]
+    Allow,
+    Deny,
+    SnapshotsOnly,
+}
+
+impl TryFrom<String> for DeployPolicy {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        DeployPolicy::parse(&value)
+            .ok_or_else(|| format!("Invalid deploy policy: '{}', expected one of: 'allow', 'deny' or 'snapshots'", value))
+    }
+}
+
+impl DeployPolicy {
+    pub fn parse(value: &str) -> Option<DeployPolicy> {
+        match value {
+            "allow" => Some(DeployPolicy::Allow),
+            "deny" => Some(DeployPolicy::Deny),
+            "snapshots" => Some(DeployPolicy::SnapshotsOnly),
+            _ => None
+        }
+    }
+
+    /// The inverse of `parse`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeployPolicy::Allow => "allow",
+            DeployPolicy::Deny => "deny",
+            DeployPolicy::SnapshotsOnly => "snapshots",
+        }
+    }
+
+    /// Whether the resource at the given (repository relative) path may be overwritten. Maven
+    /// metadata files are rewritten on every deploy and so are always allowed.
+    pub fn allows_redeploy(&self, path: &Path) -> bool {
+        if is_maven_metadata(path) {
+            return true;
+        }
+
+        match self {
+            DeployPolicy::Allow => true,
+            DeployPolicy::Deny => false,
+            DeployPolicy::SnapshotsOnly => is_snapshot(path),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::policy::DeployPolicy;
+
+    #[test]
+    fn test_deny_redeploy() {
+        let policy = DeployPolicy::Deny;
+
+        assert!(!policy.allows_redeploy(Path::new("com/example/lib/1.2.0/lib-1.2.0.jar")));
+        assert!(!policy.allows_redeploy(Path::new("com/example/lib/1.2.0-SNAPSHOT/lib-1.2.0-SNAPSHOT.jar")));
+        assert!(policy.allows_redeploy(Path::new("com/example/lib/maven-metadata.xml")));
+        assert!(policy.allows_redeploy(Path::new("com/example/lib/maven-metadata.xml.sha1")));
+    }
+
+    #[test]
+    fn test_snapshot_redeploy() {
+        let policy = DeployPolicy::SnapshotsOnly;
+
+        assert!(!policy.allows_redeploy(Path::new("com/example/lib/1.2.0/lib-1.2.0.jar")));
+        assert!(policy.allows_redeploy(Path::new("com/example/lib/1.2.0-SNAPSHOT/lib-1.2.0-SNAPSHOT.jar")));
+        assert!(policy.allows_redeploy(Path::new("com/example/lib/1.2.0-SNAPSHOT/maven-metadata.xml")));
+    }
+}
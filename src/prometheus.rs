@@ -0,0 +1,207 @@
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Instant;
+
+use metrics::{counter, describe_counter, describe_gauge, describe_histogram, histogram, Unit};
+use metrics_exporter_prometheus::{PrometheusBuilder, PrometheusHandle};
+use rocket::{async_trait, Data, get, Request, Response, State};
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::ContentType;
+use tempfile::TempPath;
+
+use crate::ARProxyConfiguration;
+use crate::err::SerializableError;
+use crate::maven::Coordinates;
+use crate::resource_access::{Check, ResourceAccess, ResourceEntry};
+
+/// Histogram buckets (in seconds) for request latencies, from local cache hits to large uploads.
+const LATENCY_BUCKETS: &[f64] = &[0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0];
+
+/// Installs the global recorder every metric is reported to.
+pub fn install() -> PrometheusHandle {
+    let handle = PrometheusBuilder::new()
+        .set_buckets(LATENCY_BUCKETS)
+        .unwrap()
+        .install_recorder()
+        .expect("Failed to install the metrics recorder.");
+
+    describe_counter!("arp_http_requests_total", "Requests handled, by repository, method and status.");
+    describe_histogram!("arp_http_request_duration_seconds", Unit::Seconds, "Time taken to handle requests.");
+    describe_counter!("arp_http_response_bytes_total", Unit::Bytes, "Bytes of response bodies served.");
+    describe_histogram!("arp_upstream_request_duration_seconds", Unit::Seconds, "Time taken by backend operations.");
+    describe_counter!("arp_upstream_errors_total", "Failed backend operations, by error and status.");
+    describe_gauge!("arp_circuit_breaker_open", "Whether the circuit of each backend is open (1) or closed (0).");
+    describe_counter!("arp_upstream_retries_total", "Requests to backends retried, by the status or error which failed them.");
+    describe_counter!("arp_coalesced_requests_total", "Downloads served by waiting on a concurrent download of the same resource.");
+    describe_counter!("arp_cache_requests_total", "Resources looked up in the cache, by whether they were found.");
+    describe_counter!("arp_token_refreshes_total", "Refreshes of the GCP access token, by result.");
+    describe_gauge!("arp_audit_log_sequence", "The sequence number of the newest audit log entry.");
+
+    handle
+}
+
+#[get("/metrics")]
+pub async fn export_metrics(handle: &State<PrometheusHandle>) -> (ContentType, String) {
+    (ContentType::Plain, handle.render())
+}
+
+/// When the request was received.
+struct RequestStart(Instant);
+
+/// Records the count, latency and size of every response.
+pub struct RequestMetrics;
+
+#[async_trait]
+impl Fairing for RequestMetrics {
+    fn info(&self) -> Info {
+        Info {
+            name: "Request metrics",
+            kind: Kind::Request | Kind::Response,
+        }
+    }
+
+    async fn on_request(&self, request: &mut Request<'_>, _: &mut Data<'_>) {
+        request.local_cache(|| RequestStart(Instant::now()));
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let elapsed = request.local_cache(|| RequestStart(Instant::now())).0.elapsed();
+
+        // Only configured repositories are used as labels, so unknown paths can't grow the
+        // number of series.
+        let repository = match (request.guard::<&ARProxyConfiguration>().await.succeeded(), request.routed_segment(0)) {
+            (Some(configuration), Some(segment)) if configuration.repositories.contains_key(segment) => segment.to_string(),
+            _ => String::new()
+        };
+        let method = request.method().as_str();
+
+        counter!(
+            "arp_http_requests_total",
+            "repository" => repository.clone(),
+            "method" => method,
+            "status" => response.status().code.to_string()
+        ).increment(1);
+
+        histogram!(
+            "arp_http_request_duration_seconds",
+            "repository" => repository.clone(),
+            "method" => method
+        ).record(elapsed.as_secs_f64());
+
+        if let Some(size) = response.body_mut().size().await {
+            counter!("arp_http_response_bytes_total", "repository" => repository).increment(size as u64);
+        }
+    }
+}
+
+/// Records the latency and errors of every operation on a backend.
+pub struct MeteredResourceAccess {
+    /// The name of the backend in the configuration.
+    pub backend: String,
+    pub inner: Arc<dyn ResourceAccess + Send + Sync>,
+}
+
+impl MeteredResourceAccess {
+    fn observe<T>(
+        &self,
+        operation: &'static str,
+        start: Instant,
+        result: Result<T, Box<dyn SerializableError>>,
+    ) -> Result<T, Box<dyn SerializableError>> {
+        histogram!(
+            "arp_upstream_request_duration_seconds",
+            "backend" => self.backend.clone(),
+            "operation" => operation
+        ).record(start.elapsed().as_secs_f64());
+
+        if let Err(err) = &result {
+            counter!(
+                "arp_upstream_errors_total",
+                "backend" => self.backend.clone(),
+                "operation" => operation,
+                "error" => err.name(),
+                "status" => err.status().to_string()
+            ).increment(1);
+        }
+
+        result
+    }
+}
+
+#[async_trait]
+impl ResourceAccess for MeteredResourceAccess {
+    async fn get_resource(&self, path: PathBuf) -> Result<TempPath, Box<dyn SerializableError>> {
+        let start = Instant::now();
+        self.observe("get", start, self.inner.get_resource(path).await)
+    }
+
+    async fn exists(&self, path: PathBuf) -> Result<bool, Box<dyn SerializableError>> {
+        let start = Instant::now();
+        self.observe("exists", start, self.inner.exists(path).await)
+    }
+
+    async fn put_resource(&self, path: PathBuf, file: TempPath) -> Result<(), Box<dyn SerializableError>> {
+        let start = Instant::now();
+        self.observe("put", start, self.inner.put_resource(path, file).await)
+    }
+
+    async fn delete_resource(&self, path: PathBuf) -> Result<(), Box<dyn SerializableError>> {
+        let start = Instant::now();
+        self.observe("delete", start, self.inner.delete_resource(path).await)
+    }
+
+    async fn list(&self, path: PathBuf) -> Result<Vec<ResourceEntry>, Box<dyn SerializableError>> {
+        let start = Instant::now();
+        self.observe("list", start, self.inner.list(path).await)
+    }
+
+    async fn list_versions(&self, repository: &str) -> Result<BTreeSet<Coordinates>, Box<dyn SerializableError>> {
+        let start = Instant::now();
+        self.observe("list_versions", start, self.inner.list_versions(repository).await)
+    }
+
+    async fn check(&self) -> Vec<Check> {
+        self.inner.check().await
+    }
+
+    async fn refresh_credentials(&self) -> Option<Result<(), Box<dyn SerializableError>>> {
+        self.inner.refresh_credentials().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::sync::Arc;
+
+    use metrics::set_default_local_recorder;
+    use metrics_exporter_prometheus::PrometheusBuilder;
+
+    use crate::prometheus::MeteredResourceAccess;
+    use crate::resource_access::memory::MemoryResourceAccess;
+    use crate::resource_access::ResourceAccess;
+
+    #[tokio::test]
+    async fn test_metered_backend() {
+        let recorder = PrometheusBuilder::new().build_recorder();
+        let handle = recorder.handle();
+        let _guard = set_default_local_recorder(&recorder);
+
+        let memory = MemoryResourceAccess::default();
+        memory.insert("releases/lib.jar", "jar");
+
+        let access = MeteredResourceAccess {
+            backend: "local".to_string(),
+            inner: Arc::new(memory),
+        };
+
+        access.get_resource(PathBuf::from("releases/lib.jar")).await.unwrap();
+        access.get_resource(PathBuf::from("releases/missing.jar")).await.unwrap_err();
+
+        let rendered = handle.render();
+
+        assert!(rendered.contains(r#"arp_upstream_request_duration_seconds_count{backend="local",operation="get"} 2"#));
+        assert!(rendered.contains(r#"arp_upstream_errors_total{backend="local",operation="get",error="Resource not found",status="404"} 1"#));
+    }
+}
@@ -0,0 +1,391 @@
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+
+use chrono::Utc;
+use log::{info, warn};
+use rocket::serde::{Deserialize, Serialize};
+use tempfile::{NamedTempFile, TempPath};
+
+use crate::RepositoryConfiguration;
+use crate::checksum::{checksums, is_checksum};
+use crate::err::{InvalidPom, InvalidSignature, IOError, MalformedMetadata, RedeployNotAllowed, ResourceNotFound, SerializableError, UnsignedArtifacts};
+use crate::maven::{Coordinates, is_maven_metadata, Segment};
+use crate::maven::metadata::{fetch_metadata, MavenMetadata};
+use crate::maven::pom::{rewrite_version, validate_pom};
+use crate::resource_access::ResourceAccess;
+use crate::retention::group_builds;
+use crate::signature::{is_signature_file, Keyring, requires_signature};
+
+/// Files waiting to be written, paired with their contents.
+pub(crate) type PendingFiles = Vec<(PathBuf, Vec<u8>)>;
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PromotionRequest {
+    pub from: String,
+    pub to: String,
+    pub group_id: String,
+    pub artifact_id: String,
+    pub version: String,
+    /// The version to publish the files as in the target repository, defaults to `version`.
+    pub target_version: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PromotionReport {
+    pub from: String,
+    pub to: String,
+    pub coordinates: Coordinates,
+    pub files: Vec<String>,
+}
+
+/// Copies every file of a version from one repository to another, regenerating checksums and
+/// the target's artifact metadata. If any file fails to be written everything already written
+/// is rolled back, so the target either has all of the files or none of them.
+pub async fn promote(
+    resource_access: &(dyn ResourceAccess + Send + Sync),
+    from: &RepositoryConfiguration,
+    to: &RepositoryConfiguration,
+    request: &PromotionRequest,
+) -> Result<PromotionReport, Box<dyn SerializableError>> {
+    let source = Coordinates {
+        group_id: Segment::parse("groupId", &request.group_id)?.as_str().to_string(),
+        artifact_id: Segment::parse("artifactId", &request.artifact_id)?.as_str().to_string(),
+        version: Segment::parse("version", &request.version)?.as_str().to_string(),
+    };
+    let target = Coordinates {
+        version: Segment::parse("targetVersion", request.target_version.as_ref().unwrap_or(&request.version))?.as_str().to_string(),
+        ..source.clone()
+    };
+
+    let source_directory = PathBuf::from(&from.id).join(source.version_path());
+    let target_directory = target.version_path();
+
+    let names = resource_access.list(source_directory.clone())
+        .await?
+        .into_iter()
+        .filter(|entry| !entry.directory)
+        .map(|entry| entry.name)
+        .filter(|name| !is_maven_metadata(name.as_ref()) && !is_checksum(name.as_ref()))
+        .collect::<Vec<String>>();
+
+    // Only the newest build of a snapshot is promoted.
+    let (names, file_version) = match source.version.strip_suffix("-SNAPSHOT")
+        .and_then(|base| group_builds(&source, names.clone()).into_iter().next().map(|build| (base, build))) {
+        Some((base, build)) => {
+            let file_version = format!("{}-{}-{}", base, build.timestamp.format("%Y%m%d.%H%M%S"), build.number);
+            (build.files, file_version)
+        }
+        None => (names, source.version.clone())
+    };
+
+    if names.is_empty() {
+        return Err(Box::new(ResourceNotFound(source_directory.to_str().unwrap().to_string())));
+    }
+
+    let mut files = Vec::new();
+
+    for name in names {
+        let target_name = match name.strip_prefix(format!("{}-{}", source.artifact_id, file_version).as_str()) {
+            Some(suffix) => format!("{}-{}{}", target.artifact_id, target.version, suffix),
+            None => name.clone()
+        };
+
+        let file = resource_access.get_resource(source_directory.join(&name)).await?;
+        let mut data = fs::read(&file)
+            .map_err(|e| Box::new(IOError(e)) as Box<dyn SerializableError>)?;
+
+        if target_name.ends_with(".pom") && source.version != target.version {
+            data = rewrite_version(String::from_utf8_lossy(&data).as_ref(), &source.version, &target.version)
+                .map_err(|err| Box::new(MalformedMetadata(name.clone(), err)) as Box<dyn SerializableError>)?
+                .into_bytes();
+        }
+
+        if target_name.ends_with(".pom") {
+            let problems = validate_pom(String::from_utf8_lossy(&data).as_ref(), &target, to.pom_validation);
+            if !problems.is_empty() {
+                return Err(Box::new(InvalidPom(target_name, problems)));
+            }
+        }
+
+        push_with_checksums(&mut files, target_directory.join(target_name), data);
+    }
+
+    // Signatures made for the source files are replaced by ARP's own.
+    if let Some(signer) = &to.signer {
+        files.retain(|(path, _)| !is_signature_file(path));
+
+        let signatures = files.iter()
+            .filter(|(path, _)| requires_signature(path))
+            .map(|(path, data)| signer.signature_files(path, data))
+            .collect::<Result<Vec<PendingFiles>, Box<dyn SerializableError>>>()?;
+
+        files.extend(signatures.into_iter().flatten());
+    }
+
+    if let Some(keyring) = &to.keyring {
+        verify_signatures(keyring, &files)?;
+    }
+
+    for (path, _) in &files {
+        if !to.deploy_policy.allows_redeploy(path) && resource_access.exists(PathBuf::from(&to.id).join(path)).await? {
+            return Err(Box::new(RedeployNotAllowed(path.to_str().unwrap().to_string())));
+        }
+    }
+
+    let metadata_path = target_directory.parent().unwrap().join("maven-metadata.xml");
+
+    let mut metadata = if resource_access.exists(PathBuf::from(&to.id).join(&metadata_path)).await? {
+        fetch_metadata(resource_access, PathBuf::from(&to.id).join(&metadata_path)).await?
+    } else {
+        MavenMetadata::default()
+    };
+    metadata.add_version(&target.version, Utc::now().format("%Y%m%d%H%M%S").to_string().as_str());
+
+    // Metadata goes last, so the new version is only advertised once all of its files exist.
+    push_with_checksums(&mut files, metadata_path, metadata.to_xml(&target.group_id, &target.artifact_id).into_bytes());
+
+    let mut journal = Vec::new();
+
+    for (path, data) in &files {
+        if let Err(err) = write(resource_access, PathBuf::from(&to.id).join(path), data, &mut journal).await {
+            warn!("Failed to promote: '{}', rolling back {} written files", path.to_str().unwrap(), journal.len());
+            roll_back(resource_access, journal).await;
+            return Err(err);
+        }
+    }
+
+    info!("Promoted {}:{}:{} to version: '{}' in repository: '{}'", source.group_id, source.artifact_id, source.version, target.version, to.id);
+
+    Ok(PromotionReport {
+        from: request.from.clone(),
+        to: request.to.clone(),
+        coordinates: target,
+        files: files.into_iter()
+            .map(|(path, _)| path.to_str().unwrap().to_string())
+            .collect(),
+    })
+}
+
+/// Checks every promoted file has a signature from the target repository's keyring. Rewriting
+/// a POM's version invalidates its signature, so such promotions need the POM re-signed.
+fn verify_signatures(keyring: &Keyring, files: &[(PathBuf, Vec<u8>)]) -> Result<(), Box<dyn SerializableError>> {
+    let mut unsigned = Vec::new();
+
+    for (path, data) in files.iter().filter(|(path, _)| requires_signature(path)) {
+        let signature_path = path.with_file_name(format!("{}.asc", path.file_name().unwrap().to_str().unwrap()));
+
+        match files.iter().find(|(path, _)| path == &signature_path) {
+            Some((_, signature)) => {
+                keyring.verify(signature, data).map_err(|reason| Box::new(
+                    InvalidSignature(signature_path.to_str().unwrap().to_string(), reason)
+                ) as Box<dyn SerializableError>)?;
+            }
+            None => unsigned.push(path.file_name().unwrap().to_str().unwrap().to_string())
+        }
+    }
+
+    if !unsigned.is_empty() {
+        return Err(Box::new(UnsignedArtifacts(files[0].0.parent().unwrap().to_str().unwrap().to_string(), unsigned)));
+    }
+
+    Ok(())
+}
+
+fn push_with_checksums(files: &mut PendingFiles, path: PathBuf, data: Vec<u8>) {
+    let name = path.file_name().unwrap().to_str().unwrap().to_string();
+
+    let sums = checksums(&data);
+    files.push((path.clone(), data));
+
+    for (extension, sum) in sums {
+        files.push((path.with_file_name(format!("{}.{}", name, extension)), sum.into_bytes()));
+    }
+}
+
+/// Writes a file, recording it (along with a backup of any file it replaced) in the journal.
+async fn write(
+    resource_access: &(dyn ResourceAccess + Send + Sync),
+    path: PathBuf,
+    data: &[u8],
+    journal: &mut Vec<(PathBuf, Option<TempPath>)>,
+) -> Result<(), Box<dyn SerializableError>> {
+    let backup = if resource_access.exists(path.clone()).await? {
+        Some(resource_access.get_resource(path.clone()).await?)
+    } else {
+        None
+    };
+
+    resource_access.put_resource(path.clone(), temp_file(data)?).await?;
+
+    journal.push((path, backup));
+
+    Ok(())
+}
+
+/// Restores every journaled file to its previous state, newest first.
+async fn roll_back(
+    resource_access: &(dyn ResourceAccess + Send + Sync),
+    journal: Vec<(PathBuf, Option<TempPath>)>,
+) {
+    for (path, backup) in journal.into_iter().rev() {
+        let result = match backup {
+            Some(backup) => resource_access.put_resource(path.clone(), backup).await,
+            None => resource_access.delete_resource(path.clone()).await,
+        };
+
+        if let Err(err) = result {
+            warn!("Failed to roll back: '{}', {}", path.to_str().unwrap(), err.message());
+        }
+    }
+}
+
+pub(crate) fn temp_file(data: &[u8]) -> Result<TempPath, Box<dyn SerializableError>> {
+    let mut file = NamedTempFile::new()
+        .map_err(|e| Box::new(IOError(e)) as Box<dyn SerializableError>)?;
+
+    file.write_all(data)
+        .map_err(|e| Box::new(IOError(e)) as Box<dyn SerializableError>)?;
+
+    Ok(file.into_temp_path())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use pgp::composed::{ArmorOptions, SignedPublicKey};
+
+    use crate::RepositoryConfiguration;
+    use crate::maven::pom::PomValidation;
+    use crate::policy::DeployPolicy;
+    use crate::promote::{promote, PromotionRequest};
+    use crate::resource_access::memory::MemoryResourceAccess;
+    use crate::signature::{Keyring, Signer};
+    use crate::signature::tests::secret_key;
+
+    fn repository(id: &str, deploy_policy: DeployPolicy) -> RepositoryConfiguration {
+        RepositoryConfiguration {
+            id: id.to_string(),
+            backend: "memory".to_string(),
+            deploy_policy,
+            retention: None,
+            pom_validation: PomValidation::None,
+            keyring: None,
+            signer: None,
+        }
+    }
+
+    fn request() -> PromotionRequest {
+        PromotionRequest {
+            from: "snapshots".to_string(),
+            to: "releases".to_string(),
+            group_id: "com.example".to_string(),
+            artifact_id: "lib".to_string(),
+            version: "1.0-SNAPSHOT".to_string(),
+            target_version: Some("1.0".to_string()),
+        }
+    }
+
+    fn snapshot_access(fail_on: Option<&str>) -> MemoryResourceAccess {
+        let access = MemoryResourceAccess {
+            fail_on: fail_on.map(|fail_on| fail_on.to_string()),
+            ..MemoryResourceAccess::default()
+        };
+
+        access.insert("snap/com/example/lib/1.0-SNAPSHOT/lib-1.0-20240101.120000-1.jar", "old");
+        access.insert("snap/com/example/lib/1.0-SNAPSHOT/lib-1.0-20240102.120000-2.jar", "new");
+        access.insert("snap/com/example/lib/1.0-SNAPSHOT/lib-1.0-20240102.120000-2.jar.sha1", "stale");
+        access.insert("snap/com/example/lib/1.0-SNAPSHOT/lib-1.0-20240102.120000-2.pom", "<project><version>1.0-SNAPSHOT</version></project>");
+        access.insert("snap/com/example/lib/1.0-SNAPSHOT/maven-metadata.xml", "<metadata/>");
+
+        access
+    }
+
+    #[tokio::test]
+    async fn test_promote_snapshot() {
+        let access = snapshot_access(None);
+
+        let report = promote(
+            &access,
+            &repository("snap", DeployPolicy::Allow),
+            &repository("rel", DeployPolicy::Deny),
+            &request(),
+        ).await.unwrap();
+
+        assert_eq!(report.coordinates.version, "1.0");
+        assert_eq!(access.read("rel/com/example/lib/1.0/lib-1.0.jar").as_deref(), Some("new"));
+        assert_eq!(access.read("rel/com/example/lib/1.0/lib-1.0.pom").as_deref(), Some("<project><version>1.0</version></project>"));
+        assert_eq!(access.read("rel/com/example/lib/1.0/lib-1.0.jar.sha1").as_deref(), Some("c2a6b03f190dfb2b4aa91f8af8d477a9bc3401dc"));
+        assert!(access.read("rel/com/example/lib/maven-metadata.xml").unwrap().contains("<release>1.0</release>"));
+
+        // Releases can't be overwritten by a second promotion.
+        assert_eq!(promote(
+            &access,
+            &repository("snap", DeployPolicy::Allow),
+            &repository("rel", DeployPolicy::Deny),
+            &request(),
+        ).await.unwrap_err().status(), 409);
+    }
+
+    #[tokio::test]
+    async fn test_promote_signs() {
+        let key = secret_key();
+        let armored = key.to_armored_bytes(ArmorOptions::default()).unwrap();
+        let public = SignedPublicKey::from(key).to_armored_bytes(ArmorOptions::default()).unwrap();
+
+        let access = snapshot_access(None);
+        let mut target = repository("rel", DeployPolicy::Deny);
+        target.keyring = Some(Keyring::parse(&public).unwrap());
+
+        // Without signatures the promotion is refused.
+        assert_eq!(promote(&access, &repository("snap", DeployPolicy::Allow), &target, &request()).await.unwrap_err().status(), 422);
+
+        target.signer = Some(Arc::new(Signer::parse(&armored, "").unwrap()));
+        promote(&access, &repository("snap", DeployPolicy::Allow), &target, &request()).await.unwrap();
+
+        assert!(access.read("rel/com/example/lib/1.0/lib-1.0.pom.asc").is_some());
+        assert!(access.read("rel/com/example/lib/1.0/lib-1.0.jar.asc.md5").is_some());
+        assert!(access.read("rel/com/example/lib/maven-metadata.xml.asc").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_promote_rejects_traversal() {
+        let access = snapshot_access(None);
+
+        for field in 0..4 {
+            let mut request = request();
+            match field {
+                0 => request.group_id = "com.example..".to_string(),
+                1 => request.artifact_id = "..".to_string(),
+                2 => request.version = "../../snap/com/example/lib/1.0-SNAPSHOT".to_string(),
+                _ => request.target_version = Some("../../../rel2".to_string()),
+            }
+
+            assert_eq!(promote(
+                &access,
+                &repository("snap", DeployPolicy::Allow),
+                &repository("rel", DeployPolicy::Allow),
+                &request,
+            ).await.unwrap_err().status(), 400);
+        }
+
+        assert!(access.files.lock().unwrap().keys().all(|path| !path.starts_with("rel")));
+    }
+
+    #[tokio::test]
+    async fn test_promote_rolls_back() {
+        let access = snapshot_access(Some("maven-metadata.xml.sha256"));
+
+        assert!(promote(
+            &access,
+            &repository("snap", DeployPolicy::Allow),
+            &repository("rel", DeployPolicy::Allow),
+            &request(),
+        ).await.is_err());
+
+        assert!(access.files.lock().unwrap().keys().all(|path| !path.starts_with("rel")));
+    }
+}
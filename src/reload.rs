@@ -0,0 +1,304 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, SystemTime};
+
+use log::{error, info, warn};
+use rocket::{async_trait, Request};
+use rocket::request::{FromRequest, Outcome};
+use sha2::{Digest, Sha256};
+use tokio::signal::unix::{signal, SignalKind};
+
+use crate::ARProxyConfiguration;
+use crate::auth::ApiCredentials;
+use crate::breaker::{CircuitBreaker, CircuitBreakingResourceAccess};
+use crate::config::{BackendConfiguration, ConfigError, setup_configuration};
+use crate::prometheus::MeteredResourceAccess;
+use crate::resource_access::{ResourceAccess, RoutingResourceAccess};
+
+pub type Backend = Arc<dyn ResourceAccess + Send + Sync>;
+
+/// The configuration requests are served with. It is replaced as a whole when reloaded, while
+/// each request keeps the configuration it started with.
+pub struct LiveConfiguration {
+    current: RwLock<Arc<ARProxyConfiguration>>,
+    path: Option<PathBuf>,
+    routing: Arc<RoutingResourceAccess>,
+    /// Connected backends, reused by reloads that leave their configuration unchanged.
+    backends: Mutex<HashMap<String, (BackendConfiguration, Backend)>>,
+    /// Digests of the credentials revoked through the admin API. A rotated key isn't revoked.
+    revoked: RwLock<HashSet<String>>,
+    /// Held for the whole of a reload, so a reload on `SIGHUP` and one on a file change can't
+    /// interleave their routing and configuration.
+    reloading: Mutex<()>,
+}
+
+fn credentials_digest(creds: &ApiCredentials) -> String {
+    hex::encode(Sha256::new()
+        .chain_update(&creds.user)
+        .chain_update([0])
+        .chain_update(&creds.key)
+        .finalize())
+}
+
+impl LiveConfiguration {
+    /// Connects the backends of the configuration, failing when any can't be connected.
+    pub fn new(configuration: ARProxyConfiguration, path: Option<PathBuf>) -> Result<LiveConfiguration, Vec<ConfigError>> {
+        let live = LiveConfiguration {
+            current: RwLock::new(Arc::new(configuration)),
+            path,
+            routing: Arc::new(RoutingResourceAccess::new(HashMap::new())),
+            backends: Mutex::new(HashMap::new()),
+            revoked: RwLock::new(HashSet::new()),
+            reloading: Mutex::new(()),
+        };
+
+        live.route(&live.load())?;
+
+        Ok(live)
+    }
+
+    pub fn load(&self) -> Arc<ARProxyConfiguration> {
+        Arc::clone(&self.current.read().unwrap())
+    }
+
+    /// Routes to the backends of every configured repository.
+    pub fn routing(&self) -> Arc<RoutingResourceAccess> {
+        Arc::clone(&self.routing)
+    }
+
+    /// Rejects the current keys of the principal until ARP restarts, returning how many keys were
+    /// revoked.
+    pub fn revoke(&self, user: &str) -> usize {
+        let configuration = self.load();
+        let mut revoked = self.revoked.write().unwrap();
+
+        configuration.creds.iter()
+            .filter(|creds| creds.user == user)
+            .filter(|creds| revoked.insert(credentials_digest(creds)))
+            .count()
+    }
+
+    pub fn is_revoked(&self, creds: &ApiCredentials) -> bool {
+        self.revoked.read().unwrap().contains(&credentials_digest(creds))
+    }
+
+    /// The connected backends of the current configuration, by name.
+    pub fn backends(&self) -> Vec<(String, Backend)> {
+        let configuration = self.load();
+
+        self.backends.lock().unwrap().iter()
+            .filter(|(name, _)| configuration.backends.contains_key(*name))
+            .map(|(name, (_, backend))| (name.clone(), Arc::clone(backend)))
+            .collect()
+    }
+
+    /// Connects the new and changed backends, then routes every configured repository to its
+    /// backend. Nothing changes when a backend fails to connect.
+    fn route(&self, configuration: &ARProxyConfiguration) -> Result<(), Vec<ConfigError>> {
+        let changed = {
+            let backends = self.backends.lock().unwrap();
+
+            configuration.backends.iter()
+                .filter(|(name, backend)| backends.get(*name).map(|(connected, _)| connected != *backend).unwrap_or(true))
+                .map(|(name, backend)| (name.clone(), backend.clone()))
+                .collect::<Vec<_>>()
+        };
+
+        // Connecting may block, so it's done without holding the lock.
+        let mut connected = Vec::new();
+        let mut errors = Vec::new();
+
+        for (name, backend) in changed {
+            match backend.connect(&configuration.upstream) {
+                Ok(inner) => connected.push((name.clone(), backend, Arc::new(MeteredResourceAccess {
+                    backend: name.clone(),
+                    inner: Arc::new(CircuitBreakingResourceAccess {
+                        backend: name,
+                        breaker: CircuitBreaker::new(configuration.upstream.breaker_threshold, configuration.upstream.breaker_cooldown),
+                        inner,
+                    }),
+                }) as Backend)),
+                Err(err) => errors.push(ConfigError::new(format!("backends.{}", name), err)),
+            }
+        }
+
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        let mut backends = self.backends.lock().unwrap();
+
+        for (name, backend, access) in connected {
+            backends.insert(name, (backend, access));
+        }
+        backends.retain(|name, _| configuration.backends.contains_key(name));
+
+        self.routing.update(configuration.repositories.values()
+            .map(|repository| (repository.id.clone(), Arc::clone(&backends[&repository.backend].1)))
+            .collect());
+
+        Ok(())
+    }
+
+    /// Reads the configuration again and swaps it in. An invalid configuration is rejected and
+    /// the current one is kept. Reloads run one at a time, so the routing always matches the
+    /// configuration of the last reload.
+    pub fn reload(&self) -> Result<(), Vec<ConfigError>> {
+        let _reloading = self.reloading.lock().unwrap();
+
+        let configuration = setup_configuration(self.path.as_deref())?;
+        let current = self.load();
+
+        if configuration.cache_directory != current.cache_directory
+            || configuration.cache_mutable_ttl != current.cache_mutable_ttl
+            || configuration.search_index_interval != current.search_index_interval
+            || configuration.retention_dry_run != current.retention_dry_run
+            || configuration.retention_interval != current.retention_interval
+            || configuration.watch_interval != current.watch_interval
+            || configuration.tracing_endpoint != current.tracing_endpoint
+            || configuration.logging != current.logging
+            || configuration.audit_file != current.audit_file
+            || configuration.hold_directory != current.hold_directory
+            || configuration.hold_max_size != current.hold_max_size
+            || configuration.upstream != current.upstream {
+            warn!("Changes to the cache, search, retention, reload, tracing, logging, audit, holds and upstream settings take effect after a restart");
+        }
+
+        self.route(&configuration)?;
+
+        info!(
+            "Reloaded configuration: {} repositories, {} principal(s)",
+            configuration.repositories.len(),
+            configuration.creds.len()
+        );
+
+        *self.current.write().unwrap() = Arc::new(configuration);
+
+        Ok(())
+    }
+}
+
+/// Provides the configuration, the same one for the whole of the request even when it is
+/// reloaded meanwhile.
+#[async_trait]
+impl<'r> FromRequest<'r> for &'r ARProxyConfiguration {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let live = request.rocket().state::<Arc<LiveConfiguration>>().unwrap();
+
+        Outcome::Success(request.local_cache(|| live.load()).as_ref())
+    }
+}
+
+async fn reload(live: &Arc<LiveConfiguration>) {
+    let live = Arc::clone(live);
+
+    // Keys are read and backends connected with blocking calls.
+    match tokio::task::spawn_blocking(move || live.reload()).await {
+        Ok(Ok(())) => {}
+        Ok(Err(errors)) => errors.iter()
+            .for_each(|err| error!("Configuration not reloaded, {}", err)),
+        Err(err) => error!("Configuration not reloaded, {}", err)
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+/// Reloads the configuration whenever ARP receives a SIGHUP.
+pub async fn reload_on_hangup(live: Arc<LiveConfiguration>) {
+    let mut hangups = match signal(SignalKind::hangup()) {
+        Ok(hangups) => hangups,
+        Err(err) => return error!("Failed to listen for SIGHUP, {}", err)
+    };
+
+    while hangups.recv().await.is_some() {
+        info!("Received SIGHUP, reloading configuration");
+        reload(&live).await;
+    }
+}
+
+/// Reloads the configuration whenever the configuration file is modified, checking once every
+/// interval.
+pub async fn watch_file(live: Arc<LiveConfiguration>, path: PathBuf, interval: Duration) {
+    let mut last_modified = modified(&path);
+
+    loop {
+        tokio::time::sleep(interval).await;
+
+        let modified = modified(&path);
+
+        if modified != last_modified {
+            info!("Configuration file: '{}' changed, reloading configuration", path.to_str().unwrap());
+            last_modified = modified;
+            reload(&live).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+
+    use tempfile::tempdir;
+
+    use crate::config::setup_configuration;
+    use crate::reload::LiveConfiguration;
+    use crate::resource_access::ResourceAccess;
+
+    #[tokio::test]
+    async fn test_reload() {
+        let directory = tempdir().unwrap();
+        let path = directory.path().join("arp.toml");
+        let backend = format!("[backends.local]\ntype = \"filesystem\"\ndirectory = {:?}\n", directory.path().join("repo"));
+
+        fs::write(&path, format!("{}[repositories.releases]\n", backend)).unwrap();
+
+        let live = LiveConfiguration::new(setup_configuration(Some(&path)).unwrap(), Some(path.clone())).unwrap();
+        let before = live.load();
+
+        fs::write(&path, format!(
+            "{}[repositories.snapshots]\n[[principals]]\nuser = \"ci\"\nkey = \"rotated\"\npermissions = [\"deploy\"]\n",
+            backend
+        )).unwrap();
+        live.reload().unwrap();
+
+        let after = live.load();
+        assert!(before.repositories.contains_key("releases"));
+        assert!(after.repositories.contains_key("snapshots") && !after.repositories.contains_key("releases"));
+        assert_eq!(after.creds[0].key, "rotated");
+        assert!(live.routing().list(PathBuf::from("snapshots")).await.unwrap().is_empty());
+        assert_eq!(live.routing().list(PathBuf::from("releases")).await.unwrap_err().status(), 404);
+
+        fs::write(&path, "[repositories.snapshots]\nbackend = \"missing\"\n").unwrap();
+        assert!(live.reload().is_err());
+        assert!(live.load().repositories.contains_key("snapshots"));
+    }
+
+    #[test]
+    fn test_revoke() {
+        let directory = tempdir().unwrap();
+        let path = directory.path().join("arp.toml");
+        let backend = format!("[backends.local]\ntype = \"filesystem\"\ndirectory = {:?}\n[repositories.releases]\n", directory.path());
+        let principal = |key: &str| format!("[[principals]]\nuser = \"ci\"\nkey = \"{}\"\npermissions = [\"deploy\"]\n", key);
+
+        fs::write(&path, format!("{}{}", backend, principal("leaked"))).unwrap();
+
+        let live = LiveConfiguration::new(setup_configuration(Some(&path)).unwrap(), Some(path.clone())).unwrap();
+
+        assert_eq!(live.revoke("ci"), 1);
+        assert_eq!(live.revoke("ci"), 0);
+        assert!(live.is_revoked(&live.load().creds[0]));
+
+        // A rotated key is accepted again.
+        fs::write(&path, format!("{}{}", backend, principal("rotated"))).unwrap();
+        live.reload().unwrap();
+
+        assert!(!live.is_revoked(&live.load().creds[0]));
+    }
+}
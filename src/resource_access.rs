@@ -1,9 +1,13 @@
-use std::path::PathBuf;
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
 
 use rocket::async_trait;
+use rocket::serde::Serialize;
 use tempfile::TempPath;
 
-use crate::err::SerializableError;
+use crate::err::{RepositoryNotFound, SerializableError};
+use crate::maven::{Coordinates, walk_versions};
 
 #[async_trait]
 pub trait ResourceAccess {
@@ -12,13 +16,237 @@ pub trait ResourceAccess {
         path: PathBuf
     ) -> Result<TempPath, Box<dyn SerializableError>>;
 
+    async fn exists(
+        & self,
+        path: PathBuf
+    ) -> Result<bool, Box<dyn SerializableError>>;
+
     async fn  put_resource(
         & self,
         path: PathBuf,
         file: TempPath,
     ) -> Result<(), Box<dyn SerializableError>>;
+
+    /// Deletes the resource at the given path. If the path points at a version directory the
+    /// whole version is deleted.
+    async fn delete_resource(
+        & self,
+        path: PathBuf,
+    ) -> Result<(), Box<dyn SerializableError>>;
+
+    /// Lists the direct children of the directory at the given path, an empty list is returned
+    /// if the directory does not exist.
+    async fn list(
+        & self,
+        path: PathBuf,
+    ) -> Result<Vec<ResourceEntry>, Box<dyn SerializableError>>;
+
+    /// Lists every version in the repository with the given (internal) id. Backends which can
+    /// list them directly should, rather than walking every directory.
+    async fn list_versions(
+        & self,
+        repository: &str,
+    ) -> Result<BTreeSet<Coordinates>, Box<dyn SerializableError>> {
+        walk_versions(self, repository).await
+    }
+
+    /// Checks what the backend depends on to serve requests, eg. its credentials or that it can
+    /// be reached, naming each dependency checked.
+    async fn check(&self) -> Vec<Check> {
+        Vec::new()
+    }
+
+    /// Renews the backend's credentials now, `None` when it doesn't have any.
+    async fn refresh_credentials(&self) -> Option<Result<(), Box<dyn SerializableError>>> {
+        None
+    }
+}
+
+/// The outcome of checking one dependency of a backend.
+pub type Check = (&'static str, Result<(), Box<dyn SerializableError>>);
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ResourceEntry {
+    pub name: String,
+    pub directory: bool,
+    pub size: Option<u64>,
+    pub updated: Option<String>,
+}
+
+/// Dispatches every call to the backend holding the repository a path belongs to, keyed by the
+/// path's first segment (the internal repository id).
+pub struct RoutingResourceAccess {
+    repositories: RwLock<HashMap<String, Arc<dyn ResourceAccess + Send + Sync>>>,
+}
+
+impl RoutingResourceAccess {
+    pub fn new(repositories: HashMap<String, Arc<dyn ResourceAccess + Send + Sync>>) -> RoutingResourceAccess {
+        RoutingResourceAccess {
+            repositories: RwLock::new(repositories),
+        }
+    }
+
+    /// Replaces every route. Repositories no longer configured can't be reached anymore, while
+    /// requests that already resolved their backend complete.
+    pub fn update(&self, repositories: HashMap<String, Arc<dyn ResourceAccess + Send + Sync>>) {
+        *self.repositories.write().unwrap() = repositories;
+    }
+
+    fn backend(&self, path: &Path) -> Result<Arc<dyn ResourceAccess + Send + Sync>, Box<dyn SerializableError>> {
+        let repository = path.iter()
+            .next()
+            .and_then(|repository| repository.to_str())
+            .unwrap_or_default();
+
+        self.repositories.read().unwrap()
+            .get(repository)
+            .cloned()
+            .ok_or_else(|| Box::new(RepositoryNotFound(repository.to_string())) as Box<dyn SerializableError>)
+    }
+}
+
+#[async_trait]
+impl ResourceAccess for RoutingResourceAccess {
+    async fn get_resource(&self, path: PathBuf) -> Result<TempPath, Box<dyn SerializableError>> {
+        self.backend(&path)?.get_resource(path).await
+    }
+
+    async fn exists(&self, path: PathBuf) -> Result<bool, Box<dyn SerializableError>> {
+        self.backend(&path)?.exists(path).await
+    }
+
+    async fn put_resource(&self, path: PathBuf, file: TempPath) -> Result<(), Box<dyn SerializableError>> {
+        self.backend(&path)?.put_resource(path, file).await
+    }
+
+    async fn delete_resource(&self, path: PathBuf) -> Result<(), Box<dyn SerializableError>> {
+        self.backend(&path)?.delete_resource(path).await
+    }
+
+    async fn list(&self, path: PathBuf) -> Result<Vec<ResourceEntry>, Box<dyn SerializableError>> {
+        self.backend(&path)?.list(path).await
+    }
+
+    async fn list_versions(&self, repository: &str) -> Result<BTreeSet<Coordinates>, Box<dyn SerializableError>> {
+        self.backend(Path::new(repository))?.list_versions(repository).await
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod memory {
+    use std::collections::{BTreeMap, BTreeSet};
+    use std::fs;
+    use std::path::PathBuf;
+    use std::sync::Mutex;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    use rocket::async_trait;
+    use tempfile::TempPath;
+
+    use crate::err::{IOError, ResourceNotFound, SerializableError};
+    use crate::promote::temp_file;
+    use crate::resource_access::{ResourceAccess, ResourceEntry};
+
+    /// An in memory backend for tests. Puts and deletes of paths containing `fail_on` are rejected.
+    #[derive(Default)]
+    pub(crate) struct MemoryResourceAccess {
+        pub(crate) files: Mutex<BTreeMap<PathBuf, Vec<u8>>>,
+        pub(crate) fail_on: Option<String>,
+        /// How long every get takes.
+        pub(crate) delay: Duration,
+        /// How many gets were made.
+        pub(crate) gets: AtomicUsize,
+    }
+
+    impl MemoryResourceAccess {
+        pub(crate) fn insert(&self, path: &str, data: &str) {
+            self.files.lock().unwrap().insert(PathBuf::from(path), data.as_bytes().to_vec());
+        }
+
+        pub(crate) fn read(&self, path: &str) -> Option<String> {
+            self.files.lock().unwrap()
+                .get(&PathBuf::from(path))
+                .map(|data| String::from_utf8_lossy(data).to_string())
+        }
+    }
+
+    #[async_trait]
+    impl ResourceAccess for MemoryResourceAccess {
+        async fn get_resource(&self, path: PathBuf) -> Result<TempPath, Box<dyn SerializableError>> {
+            self.gets.fetch_add(1, Ordering::Relaxed);
+            tokio::time::sleep(self.delay).await;
+
+            let data = self.files.lock().unwrap().get(&path).cloned()
+                .ok_or_else(|| Box::new(ResourceNotFound(path.to_str().unwrap().to_string())) as Box<dyn SerializableError>)?;
+
+            temp_file(&data)
+        }
+
+        async fn exists(&self, path: PathBuf) -> Result<bool, Box<dyn SerializableError>> {
+            Ok(self.files.lock().unwrap().contains_key(&path))
+        }
+
+        async fn put_resource(&self, path: PathBuf, file: TempPath) -> Result<(), Box<dyn SerializableError>> {
+            if self.fail_on.as_ref().map(|fail_on| path.to_str().unwrap().contains(fail_on)).unwrap_or(false) {
+                return Err(Box::new(ResourceNotFound(path.to_str().unwrap().to_string())));
+            }
+
+            let data = fs::read(file).map_err(|e| Box::new(IOError(e)) as Box<dyn SerializableError>)?;
+            self.files.lock().unwrap().insert(path, data);
+
+            Ok(())
+        }
+
+        async fn delete_resource(&self, path: PathBuf) -> Result<(), Box<dyn SerializableError>> {
+            if self.fail_on.as_ref().map(|fail_on| path.to_str().unwrap().contains(fail_on)).unwrap_or(false) {
+                return Err(Box::new(ResourceNotFound(path.to_str().unwrap().to_string())));
+            }
+
+            self.files.lock().unwrap().retain(|file, _| !file.starts_with(&path));
+
+            Ok(())
+        }
+
+        async fn list(&self, path: PathBuf) -> Result<Vec<ResourceEntry>, Box<dyn SerializableError>> {
+            let files = self.files.lock().unwrap();
+
+            let mut directories = BTreeSet::new();
+            let mut entries = Vec::new();
+
+            for (file, data) in files.iter() {
+                let relative = match file.strip_prefix(&path) {
+                    Ok(relative) => relative,
+                    Err(_) => continue
+                };
+
+                let mut components = relative.iter();
+                let name = components.next().unwrap().to_str().unwrap().to_string();
+
+                if components.next().is_some() {
+                    directories.insert(name);
+                } else {
+                    entries.push(ResourceEntry {
+                        name,
+                        directory: false,
+                        size: Some(data.len() as u64),
+                        updated: None,
+                    });
+                }
+            }
+
+            entries.extend(directories.into_iter().map(|name| ResourceEntry {
+                name,
+                directory: true,
+                size: None,
+                updated: None,
+            }));
+
+            Ok(entries)
+        }
+    }
 }
 
 // pub struct Resource {
 //     pub stream: ByteStream<Vec<u8>>,
-// }
\ No newline at end of file
+// }
@@ -0,0 +1,463 @@
+use std::cmp::Reverse;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{NaiveDateTime, TimeDelta, Utc};
+use log::{info, warn};
+use rocket::serde::{Deserialize, Serialize};
+
+use crate::audit::{Actor, AuditEvent, AuditLog};
+use crate::err::SerializableError;
+use crate::index::SearchIndex;
+use crate::maven::Coordinates;
+use crate::reload::LiveConfiguration;
+use crate::resource_access::ResourceAccess;
+
+/// Rules for cleaning up the timestamped builds of a repository's snapshot versions. The newest
+/// build of a version is always kept unless the whole version is dropped.
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(from = "RetentionRules")]
+pub struct RetentionPolicy {
+    /// How many of the newest builds of each snapshot version to keep.
+    pub keep_builds: Option<usize>,
+    /// Builds older than this are deleted.
+    pub max_age: Option<TimeDelta>,
+    /// Deletes whole snapshot versions whose newest build is older than `max_age`.
+    pub drop_stale_versions: bool,
+}
+
+/// How a retention policy is written in the configuration file.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RetentionRules {
+    keep: Option<usize>,
+    max_age_days: Option<i64>,
+    #[serde(default)]
+    drop_stale: bool,
+}
+
+impl From<RetentionRules> for RetentionPolicy {
+    fn from(rules: RetentionRules) -> Self {
+        RetentionPolicy {
+            keep_builds: rules.keep,
+            max_age: rules.max_age_days.map(TimeDelta::days),
+            drop_stale_versions: rules.drop_stale,
+        }
+    }
+}
+
+impl RetentionPolicy {
+    /// Parses a policy formatted as ';' separated rules, eg. 'keep=5;max-age=30;drop-stale=true'
+    /// where 'max-age' is a number of days.
+    pub fn parse(value: &str) -> Result<RetentionPolicy, String> {
+        let mut policy = RetentionPolicy::default();
+
+        for rule in value.split(';').filter(|rule| !rule.is_empty()) {
+            let (key, value) = rule.split_once('=')
+                .ok_or_else(|| format!("Expected a 'key=value' rule, found: '{}'", rule))?;
+
+            match key {
+                "keep" => policy.keep_builds = Some(
+                    value.parse().map_err(|_| format!("Expected a number of builds to keep, found: '{}'", value))?
+                ),
+                "max-age" => policy.max_age = Some(TimeDelta::days(
+                    value.parse().map_err(|_| format!("Expected a number of days, found: '{}'", value))?
+                )),
+                "drop-stale" => policy.drop_stale_versions = value.parse()
+                    .map_err(|_| format!("Expected 'true' or 'false', found: '{}'", value))?,
+                _ => return Err(format!("Unknown retention rule: '{}', expected one of 'keep', 'max-age' or 'drop-stale'", key))
+            }
+        }
+
+        Ok(policy)
+    }
+}
+
+/// Formats the policy as `parse` reads it.
+impl Display for RetentionPolicy {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let mut rules = Vec::new();
+
+        if let Some(keep) = self.keep_builds {
+            rules.push(format!("keep={}", keep));
+        }
+        if let Some(max_age) = self.max_age {
+            rules.push(format!("max-age={}", max_age.num_days()));
+        }
+        if self.drop_stale_versions {
+            rules.push("drop-stale=true".to_string());
+        }
+
+        write!(f, "{}", rules.join(";"))
+    }
+}
+
+#[derive(Serialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct RetentionReport {
+    pub repository: String,
+    pub dry_run: bool,
+    pub deleted_files: Vec<String>,
+    pub deleted_versions: Vec<String>,
+}
+
+/// A single timestamped deploy of a snapshot version, made up of one or more files.
+#[derive(Debug)]
+pub(crate) struct Build {
+    pub(crate) timestamp: NaiveDateTime,
+    pub(crate) number: u32,
+    pub(crate) files: Vec<String>,
+}
+
+/// Parses the timestamp and build number out of a timestamped snapshot file name, eg.
+/// 'lib-1.0-20240101.120000-4-sources.jar'.
+fn parse_build(coordinates: &Coordinates, file_name: &str) -> Option<(NaiveDateTime, u32)> {
+    let base = coordinates.version.strip_suffix("-SNAPSHOT")?;
+    let rest = file_name.strip_prefix(format!("{}-{}-", coordinates.artifact_id, base).as_str())?;
+
+    let timestamp = NaiveDateTime::parse_from_str(rest.get(..15)?, "%Y%m%d.%H%M%S").ok()?;
+
+    let number = rest.get(15..)?
+        .strip_prefix('-')?
+        .split(|c: char| !c.is_ascii_digit())
+        .next()?
+        .parse()
+        .ok()?;
+
+    Some((timestamp, number))
+}
+
+/// Groups the files of a snapshot version by build, newest first.
+pub(crate) fn group_builds(coordinates: &Coordinates, file_names: Vec<String>) -> Vec<Build> {
+    let mut builds: Vec<Build> = Vec::new();
+
+    for name in file_names {
+        let (timestamp, number) = match parse_build(coordinates, &name) {
+            Some(build) => build,
+            None => continue
+        };
+
+        match builds.iter_mut().find(|build| build.timestamp == timestamp && build.number == number) {
+            Some(build) => build.files.push(name),
+            None => builds.push(Build {
+                timestamp,
+                number,
+                files: vec![name],
+            })
+        }
+    }
+
+    builds.sort_by_key(|build| Reverse((build.timestamp, build.number)));
+
+    builds
+}
+
+/// The principal scheduled retention runs are recorded as.
+pub const RETENTION_PRINCIPAL: &str = "system";
+
+/// Applies the policy to every snapshot version in the repository. When `dry_run` is set nothing
+/// is deleted, and the report lists what would have been. Otherwise every deletion is recorded
+/// in the audit log as the actor's, as it is made, so a run failing partway still records those
+/// made before.
+pub async fn apply_retention(
+    resource_access: &(dyn ResourceAccess + Send + Sync),
+    index: &SearchIndex,
+    audit: (&AuditLog, &Actor),
+    repository: (&str, &str),
+    policy: &RetentionPolicy,
+    dry_run: bool,
+) -> Result<RetentionReport, Box<dyn SerializableError>> {
+    let (name, id) = repository;
+
+    let mut report = RetentionReport {
+        repository: name.to_string(),
+        dry_run,
+        ..RetentionReport::default()
+    };
+
+    let now = Utc::now().naive_utc();
+    let is_expired = |build: &Build| policy.max_age
+        .map(|max_age| now - build.timestamp > max_age)
+        .unwrap_or(false);
+
+    let versions = resource_access.list_versions(id).await?;
+
+    for coordinates in versions.into_iter().filter(|coordinates| coordinates.version.ends_with("-SNAPSHOT")) {
+        let version_path = coordinates.version_path();
+
+        let file_names = resource_access.list(
+            PathBuf::from(id).join(&version_path)
+        ).await?
+            .into_iter()
+            .filter(|entry| !entry.directory)
+            .map(|entry| entry.name)
+            .collect();
+
+        let builds = group_builds(&coordinates, file_names);
+
+        let newest = match builds.first() {
+            Some(newest) => newest,
+            None => continue
+        };
+
+        if policy.drop_stale_versions && is_expired(newest) {
+            delete(resource_access, audit, repository, &version_path, dry_run).await?;
+            if !dry_run {
+                index.forget(name, &version_path);
+            }
+            report.deleted_versions.push(version_path.to_str().unwrap().to_string());
+            continue;
+        }
+
+        for (position, build) in builds.iter().enumerate().skip(1) {
+            let exceeds_count = policy.keep_builds
+                .map(|keep| position >= keep)
+                .unwrap_or(false);
+
+            if !exceeds_count && !is_expired(build) {
+                continue;
+            }
+
+            for file in &build.files {
+                let path = version_path.join(file);
+                delete(resource_access, audit, repository, &path, dry_run).await?;
+                report.deleted_files.push(path.to_str().unwrap().to_string());
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+async fn delete(
+    resource_access: &(dyn ResourceAccess + Send + Sync),
+    (audit, actor): (&AuditLog, &Actor),
+    (name, id): (&str, &str),
+    path: &Path,
+    dry_run: bool,
+) -> Result<(), Box<dyn SerializableError>> {
+    if dry_run {
+        info!("Retention would delete: '{}' from repository: '{}'", path.to_str().unwrap(), id);
+        return Ok(());
+    }
+
+    info!("Retention deleting: '{}' from repository: '{}'", path.to_str().unwrap(), id);
+    let result = resource_access.delete_resource(PathBuf::from(id).join(path)).await;
+
+    audit.record(AuditEvent::deletion(actor, name, path, &result)).await;
+
+    result
+}
+
+/// Applies the retention policy of every repository, once every interval, recording deletions as
+/// `RETENTION_PRINCIPAL`'s.
+pub async fn apply_periodically(
+    resource_access: Arc<dyn ResourceAccess + Send + Sync>,
+    index: Arc<SearchIndex>,
+    audit: AuditLog,
+    configuration: Arc<LiveConfiguration>,
+    dry_run: bool,
+    interval: Duration,
+) {
+    let actor = Actor {
+        principal: RETENTION_PRINCIPAL.to_string(),
+        source_ip: None,
+    };
+
+    loop {
+        // Read every time, so reloaded policies are picked up.
+        let configuration = configuration.load();
+        let mut repositories = configuration.repositories.iter()
+            .filter_map(|(name, repository)| repository.retention.as_ref().map(|policy| (name, &repository.id, policy)))
+            .collect::<Vec<(&String, &String, &RetentionPolicy)>>();
+        repositories.sort_by_key(|(name, _, _)| *name);
+
+        for (name, id, policy) in repositories {
+            match apply_retention(resource_access.as_ref(), &index, (&audit, &actor), (name, id), policy, dry_run).await {
+                Ok(report) => info!(
+                    "Retention {} {} files and {} versions from repository: '{}'",
+                    if dry_run { "would delete" } else { "deleted" },
+                    report.deleted_files.len(),
+                    report.deleted_versions.len(),
+                    name
+                ),
+                Err(err) => warn!("Failed to apply retention to repository: '{}', {}", name, err.message())
+            }
+        }
+
+        tokio::time::sleep(interval).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use chrono::{TimeDelta, Utc};
+    use tempfile::tempdir;
+
+    use crate::audit::{Actor, AuditLog};
+    use crate::index::SearchIndex;
+    use crate::maven::Coordinates;
+    use crate::resource_access::memory::MemoryResourceAccess;
+    use crate::retention::{apply_retention, group_builds, RetentionPolicy};
+
+    #[test]
+    fn test_parse_policy() {
+        let policy = RetentionPolicy::parse("keep=5;max-age=30;drop-stale=true").unwrap();
+
+        assert_eq!(policy, RetentionPolicy {
+            keep_builds: Some(5),
+            max_age: Some(TimeDelta::days(30)),
+            drop_stale_versions: true,
+        });
+
+        assert!(RetentionPolicy::parse("keep=five").is_err());
+        assert!(RetentionPolicy::parse("forever=true").is_err());
+    }
+
+    #[test]
+    fn test_group_builds() {
+        let coordinates = Coordinates::from_version_path(Path::new("com/example/lib/1.0-SNAPSHOT")).unwrap();
+
+        let builds = group_builds(&coordinates, vec![
+            "lib-1.0-20240101.120000-1.jar".to_string(),
+            "lib-1.0-20240101.120000-1.pom".to_string(),
+            "lib-1.0-20240102.120000-2.jar".to_string(),
+            "lib-1.0-20240102.120000-2-sources.jar".to_string(),
+            "lib-1.0-20240102.120000-10.jar".to_string(),
+            "maven-metadata.xml".to_string(),
+        ]);
+
+        assert_eq!(builds.len(), 3);
+        assert_eq!(builds[0].number, 10);
+        assert_eq!(builds[1].files.len(), 2);
+        assert_eq!(builds[2].files, vec!["lib-1.0-20240101.120000-1.jar", "lib-1.0-20240101.120000-1.pom"]);
+    }
+
+    #[tokio::test]
+    async fn test_audits_each_deletion() {
+        let access = MemoryResourceAccess {
+            fail_on: Some("20240101.120000-1.pom".to_string()),
+            ..MemoryResourceAccess::default()
+        };
+        for build in ["20240101.120000-1", "20240102.120000-2", "20240103.120000-3"] {
+            access.insert(&format!("snapshots/com/example/lib/1.0-SNAPSHOT/lib-1.0-{}.jar", build), "jar");
+            access.insert(&format!("snapshots/com/example/lib/1.0-SNAPSHOT/lib-1.0-{}.pom", build), "pom");
+        }
+
+        let directory = tempdir().unwrap();
+        let audit = AuditLog::open(&directory.path().join("audit.jsonl")).unwrap();
+        let actor = Actor {
+            principal: "janitor".to_string(),
+            source_ip: None,
+        };
+        let policy = RetentionPolicy::parse("keep=1").unwrap();
+
+        // The run fails on the last file, the ones deleted before it are still recorded.
+        let result = apply_retention(&access, &SearchIndex::default(), (&audit, &actor), ("snapshots", "snapshots"), &policy, false).await;
+        assert_eq!(result.unwrap_err().status(), 404);
+
+        let entries = audit.entries().await.unwrap().into_iter()
+            .map(|entry| (entry.event.principal, entry.event.path, entry.event.status))
+            .collect::<Vec<(String, String, u16)>>();
+
+        assert_eq!(entries, vec![
+            ("janitor".to_string(), "com/example/lib/1.0-SNAPSHOT/lib-1.0-20240102.120000-2.jar".to_string(), 200),
+            ("janitor".to_string(), "com/example/lib/1.0-SNAPSHOT/lib-1.0-20240102.120000-2.pom".to_string(), 200),
+            ("janitor".to_string(), "com/example/lib/1.0-SNAPSHOT/lib-1.0-20240101.120000-1.jar".to_string(), 200),
+            ("janitor".to_string(), "com/example/lib/1.0-SNAPSHOT/lib-1.0-20240101.120000-1.pom".to_string(), 404),
+        ]);
+    }
+
+    const TOOL: &str = "com/example/tool/2.0-SNAPSHOT";
+
+    /// A snapshot version with builds deployed 40, 20, 10 and 1 days ago (returned oldest first),
+    /// another version whose only build is 60 days old, and a release.
+    fn snapshots() -> (MemoryResourceAccess, Vec<String>) {
+        let access = MemoryResourceAccess::default();
+        let timestamp = |days: i64| (Utc::now() - TimeDelta::days(days)).format("%Y%m%d.%H%M%S").to_string();
+
+        let builds = [(1, 40), (2, 20), (3, 10), (4, 1)].into_iter()
+            .map(|(number, days)| format!("com/example/lib/1.0-SNAPSHOT/lib-1.0-{}-{}.jar", timestamp(days), number))
+            .collect::<Vec<String>>();
+
+        for build in &builds {
+            access.insert(&format!("snapshots/{}", build), "jar");
+        }
+        access.insert("snapshots/com/example/lib/1.0-SNAPSHOT/maven-metadata.xml", "metadata");
+        access.insert(&format!("snapshots/{}/tool-2.0-{}-1.jar", TOOL, timestamp(60)), "jar");
+        access.insert("snapshots/com/example/lib/0.9/lib-0.9.jar", "jar");
+
+        (access, builds)
+    }
+
+    /// Applies the policy, returning the deleted builds (by age, oldest first) and versions, and
+    /// whether each of the builds, the stale version, the release and the metadata survived.
+    async fn apply(policy: &str, dry_run: bool) -> (Vec<usize>, Vec<String>, Vec<bool>) {
+        let (access, builds) = snapshots();
+
+        let directory = tempdir().unwrap();
+        let audit = AuditLog::open(&directory.path().join("audit.jsonl")).unwrap();
+        let actor = Actor {
+            principal: "janitor".to_string(),
+            source_ip: None,
+        };
+
+        let report = apply_retention(
+            &access,
+            &SearchIndex::default(),
+            (&audit, &actor),
+            ("snapshots", "snapshots"),
+            &RetentionPolicy::parse(policy).unwrap(),
+            dry_run,
+        ).await.unwrap();
+        assert_eq!(report.dry_run, dry_run);
+
+        let files = access.files.lock().unwrap();
+        let survived = builds.iter()
+            .map(|build| files.contains_key(&Path::new("snapshots").join(build)))
+            .chain([
+                files.keys().any(|path| path.starts_with(Path::new("snapshots").join(TOOL))),
+                files.contains_key(Path::new("snapshots/com/example/lib/0.9/lib-0.9.jar")),
+                files.contains_key(Path::new("snapshots/com/example/lib/1.0-SNAPSHOT/maven-metadata.xml")),
+            ])
+            .collect();
+
+        let deleted = report.deleted_files.iter()
+            .map(|file| builds.iter().position(|build| build == file).unwrap())
+            .collect();
+
+        (deleted, report.deleted_versions, survived)
+    }
+
+    #[tokio::test]
+    async fn test_apply_retention() {
+        // Keeps the newest builds of each version.
+        let (files, versions, survived) = apply("keep=2", false).await;
+        assert_eq!(files, vec![1, 0]);
+        assert!(versions.is_empty());
+        assert_eq!(survived, vec![false, false, true, true, true, true, true]);
+
+        // Deletes builds older than the max age, but never the newest build of a version.
+        let (files, versions, survived) = apply("max-age=30", false).await;
+        assert_eq!(files, vec![0]);
+        assert!(versions.is_empty());
+        assert_eq!(survived, vec![false, true, true, true, true, true, true]);
+
+        // Unless the whole version is stale.
+        let (files, versions, survived) = apply("max-age=30;drop-stale=true", false).await;
+        assert_eq!(files, vec![0]);
+        assert_eq!(versions, vec![TOOL.to_string()]);
+        assert_eq!(survived, vec![false, true, true, true, false, true, true]);
+
+        // A dry run reports the same, without deleting anything.
+        let (files, versions, survived) = apply("keep=2;max-age=30;drop-stale=true", true).await;
+        assert_eq!(files, vec![1, 0]);
+        assert_eq!(versions, vec![TOOL.to_string()]);
+        assert!(survived.iter().all(|survived| *survived));
+    }
+}
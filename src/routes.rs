@@ -1,72 +1,178 @@
+use std::fs;
 use std::fs::File;
-use std::path::PathBuf;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
-use log::{debug, info};
-use rocket::{get, put, Responder, State};
+use log::{debug, info, warn};
+use rocket::{delete, get, put, Responder, State};
 use rocket::fs::TempFile;
-use rocket::http::{Header, Status};
+use rocket::http::{Accept, Header, Status};
+use rocket::http::uri::Origin;
+use rocket::response::content::RawHtml;
 use rocket::response::status;
 use rocket::response::status::Unauthorized;
 use rocket::serde::json::Json;
 use tempfile::NamedTempFile;
 
 use crate::{ARProxyConfiguration, ManagedResourceAccess};
-use crate::auth::ApiCredentials;
-use crate::err::{BasicError, IOError, RepositoryNotFound};
+use crate::audit::{AuditAction, AuditEvent, AuditLog, AuditStatus, file_digest};
+use crate::auth::{ApiCredentials, Deleter, Deployer};
+use crate::cache::ResourceCache;
+use crate::err::{BasicError, InvalidModule, InvalidPom, IOError, RedeployNotAllowed, RepositoryNotFound, ResourceNotFound};
+use crate::gradle::{GradleModule, is_gradle_module, ModuleReport};
+use crate::index::SearchIndex;
+use crate::listing::render_listing;
+use crate::maven::Coordinates;
+use crate::maven::pom::{PomValidation, validate_pom};
+use crate::resource_access::ResourceEntry;
+use crate::signature::{HeldUploads, is_signature_file, requires_signature, SignatureCheck, verify_upload};
+
+/// Sent along with cached resources served while their backend is unavailable.
+const STALE_WARNING: &str = "110 - \"Response is Stale\"";
+
+#[derive(Responder)]
+pub enum RepositoryResource {
+    File(File),
+    /// A cached resource, possibly expired, served because its backend failed.
+    Stale(File, Header<'static>),
+    Listing(Json<Vec<ResourceEntry>>),
+    ListingPage(RawHtml<String>),
+}
+
+#[derive(Responder)]
+pub enum PublishResponse {
+    Empty(()),
+    /// Uploading a Gradle module responds with how its files compare to those deployed.
+    Module(Json<ModuleReport>),
+    /// The file is held until its signature is deployed.
+    Held(status::Accepted<()>),
+    /// A held Gradle module, along with how its files compare to those deployed.
+    HeldModule(status::Accepted<Json<ModuleReport>>),
+}
+
+impl AuditStatus for PublishResponse {
+    fn status(&self) -> u16 {
+        match self {
+            PublishResponse::Empty(_) | PublishResponse::Module(_) => 200,
+            PublishResponse::Held(_) | PublishResponse::HeldModule(_) => 202,
+        }
+    }
+}
 
 #[get("/<repository>/<path..>", rank = 3)]
 pub async fn get_repository_resource(
     repository: &str,
     path: PathBuf,
+    uri: &Origin<'_>,
+    accept: Option<&Accept>,
     resource_access: &ManagedResourceAccess,
-    configuration: &State<ARProxyConfiguration>,
-) -> Result<File, status::Custom<Json<BasicError>>> {
-    let repository = configuration.repositories.get(repository).ok_or(
+    resource_cache: &State<Option<Arc<ResourceCache>>>,
+    configuration: &ARProxyConfiguration,
+) -> Result<RepositoryResource, status::Custom<Json<BasicError>>> {
+    let public_name = repository;
+    let repository = &configuration.repositories.get(repository).ok_or(
         BasicError::from(Box::new(RepositoryNotFound(repository.to_string())))
-    )?;
-
-    info!("Fetching resource: '{}' from repository: '{}'", path.to_str().unwrap(), repository);
+    )?.id;
 
     let resource_path = PathBuf::new()
         .join(repository)
-        .join(path);
+        .join(&path);
 
     debug!("Full resource path: '{}'", resource_path.to_str().unwrap());
 
-    let arc = Arc::clone(&resource_access);
-    let resource_path = arc.get_resource(
-        resource_path
-    ).await.map_err(|e| BasicError::from(e))?;
+    let arc = Arc::clone(resource_access);
+
+    if path.as_os_str().is_empty() || uri.path().ends_with('/') {
+        info!("Listing directory: '{}' in repository: '{}'", path.to_str().unwrap(), repository);
+
+        let entries = arc.list(
+            resource_path
+        ).await.map_err(BasicError::from)?;
+
+        if entries.is_empty() && !path.as_os_str().is_empty() {
+            return Err(BasicError::from(Box::new(ResourceNotFound(path.to_str().unwrap().to_string()))));
+        }
+
+        return if accept.map(|accept| accept.preferred().is_json()).unwrap_or(false) {
+            Ok(RepositoryResource::Listing(Json(entries)))
+        } else {
+            Ok(RepositoryResource::ListingPage(RawHtml(render_listing(public_name, &path, &entries))))
+        };
+    }
+
+    info!("Fetching resource: '{}' from repository: '{}'", path.to_str().unwrap(), repository);
+
+    let file = match arc.get_resource(resource_path.clone()).await {
+        Ok(file) => file,
+        // Builds keep working from the cache while the backend is down.
+        Err(err) if err.status() >= 500 => match resource_cache.as_deref().and_then(|cache| cache.get_stale(&resource_path)) {
+            Some(stale) => {
+                warn!("Serving stale resource: '{}' from cache, {}", resource_path.to_str().unwrap(), err.message());
+
+                let file = File::open(stale)
+                    .map_err(|e| BasicError::from(Box::new(IOError(e))))?;
 
-    let file = File::open(resource_path)
+                return Ok(RepositoryResource::Stale(file, Header::new("Warning", STALE_WARNING)));
+            }
+            None => return Err(BasicError::from(err))
+        },
+        Err(err) => return Err(BasicError::from(err))
+    };
+
+    let file = File::open(file)
         .map_err(|e| BasicError::from(Box::new(IOError(e))))?;
 
-    Ok(file)
+    Ok(RepositoryResource::File(file))
 }
 
 
+#[allow(clippy::too_many_arguments)]
 #[put("/<repository>/<path..>", data = "<body_file>")]
-pub async fn put_repository_resource<'a>(
-    _name: &ApiCredentials,
+pub async fn put_repository_resource(
+    deployer: Deployer<'_>,
     repository: &str,
     path: PathBuf,
-    mut body_file: TempFile<'_>,
+    body_file: TempFile<'_>,
+    client_ip: Option<IpAddr>,
     resource_access: &ManagedResourceAccess,
-    configuration: &State<ARProxyConfiguration>
-) -> Result<(), status::Custom<Json<BasicError>>> {
-    let repository = configuration.repositories.get(repository).ok_or(
-        BasicError::from(Box::new(RepositoryNotFound(repository.to_string())))
-    )?;
+    index: &State<Arc<SearchIndex>>,
+    held: &State<HeldUploads>,
+    audit: &State<AuditLog>,
+    configuration: &ARProxyConfiguration
+) -> Result<PublishResponse, status::Custom<Json<BasicError>>> {
+    let mut digest = None;
+    let mut signature = None;
 
-    info!("Putting resource: '{}' to repository: '{}'", path.to_str().unwrap(), repository);
+    let result = match receive(body_file).await {
+        Ok(file) => {
+            if audit.is_enabled() {
+                digest = file_digest(file.path()).ok();
+            }
 
-    let resource_path = PathBuf::new()
-        .join(repository)
-        .join(path);
+            publish(&deployer.0.user, repository, &path, file, resource_access, index, held, &mut signature, configuration).await
+        }
+        Err(err) => Err(err)
+    };
 
-    debug!("Full resource path: '{}'", resource_path.to_str().unwrap());
+    audit.record(AuditEvent {
+        principal: deployer.0.user.clone(),
+        source_ip: client_ip.map(|ip| ip.to_string()),
+        action: AuditAction::Put,
+        repository: repository.to_string(),
+        path: path.to_str().unwrap().to_string(),
+        size: digest.as_ref().map(|(size, _)| *size),
+        sha256: digest.map(|(_, sha256)| sha256),
+        signature,
+        status: 200,
+        error: None,
+    }.outcome(&result)).await;
+
+    result
+}
 
+/// Writes the uploaded body to a temporary file.
+async fn receive(mut body_file: TempFile<'_>) -> Result<NamedTempFile, status::Custom<Json<BasicError>>> {
     let file = NamedTempFile::new()
         .map_err(|err| status::Custom(
             Status::InternalServerError,
@@ -80,12 +186,187 @@ pub async fn put_repository_resource<'a>(
             Json::<BasicError>(err.into()),
         ))?;
 
-    Arc::clone(&resource_access).put_resource(
-        resource_path,
+    Ok(file)
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn publish(
+    principal: &str,
+    repository: &str,
+    path: &Path,
+    file: NamedTempFile,
+    resource_access: &ManagedResourceAccess,
+    index: &State<Arc<SearchIndex>>,
+    held: &HeldUploads,
+    signature: &mut Option<String>,
+    configuration: &ARProxyConfiguration
+) -> Result<PublishResponse, status::Custom<Json<BasicError>>> {
+    let public_name = repository;
+    let repository = configuration.repositories.get(repository).ok_or(
+        BasicError::from(Box::new(RepositoryNotFound(repository.to_string())))
+    )?;
+
+    info!("Putting resource: '{}' to repository: '{}'", path.to_str().unwrap(), repository.id);
+
+    let resource_path = PathBuf::new()
+        .join(&repository.id)
+        .join(path);
+
+    debug!("Full resource path: '{}'", resource_path.to_str().unwrap());
+
+    if !repository.deploy_policy.allows_redeploy(path) && Arc::clone(resource_access)
+        .exists(resource_path.clone())
+        .await
+        .map_err(BasicError::from)? {
+        return Err(BasicError::from(Box::new(RedeployNotAllowed(path.to_str().unwrap().to_string()))));
+    }
+
+    if path.extension().map(|extension| extension == "pom").unwrap_or(false)
+        && repository.pom_validation != PomValidation::None {
+        let data = fs::read(file.path())
+            .map_err(|e| BasicError::from(Box::new(IOError(e))))?;
+
+        let problems = match path.parent().and_then(Coordinates::from_version_path) {
+            Some(coordinates) => validate_pom(String::from_utf8_lossy(&data).as_ref(), &coordinates, repository.pom_validation),
+            None => vec!["Not deployed to a 'group/artifact/version' directory".to_string()]
+        };
+
+        if !problems.is_empty() {
+            return Err(BasicError::from(Box::new(InvalidPom(path.to_str().unwrap().to_string(), problems))));
+        }
+    }
+
+    if repository.signer.is_some() && is_signature_file(path) {
+        info!("Ignoring signature: '{}', repository: '{}' is signed by ARP", path.to_str().unwrap(), repository.id);
+        return Ok(PublishResponse::Empty(()));
+    }
+
+    // Repositories signed by ARP don't take signatures from clients, so nothing is held.
+    let signature_check = match (&repository.keyring, &repository.signer) {
+        (Some(keyring), None) => verify_upload(resource_access.as_ref(), keyring, held, principal, &resource_path, file.path())
+            .await
+            .map_err(BasicError::from)?,
+        _ => SignatureCheck::Accepted
+    };
+    *signature = signature_check.describe();
+
+    let signed_data = match (&repository.signer, requires_signature(path)) {
+        (Some(_), true) => Some(fs::read(file.path()).map_err(|e| BasicError::from(Box::new(IOError(e))))?),
+        _ => None
+    };
+
+    let module_report = match is_gradle_module(path) {
+        true => {
+            let data = fs::read(file.path())
+                .map_err(|e| BasicError::from(Box::new(IOError(e))))?;
+
+            let module = GradleModule::parse(&data)
+                .map_err(|err| BasicError::from(Box::new(InvalidModule(path.to_str().unwrap().to_string(), err))))?;
+
+            Some(module.validate(
+                resource_access.as_ref(),
+                path,
+                resource_path.parent().unwrap(),
+            ).await.map_err(BasicError::from)?)
+        }
+        false => None
+    };
+
+    if signature_check == SignatureCheck::Hold {
+        info!("Holding: '{}' until its signature is deployed", resource_path.to_str().unwrap());
+        held.hold(principal, &resource_path, file.path()).map_err(BasicError::from)?;
+
+        return Ok(match module_report {
+            Some(report) => PublishResponse::HeldModule(status::Accepted(Json(report))),
+            None => PublishResponse::Held(status::Accepted(()))
+        });
+    }
+
+    // The signed file (if it was held) is published before its signature.
+    if let SignatureCheck::Verified(_) = signature_check {
+        held.publish(resource_access.as_ref(), principal, &resource_path.with_extension(""))
+            .await
+            .map_err(BasicError::from)?;
+    }
+
+    Arc::clone(resource_access).put_resource(
+        resource_path.clone(),
         file.into_temp_path(),
-    ).await.map_err(|e|
-    BasicError::from(e)
-    )
+    ).await.map_err(BasicError::from)?;
+
+    if let (Some(signer), Some(data)) = (&repository.signer, signed_data) {
+        signer.publish_signature(resource_access.as_ref(), &resource_path, &data)
+            .await
+            .map_err(BasicError::from)?;
+    }
+
+    if let Some(coordinates) = Coordinates::from_file_path(path) {
+        index.record(public_name, coordinates);
+    }
+
+    Ok(match module_report {
+        Some(report) => PublishResponse::Module(Json(report)),
+        None => PublishResponse::Empty(())
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+#[delete("/<repository>/<path..>")]
+pub async fn delete_repository_resource(
+    deleter: Deleter<'_>,
+    repository: &str,
+    path: PathBuf,
+    client_ip: Option<IpAddr>,
+    resource_access: &ManagedResourceAccess,
+    index: &State<Arc<SearchIndex>>,
+    audit: &State<AuditLog>,
+    configuration: &ARProxyConfiguration,
+) -> Result<(), status::Custom<Json<BasicError>>> {
+    let result = delete(repository, &path, resource_access, index, configuration).await;
+
+    audit.record(AuditEvent {
+        principal: deleter.0.user.clone(),
+        source_ip: client_ip.map(|ip| ip.to_string()),
+        action: AuditAction::Delete,
+        repository: repository.to_string(),
+        path: path.to_str().unwrap().to_string(),
+        size: None,
+        sha256: None,
+        signature: None,
+        status: 200,
+        error: None,
+    }.outcome(&result)).await;
+
+    result
+}
+
+async fn delete(
+    repository: &str,
+    path: &Path,
+    resource_access: &ManagedResourceAccess,
+    index: &State<Arc<SearchIndex>>,
+    configuration: &ARProxyConfiguration,
+) -> Result<(), status::Custom<Json<BasicError>>> {
+    let public_name = repository;
+    let repository = &configuration.repositories.get(repository).ok_or(
+        BasicError::from(Box::new(RepositoryNotFound(repository.to_string())))
+    )?.id;
+
+    info!("Deleting resource: '{}' from repository: '{}'", path.to_str().unwrap(), repository);
+
+    let resource_path = PathBuf::new()
+        .join(repository)
+        .join(path);
+
+    debug!("Full resource path: '{}'", resource_path.to_str().unwrap());
+
+    Arc::clone(resource_access).delete_resource(
+        resource_path
+    ).await.map_err(BasicError::from)?;
+
+    index.forget(public_name, path);
+
+    Ok(())
 }
 
 #[get("/")]
@@ -101,10 +382,10 @@ pub struct AuthRequestResponse {
 
 #[get("/authenticated", rank = 2)]
 pub async fn un_authenticated() -> Unauthorized<AuthRequestResponse> {
-    return Unauthorized(AuthRequestResponse {
+    Unauthorized(AuthRequestResponse {
         body: "Please authenticate".to_string(),
         more: Header::new("WWW-Authenticate", r#"Basic realm="""#),
-    });
+    })
 }
 
 #[get("/authenticated", rank = 1)]
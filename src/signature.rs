@@ -0,0 +1,739 @@
+use std::collections::HashSet;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use log::{info, warn};
+use pgp::composed::{ArmorOptions, Deserializable, DetachedSignature, SignedPublicKey, SignedPublicSubKey, SignedSecretKey};
+use pgp::crypto::hash::HashAlgorithm;
+use pgp::packet::{Signature, SignatureType, SubpacketData};
+use pgp::types::{KeyDetails, Password, SigningKey, Timestamp};
+use percent_encoding::{NON_ALPHANUMERIC, utf8_percent_encode};
+use rand::thread_rng;
+use tempfile::NamedTempFile;
+
+use crate::checksum::{checksums, is_checksum};
+use crate::err::{HoldLimitExceeded, InvalidSignature, IOError, SerializableError, SigningFailed, UnsignedArtifacts};
+use crate::maven::is_maven_metadata;
+use crate::maven::metadata::{fetch_metadata, MavenMetadata};
+use crate::promote::{PendingFiles, temp_file};
+use crate::resource_access::ResourceAccess;
+
+/// The public keys of the publishers trusted to sign artifacts in a repository.
+pub struct Keyring {
+    keys: Vec<SignedPublicKey>,
+}
+
+/// A private key used to sign artifacts as they are deployed, so publishers don't need it.
+pub struct Signer {
+    key: SignedSecretKey,
+    passphrase: String,
+}
+
+/// Whether the path points at a detached signature, eg. 'lib-1.0.jar.asc'.
+pub fn is_signature(path: &Path) -> bool {
+    path.extension()
+        .map(|extension| extension == "asc")
+        .unwrap_or(false)
+}
+
+/// Whether the path points at a signature or one of a signature's checksums.
+pub fn is_signature_file(path: &Path) -> bool {
+    is_signature(path) || (is_checksum(path) && is_signature(&path.with_extension("")))
+}
+
+/// Whether a file must be accompanied by a signature. Metadata, checksums and signatures
+/// themselves aren't signed.
+pub fn requires_signature(path: &Path) -> bool {
+    !is_maven_metadata(path) && !is_checksum(path) && !is_signature(path)
+}
+
+impl Keyring {
+    /// Parses every (armored or binary) public key in the data. Keys whose self signatures
+    /// don't verify are left out, as are subkeys whose binding signatures don't verify.
+    pub fn parse(data: &[u8]) -> Result<Keyring, String> {
+        let (keys, _) = SignedPublicKey::from_reader_many(data)
+            .map_err(|err| err.to_string())?;
+
+        let mut keyring = Keyring {
+            keys: Vec::new(),
+        };
+
+        for key in keys {
+            let mut key = key.map_err(|err| err.to_string())?;
+
+            if let Err(err) = key.details.verify_bindings(&key.primary_key) {
+                warn!("Ignoring key: '{}' with invalid bindings, {}", key.fingerprint(), err);
+                continue;
+            }
+
+            let primary_key = key.primary_key.clone();
+            key.public_subkeys.retain(|subkey| match subkey.verify_bindings(&primary_key) {
+                Ok(()) => true,
+                Err(err) => {
+                    warn!("Ignoring subkey: '{}' with invalid bindings, {}", subkey.key.fingerprint(), err);
+                    false
+                }
+            });
+
+            keyring.keys.push(key);
+        }
+
+        if keyring.keys.is_empty() {
+            return Err("No valid public keys found".to_string());
+        }
+
+        Ok(keyring)
+    }
+
+    /// The fingerprints of the primary keys.
+    pub fn fingerprints(&self) -> Vec<String> {
+        self.keys.iter()
+            .map(|key| key.fingerprint().to_string())
+            .collect()
+    }
+
+    pub fn load(path: &Path) -> Result<Keyring, String> {
+        Keyring::parse(&fs::read(path).map_err(|err| err.to_string())?)
+    }
+
+    /// Verifies a detached signature of the data, returning the fingerprint of the primary key
+    /// of whoever signed it. The key (and subkey) which made it must not be revoked, and must not
+    /// have expired by the time the signature was made.
+    pub fn verify(&self, signature: &[u8], data: &[u8]) -> Result<String, String> {
+        let (signature, _) = DetachedSignature::from_reader_single(signature)
+            .map_err(|err| format!("Unparsable signature, {}", err))?;
+
+        let signed_at = signature.signature.created().unwrap_or_else(Timestamp::now);
+
+        for key in &self.keys {
+            let validity = if signature.verify(&key.primary_key, data).is_ok() {
+                primary_validity(key, signed_at)
+                    .and_then(|()| primary_signing(key))
+            } else {
+                match key.public_subkeys.iter().find(|subkey| signature.verify(&subkey.key, data).is_ok()) {
+                    Some(subkey) => primary_validity(key, signed_at)
+                        .and_then(|()| subkey_validity(subkey, signed_at)),
+                    None => continue
+                }
+            };
+
+            return validity.map(|()| key.fingerprint().to_string());
+        }
+
+        Err("Not signed by a trusted key".to_string())
+    }
+}
+
+/// The newest of the signatures, which is the one in effect.
+fn newest<'a>(signatures: impl Iterator<Item = &'a Signature>) -> Option<&'a Signature> {
+    signatures.max_by_key(|signature| signature.created().map(Timestamp::as_secs))
+}
+
+/// Whether a key created at `created` had expired at `at`, according to its newest self signature.
+fn expired(created: Timestamp, self_signature: Option<&Signature>, at: Timestamp) -> bool {
+    self_signature
+        .and_then(|signature| signature.key_expiration_time())
+        .filter(|expiration| expiration.as_secs() != 0)
+        .map(|expiration| u64::from(created.as_secs()) + u64::from(expiration.as_secs()) <= u64::from(at.as_secs()))
+        .unwrap_or(false)
+}
+
+/// The signatures the primary key made over its own user ids and itself.
+fn self_signatures(key: &SignedPublicKey) -> impl Iterator<Item = &Signature> {
+    key.details.users.iter()
+        .flat_map(|user| user.signatures.iter())
+        .chain(key.details.direct_signatures.iter())
+        .filter(|signature| signature.issuer_fingerprint().contains(&&key.fingerprint())
+            || signature.issuer_key_id().contains(&&key.legacy_key_id()))
+}
+
+fn primary_validity(key: &SignedPublicKey, at: Timestamp) -> Result<(), String> {
+    if !key.details.revocation_signatures.is_empty() {
+        return Err(format!("Signed by revoked key: '{}'", key.fingerprint()));
+    }
+
+    if expired(key.primary_key.created_at(), newest(self_signatures(key)), at) {
+        return Err(format!("Signed by key: '{}' after it expired", key.fingerprint()));
+    }
+
+    Ok(())
+}
+
+/// Primary keys are only trusted to sign when the newest self signature giving their key flags
+/// allows it, the same as subkeys.
+fn primary_signing(key: &SignedPublicKey) -> Result<(), String> {
+    let flags = newest(self_signatures(key)
+        .filter(|signature| signature.config()
+            .map(|config| config.hashed_subpackets().any(|subpacket| matches!(subpacket.data, SubpacketData::KeyFlags(_))))
+            .unwrap_or(false)));
+
+    if !flags.map(|signature| signature.key_flags().sign()).unwrap_or(false) {
+        return Err(format!("Signed by key: '{}' which isn't allowed to sign", key.fingerprint()));
+    }
+
+    Ok(())
+}
+
+/// Subkeys are only trusted to sign while bound for signing by their newest binding signature.
+fn subkey_validity(subkey: &SignedPublicSubKey, at: Timestamp) -> Result<(), String> {
+    let fingerprint = subkey.key.fingerprint();
+
+    if subkey.signatures.iter().any(|signature| signature.typ() == Some(SignatureType::SubkeyRevocation)) {
+        return Err(format!("Signed by revoked subkey: '{}'", fingerprint));
+    }
+
+    let binding = newest(subkey.signatures.iter()
+        .filter(|signature| signature.typ() == Some(SignatureType::SubkeyBinding)));
+
+    if !binding.map(|binding| binding.key_flags().sign()).unwrap_or(false) {
+        return Err(format!("Signed by subkey: '{}' which isn't bound for signing", fingerprint));
+    }
+
+    if expired(subkey.key.created_at(), binding, at) {
+        return Err(format!("Signed by subkey: '{}' after it expired", fingerprint));
+    }
+
+    Ok(())
+}
+
+impl Signer {
+    /// Parses an (armored or binary) secret key, checking it can be unlocked with the passphrase.
+    pub fn parse(data: &[u8], passphrase: &str) -> Result<Signer, String> {
+        let (key, _) = SignedSecretKey::from_reader_single(data)
+            .map_err(|err| err.to_string())?;
+
+        key.verify_bindings().map_err(|err| err.to_string())?;
+
+        let signer = Signer {
+            key,
+            passphrase: passphrase.to_string(),
+        };
+
+        signer.sign(&[])?;
+
+        Ok(signer)
+    }
+
+    /// Prefers a subkey bound for signing, falling back to the primary key.
+    fn signing_key(&self) -> &dyn SigningKey {
+        self.key.secret_subkeys.iter()
+            .find(|subkey| subkey.signatures.iter().any(|signature| signature.key_flags().sign()))
+            .map(|subkey| &subkey.key as &dyn SigningKey)
+            .unwrap_or(&self.key.primary_key)
+    }
+
+    pub fn fingerprint(&self) -> String {
+        self.key.fingerprint().to_string()
+    }
+
+    /// Creates an armored detached signature of the data.
+    pub fn sign(&self, data: &[u8]) -> Result<String, String> {
+        DetachedSignature::sign_binary_data(
+            thread_rng(),
+            &Box::new(self.signing_key()),
+            &Password::from(self.passphrase.as_str()),
+            HashAlgorithm::Sha256,
+            data,
+        )
+            .and_then(|signature| signature.to_armored_string(ArmorOptions::default()))
+            .map_err(|err| err.to_string())
+    }
+
+    /// The signature of a file, followed by the signature's checksums, ready to be deployed.
+    pub fn signature_files(&self, path: &Path, data: &[u8]) -> Result<PendingFiles, Box<dyn SerializableError>> {
+        let signature_path = path.with_file_name(format!("{}.asc", path.file_name().unwrap().to_str().unwrap()));
+
+        let signature = self.sign(data)
+            .map_err(|err| Box::new(SigningFailed(path.to_str().unwrap().to_string(), err)) as Box<dyn SerializableError>)?
+            .into_bytes();
+
+        let mut files = checksums(&signature)
+            .into_iter()
+            .map(|(extension, sum)| (
+                signature_path.with_file_name(format!("{}.{}", signature_path.file_name().unwrap().to_str().unwrap(), extension)),
+                sum.into_bytes()
+            ))
+            .collect::<PendingFiles>();
+        files.insert(0, (signature_path, signature));
+
+        Ok(files)
+    }
+
+    /// Signs a deployed file, publishing the signature (and its checksums) next to it.
+    pub async fn publish_signature(
+        &self,
+        resource_access: &(dyn ResourceAccess + Send + Sync),
+        resource_path: &Path,
+        data: &[u8],
+    ) -> Result<(), Box<dyn SerializableError>> {
+        for (path, data) in self.signature_files(resource_path, data)? {
+            resource_access.put_resource(path, temp_file(&data)?).await?;
+        }
+
+        info!("Signed: '{}' with key: '{}'", resource_path.to_str().unwrap(), self.fingerprint());
+
+        Ok(())
+    }
+}
+
+/// How long a file is held waiting for its signature before it is dropped.
+const HOLD_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// Files deployed to a repository which requires signed artifacts, held back (along with their
+/// checksums) until a valid signature of them is deployed, so unsigned files are never published.
+/// Each principal's files are kept in a directory of their own, and only a signature deployed by
+/// the same principal releases them. Instances sharing the directory (eg. on a shared volume)
+/// share the held files.
+pub struct HeldUploads {
+    directory: PathBuf,
+    /// Bytes each principal may hold at once.
+    max_size: u64,
+}
+
+/// Drops the files held in the directory for longer than the TTL, returning the size of the rest.
+/// Files another instance removes meanwhile are skipped.
+fn prune(directory: &Path) -> io::Result<u64> {
+    let mut size = 0;
+
+    for entry in fs::read_dir(directory)? {
+        let entry = entry?;
+
+        let metadata = match entry.metadata() {
+            Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+            metadata => metadata?
+        };
+
+        if metadata.is_dir() {
+            size += prune(&entry.path())?;
+        } else if metadata.modified()?.elapsed().unwrap_or_default() < HOLD_TTL {
+            size += metadata.len();
+        } else {
+            warn!("Dropping: '{}', no signature was deployed for it", entry.path().to_str().unwrap());
+            remove(&entry.path())?;
+        }
+    }
+
+    Ok(size)
+}
+
+fn remove(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+        result => result
+    }
+}
+
+impl HeldUploads {
+    /// Holds files in the directory, dropping those left in it for longer than the TTL.
+    pub fn open(directory: &Path, max_size: u64) -> io::Result<HeldUploads> {
+        fs::create_dir_all(directory)?;
+        prune(directory)?;
+
+        Ok(HeldUploads {
+            directory: directory.to_path_buf(),
+            max_size,
+        })
+    }
+
+    fn principal_directory(&self, principal: &str) -> PathBuf {
+        self.directory.join(utf8_percent_encode(principal, NON_ALPHANUMERIC).to_string())
+    }
+
+    fn location(&self, principal: &str, resource_path: &Path) -> PathBuf {
+        self.principal_directory(principal).join(resource_path)
+    }
+
+    /// Holds a copy of the file, refusing it when the principal would hold more than the limit.
+    /// Holding a file again replaces the one held.
+    pub fn hold(&self, principal: &str, resource_path: &Path, file: &Path) -> Result<(), Box<dyn SerializableError>> {
+        let io_error = |e: io::Error| Box::new(IOError(e)) as Box<dyn SerializableError>;
+
+        let directory = self.principal_directory(principal);
+        let location = self.location(principal, resource_path);
+
+        let held = match directory.is_dir() {
+            true => prune(&directory).map_err(io_error)?,
+            false => 0
+        };
+        let replaced = fs::metadata(&location).map(|metadata| metadata.len()).unwrap_or(0);
+        let size = fs::metadata(file).map_err(io_error)?.len();
+
+        if held - replaced.min(held) + size > self.max_size {
+            return Err(Box::new(HoldLimitExceeded(principal.to_string(), self.max_size)));
+        }
+
+        let parent = location.parent().unwrap();
+        fs::create_dir_all(parent).map_err(io_error)?;
+
+        // Copied next to its destination first, so another instance never reads half of it.
+        let mut copy = NamedTempFile::new_in(parent).map_err(io_error)?;
+        io::copy(&mut File::open(file).map_err(io_error)?, &mut copy).map_err(io_error)?;
+        copy.persist(&location).map_err(|err| io_error(err.error))?;
+
+        Ok(())
+    }
+
+    pub fn is_held(&self, principal: &str, resource_path: &Path) -> bool {
+        self.location(principal, resource_path).is_file()
+    }
+
+    fn read(&self, principal: &str, resource_path: &Path) -> Option<io::Result<Vec<u8>>> {
+        let location = self.location(principal, resource_path);
+
+        location.is_file().then(|| fs::read(location))
+    }
+
+    /// Publishes a held file once it is signed, followed by its held checksums. Each is only
+    /// dropped once written, so if any fails to be, it and the files after it stay held.
+    pub async fn publish(
+        &self,
+        resource_access: &(dyn ResourceAccess + Send + Sync),
+        principal: &str,
+        resource_path: &Path,
+    ) -> Result<(), Box<dyn SerializableError>> {
+        let io_error = |e: io::Error| Box::new(IOError(e)) as Box<dyn SerializableError>;
+
+        let name = resource_path.file_name().unwrap();
+        let directory = self.location(principal, resource_path.parent().unwrap());
+
+        let mut paths = match fs::read_dir(&directory) {
+            Ok(entries) => entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| resource_path.with_file_name(entry.file_name()))
+                .filter(|path| path.file_name().unwrap() == name || (is_checksum(path) && path.with_extension("").file_name().unwrap() == name))
+                .collect::<Vec<PathBuf>>(),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(err) => return Err(io_error(err))
+        };
+        paths.sort_by_key(|path| path != resource_path);
+
+        for path in paths {
+            let location = self.location(principal, &path);
+
+            let file = NamedTempFile::new()
+                .and_then(|file| fs::copy(&location, file.path()).map(|_| file.into_temp_path()))
+                .map_err(io_error)?;
+
+            resource_access.put_resource(path.clone(), file).await?;
+            remove(&location).map_err(io_error)?;
+
+            info!("Published: '{}' now that it is signed", path.to_str().unwrap());
+        }
+
+        Ok(())
+    }
+}
+
+/// What became of an upload to a repository which requires signed artifacts.
+#[derive(Debug, PartialEq)]
+pub enum SignatureCheck {
+    /// The file can be written as is, eg. metadata.
+    Accepted,
+    /// The uploaded signature verified, against a key with this fingerprint.
+    Verified(String),
+    /// The file must be held until its signature is deployed.
+    Hold,
+}
+
+impl SignatureCheck {
+    /// How the check is recorded in the audit log.
+    pub fn describe(&self) -> Option<String> {
+        match self {
+            SignatureCheck::Accepted => None,
+            SignatureCheck::Verified(fingerprint) => Some(format!("verified, key: {}", fingerprint)),
+            SignatureCheck::Hold => Some("held until signed".to_string()),
+        }
+    }
+}
+
+/// Checks an upload to a repository which requires signed artifacts. Files which need a signature
+/// (and checksums of them) are held until it is deployed, and a signature must verify against the
+/// file it signs, held for the same principal or already deployed. Artifact metadata may only advertise new release
+/// versions once every one of their files has a signature.
+pub async fn verify_upload(
+    resource_access: &(dyn ResourceAccess + Send + Sync),
+    keyring: &Keyring,
+    held: &HeldUploads,
+    principal: &str,
+    resource_path: &Path,
+    file: &Path,
+) -> Result<SignatureCheck, Box<dyn SerializableError>> {
+    let read = |path: &Path| fs::read(path)
+        .map_err(|e| Box::new(IOError(e)) as Box<dyn SerializableError>);
+
+    if is_signature(resource_path) {
+        let signed_path = resource_path.with_extension("");
+        let invalid = |reason: String| Box::new(InvalidSignature(resource_path.to_str().unwrap().to_string(), reason)) as Box<dyn SerializableError>;
+
+        let data = match held.read(principal, &signed_path) {
+            Some(data) => data.map_err(|e| Box::new(IOError(e)) as Box<dyn SerializableError>)?,
+            None if resource_access.exists(signed_path.clone()).await? => read(&resource_access.get_resource(signed_path).await?)?,
+            None => return Err(invalid("The signed file must be deployed before its signature".to_string()))
+        };
+
+        let fingerprint = keyring.verify(&read(file)?, &data).map_err(invalid)?;
+        info!("Verified signature: '{}' made by key: '{}'", resource_path.to_str().unwrap(), fingerprint);
+
+        return Ok(SignatureCheck::Verified(fingerprint));
+    }
+
+    if requires_signature(resource_path) || (is_checksum(resource_path) && held.is_held(principal, &resource_path.with_extension(""))) {
+        return Ok(SignatureCheck::Hold);
+    }
+
+    if resource_path.file_name().map(|name| name != "maven-metadata.xml").unwrap_or(true) {
+        return Ok(SignatureCheck::Accepted);
+    }
+
+    let metadata = MavenMetadata::parse(String::from_utf8_lossy(&read(file)?).as_ref())
+        .unwrap_or_default();
+
+    let existing = if resource_access.exists(resource_path.to_path_buf()).await? {
+        fetch_metadata(resource_access, resource_path.to_path_buf()).await?.versions
+    } else {
+        Vec::new()
+    };
+
+    for version in metadata.versions.iter()
+        .filter(|version| !version.ends_with("-SNAPSHOT") && !existing.contains(version)) {
+        let directory = resource_path.with_file_name(version);
+
+        let unsigned = unsigned_files(resource_access, &directory).await?;
+        if !unsigned.is_empty() {
+            return Err(Box::new(UnsignedArtifacts(directory.to_str().unwrap().to_string(), unsigned)));
+        }
+    }
+
+    Ok(SignatureCheck::Accepted)
+}
+
+/// The files in a version directory which are missing a signature.
+pub async fn unsigned_files(
+    resource_access: &(dyn ResourceAccess + Send + Sync),
+    directory: &Path,
+) -> Result<Vec<String>, Box<dyn SerializableError>> {
+    let names = resource_access.list(directory.to_path_buf())
+        .await?
+        .into_iter()
+        .filter(|entry| !entry.directory)
+        .map(|entry| entry.name)
+        .collect::<HashSet<String>>();
+
+    let mut unsigned = names.iter()
+        .filter(|name| requires_signature(&PathBuf::from(name)) && !names.contains(&format!("{}.asc", name)))
+        .cloned()
+        .collect::<Vec<String>>();
+    unsigned.sort();
+
+    Ok(unsigned)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::path::Path;
+
+    use pgp::composed::{ArmorOptions, DetachedSignature, KeyType, SecretKeyParamsBuilder, SignedPublicKey, SignedSecretKey, SubkeyParamsBuilder};
+    use pgp::crypto::hash::HashAlgorithm;
+    use pgp::packet::{SignatureConfig, SignatureType, Subpacket, SubpacketData};
+    use pgp::types::{Duration, KeyDetails, Password, Timestamp};
+    use rand::thread_rng;
+
+    use crate::resource_access::memory::MemoryResourceAccess;
+    use crate::signature::{HeldUploads, Keyring, SignatureCheck, Signer, verify_upload};
+
+    pub(crate) fn secret_key() -> SignedSecretKey {
+        SecretKeyParamsBuilder::default()
+            .key_type(KeyType::Ed25519Legacy)
+            .can_certify(true)
+            .can_sign(true)
+            .primary_user_id("Publisher <publisher@example.com>".into())
+            .build()
+            .unwrap()
+            .generate(thread_rng())
+            .unwrap()
+    }
+
+    pub(crate) fn sign(key: &SignedSecretKey, data: &[u8]) -> String {
+        DetachedSignature::sign_binary_data(thread_rng(), &key.primary_key, &Password::empty(), HashAlgorithm::Sha256, data)
+            .unwrap()
+            .to_armored_string(ArmorOptions::default())
+            .unwrap()
+    }
+
+    fn keyring(key: &SignedSecretKey) -> Keyring {
+        Keyring::parse(SignedPublicKey::from(key.clone())
+            .to_armored_bytes(ArmorOptions::default())
+            .unwrap()
+            .as_slice()).unwrap()
+    }
+
+    #[test]
+    fn test_verify() {
+        let trusted = secret_key();
+        let other = secret_key();
+        let keyring = keyring(&trusted);
+
+        assert!(keyring.verify(sign(&trusted, b"jar").as_bytes(), b"jar").is_ok());
+        assert!(keyring.verify(sign(&trusted, b"jar").as_bytes(), b"changed").is_err());
+        assert!(keyring.verify(sign(&other, b"jar").as_bytes(), b"jar").is_err());
+        assert!(keyring.verify(b"not a signature", b"jar").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_publish_signature() {
+        let key = secret_key();
+        let signer = Signer::parse(key.to_armored_bytes(ArmorOptions::default()).unwrap().as_slice(), "").unwrap();
+
+        let access = MemoryResourceAccess::default();
+        signer.publish_signature(&access, Path::new("rel/com/example/lib/1.0/lib-1.0.jar"), b"jar").await.unwrap();
+
+        let signature = access.read("rel/com/example/lib/1.0/lib-1.0.jar.asc").unwrap();
+        assert!(keyring(&key).verify(signature.as_bytes(), b"jar").is_ok());
+        assert!(access.read("rel/com/example/lib/1.0/lib-1.0.jar.asc.sha1").is_some());
+    }
+
+    /// Adds a self signature, made by the primary key, to a copy of its public key.
+    fn self_signed(key: &SignedSecretKey, typ: SignatureType, created: Timestamp, extra: Vec<Subpacket>) -> SignedPublicKey {
+        let mut public = SignedPublicKey::from(key.clone());
+
+        let mut config = SignatureConfig::v4(typ, key.primary_key.algorithm(), HashAlgorithm::Sha256);
+        config.hashed_subpackets = vec![
+            Subpacket::regular(SubpacketData::SignatureCreationTime(created)).unwrap(),
+            Subpacket::regular(SubpacketData::IssuerFingerprint(key.fingerprint())).unwrap(),
+        ];
+        config.hashed_subpackets.extend(extra);
+
+        match typ {
+            SignatureType::SubkeyRevocation => {
+                let signature = config.sign_subkey_binding(&key.primary_key, &public.primary_key, &Password::empty(), &public.public_subkeys[0].key).unwrap();
+                public.public_subkeys[0].signatures.push(signature);
+            }
+            _ => {
+                let signature = config.sign_key(&key.primary_key, &Password::empty(), &public.primary_key).unwrap();
+                public.details.direct_signatures.push(signature);
+            }
+        }
+
+        public
+    }
+
+    #[test]
+    fn test_verify_key_validity() {
+        let key = SecretKeyParamsBuilder::default()
+            .key_type(KeyType::Ed25519Legacy)
+            .can_certify(true)
+            .created_at(Timestamp::from_secs(Timestamp::now().as_secs() - 2 * 24 * 60 * 60))
+            .primary_user_id("Publisher <publisher@example.com>".into())
+            .subkeys(vec![SubkeyParamsBuilder::default()
+                .key_type(KeyType::Ed25519Legacy)
+                .can_sign(true)
+                .build()
+                .unwrap()])
+            .build()
+            .unwrap()
+            .generate(thread_rng())
+            .unwrap();
+
+        let signature = DetachedSignature::sign_binary_data(thread_rng(), &key.secret_subkeys[0].key, &Password::empty(), HashAlgorithm::Sha256, &b"jar"[..])
+            .unwrap()
+            .to_armored_string(ArmorOptions::default())
+            .unwrap();
+
+        // Signed by a subkey bound for signing.
+        assert!(keyring(&key).verify(signature.as_bytes(), b"jar").is_ok());
+
+        let parse = |public: SignedPublicKey| Keyring::parse(public.to_armored_bytes(ArmorOptions::default()).unwrap().as_slice()).unwrap();
+
+        let revoked = parse(self_signed(&key, SignatureType::SubkeyRevocation, Timestamp::now(), Vec::new()));
+        assert!(revoked.verify(signature.as_bytes(), b"jar").unwrap_err().contains("revoked subkey"));
+
+        // The newest self signature says the key expired a day after it was created.
+        let expired = parse(self_signed(&key, SignatureType::Key, Timestamp::from_secs(Timestamp::now().as_secs() + 1), vec![
+            Subpacket::regular(SubpacketData::KeyExpirationTime(Duration::from_secs(24 * 60 * 60))).unwrap(),
+        ]));
+        assert!(expired.verify(signature.as_bytes(), b"jar").unwrap_err().contains("after it expired"));
+
+        // Made by a primary key which may only certify.
+        let certifying = SecretKeyParamsBuilder::default()
+            .key_type(KeyType::Ed25519Legacy)
+            .can_certify(true)
+            .can_sign(false)
+            .primary_user_id("Publisher <publisher@example.com>".into())
+            .build()
+            .unwrap()
+            .generate(thread_rng())
+            .unwrap();
+        let err = keyring(&certifying).verify(sign(&certifying, b"jar").as_bytes(), b"jar").unwrap_err();
+        assert!(err.contains("isn't allowed to sign"));
+    }
+
+    #[tokio::test]
+    async fn test_verify_upload() {
+        let key = secret_key();
+        let keyring = keyring(&key);
+
+        let access = MemoryResourceAccess::default();
+
+        let directory = tempfile::tempdir().unwrap();
+        let held = HeldUploads::open(&directory.path().join("held"), 16).unwrap();
+        let file = directory.path().join("upload");
+        std::fs::write(&file, "jar").unwrap();
+
+        // Files needing a signature are held until it is deployed, along with their checksums.
+        let jar = Path::new("rel/com/example/lib/1.0/lib-1.0.jar");
+        let checksum = Path::new("rel/com/example/lib/1.0/lib-1.0.jar.sha1");
+
+        assert_eq!(verify_upload(&access, &keyring, &held, "ci", jar, &file).await.unwrap(), SignatureCheck::Hold);
+        held.hold("ci", jar, &file).unwrap();
+        assert_eq!(verify_upload(&access, &keyring, &held, "ci", checksum, &file).await.unwrap(), SignatureCheck::Hold);
+        std::fs::write(&file, "sum").unwrap();
+        held.hold("ci", checksum, &file).unwrap();
+
+        // Each principal may only hold so much.
+        std::fs::write(&file, "a sources jar").unwrap();
+        assert_eq!(held.hold("ci", Path::new("rel/com/example/lib/1.0/lib-1.0-sources.jar"), &file).unwrap_err().status(), 507);
+        held.hold("other", Path::new("rel/com/example/lib/1.0/lib-1.0-sources.jar"), &file).unwrap();
+
+        // Only the principal who deployed a held file can sign it.
+        std::fs::write(&file, sign(&key, b"jar")).unwrap();
+        let signature = Path::new("rel/com/example/lib/1.0/lib-1.0.jar.asc");
+        assert_eq!(verify_upload(&access, &keyring, &held, "other", signature, &file).await.unwrap_err().status(), 422);
+
+        std::fs::write(&file, sign(&key, b"other")).unwrap();
+        assert_eq!(verify_upload(&access, &keyring, &held, "ci", signature, &file).await.unwrap_err().status(), 422);
+
+        std::fs::write(&file, sign(&key, b"jar")).unwrap();
+        assert!(matches!(
+            verify_upload(&access, &keyring, &held, "ci", signature, &file).await.unwrap(),
+            SignatureCheck::Verified(_)
+        ));
+
+        // Held files are shared by every instance using the directory.
+        let instance = HeldUploads::open(&directory.path().join("held"), 16).unwrap();
+        instance.publish(&access, "ci", jar).await.unwrap();
+        assert_eq!(access.read("rel/com/example/lib/1.0/lib-1.0.jar").as_deref(), Some("jar"));
+        assert_eq!(access.read("rel/com/example/lib/1.0/lib-1.0.jar.sha1").as_deref(), Some("sum"));
+        assert!(!held.is_held("ci", jar));
+        assert!(!held.is_held("ci", checksum));
+        assert!(held.is_held("other", Path::new("rel/com/example/lib/1.0/lib-1.0-sources.jar")));
+
+        // Signatures of files which are neither held nor deployed are refused.
+        assert_eq!(verify_upload(&access, &keyring, &held, "ci", Path::new("rel/com/example/lib/1.0/lib-1.0.pom.asc"), &file).await.unwrap_err().status(), 422);
+
+        access.insert("rel/com/example/lib/1.0/lib-1.0.jar.asc", "signature");
+        access.insert("rel/com/example/lib/1.0/lib-1.0.pom", "pom");
+
+        let metadata = Path::new("rel/com/example/lib/maven-metadata.xml");
+        std::fs::write(&file, "<metadata><versioning><versions><version>1.0</version></versions></versioning></metadata>").unwrap();
+
+        let err = verify_upload(&access, &keyring, &held, "ci", metadata, &file).await.unwrap_err();
+        assert_eq!(err.status(), 422);
+        assert!(err.message().contains("lib-1.0.pom"));
+
+        access.insert("rel/com/example/lib/1.0/lib-1.0.pom.asc", "signature");
+        assert_eq!(verify_upload(&access, &keyring, &held, "ci", metadata, &file).await.unwrap(), SignatureCheck::Accepted);
+    }
+}
@@ -0,0 +1,204 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::future::Future;
+
+use opentelemetry::{global, Context, KeyValue};
+use opentelemetry::context::FutureExt;
+use opentelemetry::trace::{SpanKind, Status, TraceContextExt, Tracer};
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::Resource;
+use opentelemetry_sdk::trace::SdkTracerProvider;
+use reqwest::{RequestBuilder, Response};
+use reqwest::header::{HeaderName, HeaderValue};
+use rocket::{async_trait, Data, Request, Response as RocketResponse, Route};
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::route::{Handler, Outcome};
+
+const TRACER: &str = "artifact-registry-proxy";
+
+/// Exports spans to the OTLP (HTTP) collector at the given endpoint, eg. `http://localhost:4318`,
+/// and propagates W3C trace context.
+pub fn install(endpoint: &str) -> Result<SdkTracerProvider, String> {
+    let exporter = SpanExporter::builder()
+        .with_http()
+        .with_endpoint(format!("{}/v1/traces", endpoint.trim_end_matches('/')))
+        .build()
+        .map_err(|err| err.to_string())?;
+
+    let provider = SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(Resource::builder().with_service_name(TRACER).build())
+        .build();
+
+    global::set_tracer_provider(provider.clone());
+    global::set_text_map_propagator(TraceContextPropagator::new());
+
+    Ok(provider)
+}
+
+/// Runs the future in a child span of the current one.
+pub async fn in_span<T>(name: &'static str, kind: SpanKind, future: impl Future<Output = T>) -> T {
+    let tracer = global::tracer(TRACER);
+    let span = tracer.span_builder(name)
+        .with_kind(kind)
+        .start(&tracer);
+
+    future.with_context(Context::current_with_span(span)).await
+}
+
+/// Runs the (blocking) function in a child span of the current one.
+pub fn in_sync_span<T>(name: &'static str, function: impl FnOnce() -> T) -> T {
+    let _guard = Context::current_with_span(global::tracer(TRACER).start(name)).attach();
+
+    function()
+}
+
+/// Sends the request in a client span, passing the trace on to the server.
+pub async fn send(request: RequestBuilder) -> Result<Response, reqwest::Error> {
+    let (client, request) = request.build_split();
+    let mut request = request?;
+
+    let tracer = global::tracer(TRACER);
+    let span = tracer.span_builder(format!("{} {}", request.method(), request.url().host_str().unwrap_or_default()))
+        .with_kind(SpanKind::Client)
+        .with_attributes([
+            KeyValue::new("http.request.method", request.method().to_string()),
+            KeyValue::new("url.full", request.url().to_string()),
+        ])
+        .start(&tracer);
+    let context = Context::current_with_span(span);
+
+    let mut headers = HashMap::new();
+    global::get_text_map_propagator(|propagator| propagator.inject_context(&context, &mut headers));
+
+    for (name, value) in headers {
+        if let (Ok(name), Ok(value)) = (HeaderName::try_from(name), HeaderValue::try_from(value)) {
+            request.headers_mut().insert(name, value);
+        }
+    }
+
+    let response = client.execute(request).with_context(context.clone()).await;
+    let span = context.span();
+
+    match &response {
+        Ok(response) => span.set_attribute(KeyValue::new("http.response.status_code", response.status().as_u16() as i64)),
+        Err(err) => span.set_status(Status::error(err.to_string()))
+    }
+
+    response
+}
+
+/// The trace context of a request, holding its span.
+struct RequestContext(Context);
+
+/// The trace context of the request, holding its span.
+pub fn request_context<'r>(request: &'r Request<'_>) -> &'r Context {
+    &request.local_cache(|| RequestContext(Context::new())).0
+}
+
+/// Starts a span for every request, continuing the trace of the caller's `traceparent`.
+pub struct RequestTracing;
+
+#[async_trait]
+impl Fairing for RequestTracing {
+    fn info(&self) -> Info {
+        Info {
+            name: "Request tracing",
+            kind: Kind::Request | Kind::Response,
+        }
+    }
+
+    async fn on_request(&self, request: &mut Request<'_>, _: &mut Data<'_>) {
+        let headers = request.headers().iter()
+            .map(|header| (header.name.as_str().to_lowercase(), header.value.to_string()))
+            .collect::<HashMap<String, String>>();
+        let parent = global::get_text_map_propagator(|propagator| propagator.extract(&headers));
+
+        let tracer = global::tracer(TRACER);
+        let span = tracer.span_builder(request.method().as_str())
+            .with_kind(SpanKind::Server)
+            .with_attributes([
+                KeyValue::new("http.request.method", request.method().as_str()),
+                KeyValue::new("url.path", request.uri().path().to_string()),
+            ])
+            .start_with_context(&tracer, &parent);
+
+        request.local_cache(|| RequestContext(parent.with_span(span)));
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut RocketResponse<'r>) {
+        let span = request_context(request).span();
+
+        if let Some(route) = request.route() {
+            span.update_name(Cow::Owned(format!("{} {}", request.method(), route.uri)));
+        }
+
+        span.set_attribute(KeyValue::new("http.response.status_code", response.status().code as i64));
+
+        if response.status().code >= 500 {
+            span.set_status(Status::error(response.status().reason_lossy()));
+        }
+
+        span.end();
+    }
+}
+
+/// Runs a route's handler within the request's span, so the spans it starts are children of it.
+#[derive(Clone)]
+struct TracedHandler(Box<dyn Handler>);
+
+#[async_trait]
+impl Handler for TracedHandler {
+    async fn handle<'r>(&self, request: &'r Request<'_>, data: Data<'r>) -> Outcome<'r> {
+        let context = request_context(request).clone();
+
+        self.0.handle(request, data).with_context(context).await
+    }
+}
+
+pub fn traced(routes: Vec<Route>) -> Vec<Route> {
+    routes.into_iter()
+        .map(|mut route| {
+            route.handler = Box::new(TracedHandler(route.handler));
+            route
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use opentelemetry::global;
+    use opentelemetry::trace::{SpanKind, TraceContextExt, Tracer};
+    use opentelemetry::Context;
+    use opentelemetry_sdk::trace::{InMemorySpanExporter, SdkTracerProvider};
+
+    use crate::trace::{in_span, in_sync_span, TRACER};
+
+    #[tokio::test]
+    async fn test_child_spans() {
+        let exporter = InMemorySpanExporter::default();
+        let provider = SdkTracerProvider::builder()
+            .with_simple_exporter(exporter.clone())
+            .build();
+        global::set_tracer_provider(provider.clone());
+
+        let parent = Context::current_with_span(global::tracer(TRACER).start("request"));
+        let trace_id = parent.span().span_context().trace_id();
+
+        let _guard = parent.attach();
+        in_span("get_key", SpanKind::Internal, async {
+            in_sync_span("cache read", || {});
+        }).await;
+
+        // The provider is global, so spans of tests running alongside are exported here too.
+        let spans = exporter.get_finished_spans().unwrap()
+            .into_iter()
+            .filter(|span| span.span_context.trace_id() == trace_id)
+            .collect::<Vec<_>>();
+        let names = spans.iter().map(|span| span.name.as_ref()).collect::<Vec<&str>>();
+
+        assert_eq!(names, vec!["cache read", "get_key"]);
+        assert_eq!(spans[0].parent_span_id, spans[1].span_context.span_id());
+    }
+}
@@ -0,0 +1,427 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use percent_encoding::{AsciiSet, NON_ALPHANUMERIC, utf8_percent_encode};
+use rocket::get;
+use rocket::response::content::RawHtml;
+use rocket::response::status;
+use rocket::serde::json::Json;
+
+use crate::{ARProxyConfiguration, ManagedResourceAccess};
+use crate::checksum::is_checksum;
+use crate::config::BackendConfiguration;
+use crate::err::{BasicError, IOError, RepositoryNotFound, ResourceNotFound, SerializableError};
+use crate::listing::escape;
+use crate::maven::Coordinates;
+use crate::maven::metadata::MavenMetadata;
+use crate::maven::pom::PomDetails;
+use crate::resource_access::{ResourceAccess, ResourceEntry};
+
+const STYLE: &str = "body{font-family:sans-serif;margin:2em auto;max-width:72em;padding:0 1em;color:#222}\
+a{color:#0b57d0;text-decoration:none}a:hover{text-decoration:underline}\
+table{border-collapse:collapse;width:100%;margin-bottom:1.5em}th,td{text-align:left;padding:.3em .6em;border-bottom:1px solid #ddd}\
+td.hash{font-family:monospace;font-size:.8em;word-break:break-all}nav{margin-bottom:1em}\
+pre{background:#f5f5f5;padding:.8em;margin:.3em 0 1em;overflow-x:auto}button{float:right}";
+
+/// Left as is in the segments of links, everything else is percent-encoded.
+const SEGMENT: &AsciiSet = &NON_ALPHANUMERIC.remove(b'-').remove(b'.').remove(b'_').remove(b'~');
+
+/// Joins the segments into an absolute, percent-encoded link, eg. '/ui/releases/com/example/'.
+fn href<'a>(segments: impl IntoIterator<Item = &'a str>, directory: bool) -> String {
+    let mut href = segments.into_iter()
+        .map(|segment| format!("/{}", utf8_percent_encode(segment, SEGMENT)))
+        .collect::<String>();
+
+    if directory {
+        href.push('/');
+    }
+
+    href
+}
+
+fn segments(path: &Path) -> impl Iterator<Item = &str> {
+    path.iter().map(|segment| segment.to_str().unwrap())
+}
+
+fn page(title: &str, navigation: &str, body: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n<title>{0}</title>\n<style>{1}</style>\n</head>\n<body>\n<nav>{2}</nav>\n<h1>{0}</h1>\n{3}</body>\n</html>\n",
+        escape(title),
+        STYLE,
+        navigation,
+        body
+    )
+}
+
+fn link(href: &str, text: &str) -> String {
+    format!("<a href=\"{}\">{}</a>", escape(href), escape(text))
+}
+
+/// Links to the repository overview and each directory down to the path.
+fn navigation(repository: &str, path: &Path) -> String {
+    let mut directory = vec!["ui", repository];
+    let mut links = vec![link("/ui", "Repositories"), link(&href(directory.clone(), true), repository)];
+
+    for segment in segments(path) {
+        directory.push(segment);
+        links.push(link(&href(directory.clone(), true), segment));
+    }
+
+    links.join(" / ")
+}
+
+/// Formats a size in bytes, eg. '1.5 MiB'.
+fn human_size(size: u64) -> String {
+    let mut value = size as f64;
+
+    for unit in ["B", "KiB", "MiB", "GiB"] {
+        if value < 1024.0 || unit == "GiB" {
+            return match unit {
+                "B" => format!("{} B", size),
+                _ => format!("{:.1} {}", value, unit)
+            };
+        }
+
+        value /= 1024.0;
+    }
+
+    unreachable!()
+}
+
+/// The type a dependency on an artifact of the packaging declares, `None` for packagings which
+/// produce a jar (the default type).
+fn dependency_type(packaging: &str) -> Option<&str> {
+    match packaging {
+        "jar" | "bundle" | "maven-plugin" | "ejb" => None,
+        packaging => Some(packaging)
+    }
+}
+
+/// Copy-paste snippets declaring a dependency on the version, for each build tool.
+pub fn dependency_snippets(coordinates: &Coordinates, packaging: &str) -> [(&'static str, String); 5] {
+    let Coordinates { group_id, artifact_id, version } = coordinates;
+    let dependency_type = dependency_type(packaging);
+
+    let maven_type = dependency_type
+        .map(|dependency_type| format!("\n    <type>{}</type>", dependency_type))
+        .unwrap_or_default();
+    let gradle_type = dependency_type
+        .map(|dependency_type| format!("@{}", dependency_type))
+        .unwrap_or_default();
+    let sbt_type = dependency_type
+        .map(|dependency_type| format!(" artifacts Artifact(\"{}\", \"{1}\", \"{1}\")", artifact_id, dependency_type))
+        .unwrap_or_default();
+    let ivy = match dependency_type {
+        Some(dependency_type) => format!(
+            "<dependency org=\"{}\" name=\"{}\" rev=\"{}\">\n    <artifact name=\"{1}\" type=\"{3}\" ext=\"{3}\" />\n</dependency>",
+            group_id, artifact_id, version, dependency_type
+        ),
+        None => format!("<dependency org=\"{}\" name=\"{}\" rev=\"{}\" />", group_id, artifact_id, version)
+    };
+
+    [
+        ("Maven", format!(
+            "<dependency>\n    <groupId>{}</groupId>\n    <artifactId>{}</artifactId>\n    <version>{}</version>{}\n</dependency>",
+            group_id, artifact_id, version, maven_type
+        )),
+        ("Gradle (Kotlin)", format!("implementation(\"{}:{}:{}{}\")", group_id, artifact_id, version, gradle_type)),
+        ("Gradle (Groovy)", format!("implementation '{}:{}:{}{}'", group_id, artifact_id, version, gradle_type)),
+        ("sbt", format!("libraryDependencies += \"{}\" % \"{}\" % \"{}\"{}", group_id, artifact_id, version, sbt_type)),
+        ("Ivy", ivy),
+    ]
+}
+
+async fn read(resource_access: &(dyn ResourceAccess + Send + Sync), path: PathBuf) -> Result<String, Box<dyn SerializableError>> {
+    let file = resource_access.get_resource(path).await?;
+
+    fs::read_to_string(&file).map_err(|e| Box::new(IOError(e)) as Box<dyn SerializableError>)
+}
+
+fn render_directory(
+    repository: &str,
+    path: &Path,
+    entries: &[ResourceEntry],
+    metadata: Option<MavenMetadata>,
+) -> String {
+    let mut body = String::new();
+
+    if let Some(metadata) = metadata {
+        body.push_str(&format!(
+            "<p>{} version(s), latest: {}, latest release: {}</p>\n",
+            metadata.versions.len(),
+            escape(metadata.latest_version().unwrap_or("-")),
+            escape(metadata.latest_release().unwrap_or("-"))
+        ));
+    }
+
+    body.push_str("<table>\n<tr><th>Name</th><th>Uploaded</th><th>Size</th></tr>\n");
+
+    let directory = PathBuf::from(repository).join(path);
+
+    for entry in entries {
+        let name = match entry.directory {
+            true => link(&href(["ui"].into_iter().chain(segments(&directory)).chain([entry.name.as_str()]), true), &format!("{}/", entry.name)),
+            false => link(&href(segments(&directory).chain([entry.name.as_str()]), false), &entry.name)
+        };
+
+        body.push_str(&format!(
+            "<tr><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+            name,
+            entry.updated.as_deref().map(escape).unwrap_or_default(),
+            entry.size.map(human_size).unwrap_or_default()
+        ));
+    }
+
+    body.push_str("</table>\n");
+
+    page(&format!("/{}/", directory.to_str().unwrap().trim_end_matches('/')), &navigation(repository, path), &body)
+}
+
+fn render_pom(details: &PomDetails) -> String {
+    let mut body = String::from("<h2>Project</h2>\n<table>\n");
+
+    let url = details.url.as_ref()
+        .filter(|url| url.starts_with("https://") || url.starts_with("http://"))
+        .map(|url| link(url, url));
+
+    for (name, value) in [
+        ("Name", details.name.as_deref().map(escape)),
+        ("Description", details.description.as_deref().map(escape)),
+        ("URL", url),
+        ("Packaging", Some(escape(&details.packaging))),
+        ("Licenses", Some(escape(&details.licenses.join(", "))).filter(|licenses| !licenses.is_empty())),
+    ] {
+        if let Some(value) = value {
+            body.push_str(&format!("<tr><th>{}</th><td>{}</td></tr>\n", name, value));
+        }
+    }
+
+    body.push_str("</table>\n");
+
+    if !details.dependencies.is_empty() {
+        body.push_str("<h2>Dependencies</h2>\n<table>\n<tr><th>Group</th><th>Artifact</th><th>Version</th><th>Scope</th></tr>\n");
+
+        for dependency in &details.dependencies {
+            body.push_str(&format!(
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+                escape(&dependency.group_id),
+                escape(&dependency.artifact_id),
+                escape(dependency.version.as_deref().unwrap_or("managed")),
+                escape(dependency.scope.as_deref().unwrap_or("compile"))
+            ));
+        }
+
+        body.push_str("</table>\n");
+    }
+
+    body
+}
+
+async fn render_version(
+    resource_access: &(dyn ResourceAccess + Send + Sync),
+    (public_name, repository): (&str, &str),
+    path: &Path,
+    coordinates: &Coordinates,
+    entries: &[ResourceEntry],
+) -> Result<String, Box<dyn SerializableError>> {
+    let directory = PathBuf::from(repository).join(path);
+    let names = entries.iter().map(|entry| entry.name.as_str()).collect::<Vec<&str>>();
+
+    // A POM which can't be read or parsed only leaves its details out.
+    let pom = entries.iter().find(|entry| !entry.directory && entry.name.ends_with(".pom"));
+    let details = match pom {
+        Some(pom) => read(resource_access, directory.join(&pom.name)).await
+            .ok()
+            .and_then(|pom| PomDetails::parse(&pom).ok()),
+        None => None
+    };
+
+    let mut body = String::new();
+
+    if let Some(details) = &details {
+        body.push_str(&render_pom(details));
+    }
+
+    body.push_str("<h2>Usage</h2>\n");
+
+    let packaging = details.as_ref().map(|details| details.packaging.as_str()).unwrap_or("jar");
+
+    for (tool, snippet) in dependency_snippets(coordinates, packaging) {
+        body.push_str(&format!(
+            "<h3>{}</h3>\n<button onclick=\"navigator.clipboard.writeText(this.nextElementSibling.textContent)\">Copy</button><pre>{}</pre>\n",
+            tool,
+            escape(&snippet)
+        ));
+    }
+
+    body.push_str("<h2>Files</h2>\n<table>\n<tr><th>Name</th><th>Uploaded</th><th>Size</th><th>SHA-1</th><th>SHA-256</th></tr>\n");
+
+    for entry in entries.iter().filter(|entry| !entry.directory && !is_checksum(Path::new(&entry.name))) {
+        let mut hashes = Vec::new();
+
+        // Checksums are read from the files published next to the file, when there are any.
+        for extension in ["sha1", "sha256"] {
+            let checksum = format!("{}.{}", entry.name, extension);
+
+            hashes.push(match names.contains(&checksum.as_str()) {
+                true => read(resource_access, directory.join(&checksum)).await
+                    .map(|checksum| checksum.split_whitespace().next().unwrap_or_default().to_string())
+                    .unwrap_or_default(),
+                false => String::new()
+            });
+        }
+
+        body.push_str(&format!(
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td class=\"hash\">{}</td><td class=\"hash\">{}</td></tr>\n",
+            link(&href([public_name].into_iter().chain(segments(path)).chain([entry.name.as_str()]), false), &entry.name),
+            entry.updated.as_deref().map(escape).unwrap_or_default(),
+            entry.size.map(human_size).unwrap_or_default(),
+            escape(&hashes[0]),
+            escape(&hashes[1])
+        ));
+    }
+
+    body.push_str("</table>\n");
+
+    Ok(page(
+        &format!("{}:{}:{}", coordinates.group_id, coordinates.artifact_id, coordinates.version),
+        &navigation(public_name, path),
+        &body,
+    ))
+}
+
+/// Renders the page of a directory, or of a version when the directory holds one's files.
+pub async fn render_path(
+    resource_access: &(dyn ResourceAccess + Send + Sync),
+    (public_name, repository): (&str, &str),
+    path: &Path,
+) -> Result<String, Box<dyn SerializableError>> {
+    let directory = PathBuf::from(repository).join(path);
+    let entries = resource_access.list(directory.clone()).await?;
+
+    if entries.is_empty() && !path.as_os_str().is_empty() {
+        return Err(Box::new(ResourceNotFound(path.to_str().unwrap().to_string())));
+    }
+
+    let version = Coordinates::from_version_path(path).filter(|coordinates| entries.iter()
+        .any(|entry| !entry.directory && Coordinates::from_file_path(&path.join(&entry.name)).as_ref() == Some(coordinates)));
+
+    if let Some(coordinates) = version {
+        return render_version(resource_access, (public_name, repository), path, &coordinates, &entries).await;
+    }
+
+    let metadata = match entries.iter().any(|entry| entry.name == "maven-metadata.xml") {
+        true => read(resource_access, directory.join("maven-metadata.xml")).await
+            .ok()
+            .and_then(|metadata| MavenMetadata::parse(&metadata).ok()),
+        false => None
+    };
+
+    Ok(render_directory(public_name, path, &entries, metadata))
+}
+
+/// The overview of every repository.
+#[get("/ui")]
+pub async fn ui_repositories(configuration: &ARProxyConfiguration) -> RawHtml<String> {
+    let mut repositories = configuration.repositories.iter().collect::<Vec<_>>();
+    repositories.sort_by_key(|(name, _)| *name);
+
+    let mut body = String::from("<table>\n<tr><th>Repository</th><th>Backend</th><th>Deploy policy</th><th>Signed</th></tr>\n");
+
+    for (name, repository) in repositories {
+        let backend = match configuration.backends.get(&repository.backend) {
+            Some(BackendConfiguration::ArtifactRegistry { .. }) => "Artifact Registry",
+            Some(BackendConfiguration::Filesystem { .. }) => "Filesystem",
+            None => ""
+        };
+
+        body.push_str(&format!(
+            "<tr><td>{}</td><td>{} ({})</td><td>{}</td><td>{}</td></tr>\n",
+            link(&href(["ui", name.as_str()], true), name),
+            escape(&repository.backend),
+            backend,
+            repository.deploy_policy.as_str(),
+            if repository.signer.is_some() || repository.keyring.is_some() { "yes" } else { "no" }
+        ));
+    }
+
+    body.push_str("</table>\n");
+
+    RawHtml(page("Repositories", &link("/ui", "Repositories"), &body))
+}
+
+#[get("/ui/<repository>/<path..>")]
+pub async fn ui_browse(
+    repository: &str,
+    path: PathBuf,
+    resource_access: &ManagedResourceAccess,
+    configuration: &ARProxyConfiguration,
+) -> Result<RawHtml<String>, status::Custom<Json<BasicError>>> {
+    let id = &configuration.repositories.get(repository).ok_or(
+        BasicError::from(Box::new(RepositoryNotFound(repository.to_string())))
+    )?.id;
+
+    render_path(resource_access.as_ref(), (repository, id), &path)
+        .await
+        .map(RawHtml)
+        .map_err(BasicError::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use crate::maven::Coordinates;
+    use crate::resource_access::memory::MemoryResourceAccess;
+    use crate::ui::{dependency_snippets, render_path};
+
+    #[tokio::test]
+    async fn test_version_page() {
+        let memory = MemoryResourceAccess::default();
+        memory.insert("releases-id/com/example/lib/maven-metadata.xml", "<metadata><versioning><release>1.0</release><versions><version>1.0</version></versions></versioning></metadata>");
+        memory.insert("releases-id/com/example/lib/1.0/lib-1.0.jar", "jar");
+        memory.insert("releases-id/com/example/lib/1.0/lib-1.0.jar.sha1", "c1e4a5c5a1b3c5e0c1e4a5c5a1b3c5e0c1e4a5c5");
+        memory.insert("releases-id/com/example/lib/1.0/lib-1.0.pom", "<project><name>Example &amp; co</name></project>");
+
+        let page = render_path(&memory, ("releases", "releases-id"), Path::new("com/example/lib/1.0")).await.unwrap();
+
+        assert!(page.contains("<h1>com.example:lib:1.0</h1>"));
+        assert!(page.contains("<td>Example &amp; co</td>"));
+        assert!(page.contains("implementation(&quot;com.example:lib:1.0&quot;)"));
+        assert!(page.contains("<td class=\"hash\">c1e4a5c5a1b3c5e0c1e4a5c5a1b3c5e0c1e4a5c5</td>"));
+        assert!(page.contains("href=\"/releases/com/example/lib/1.0/lib-1.0.jar\""));
+        assert!(!page.contains(">lib-1.0.jar.sha1<"));
+
+        let page = render_path(&memory, ("releases", "releases-id"), Path::new("com/example/lib")).await.unwrap();
+
+        assert!(page.contains("1 version(s), latest: 1.0, latest release: 1.0"));
+        assert!(page.contains("href=\"/ui/releases/com/example/lib/1.0/\""));
+
+        // An unreadable POM leaves its details out, and links are percent-encoded.
+        memory.insert("releases-id/com/example/lib/2.0/lib-2.0 #1.jar", "jar");
+        memory.files.lock().unwrap().insert(PathBuf::from("releases-id/com/example/lib/2.0/lib-2.0.pom"), vec![0xff, 0xfe]);
+
+        let page = render_path(&memory, ("releases", "releases-id"), Path::new("com/example/lib/2.0")).await.unwrap();
+
+        assert!(page.contains("<h1>com.example:lib:2.0</h1>"));
+        assert!(!page.contains("<h2>Project</h2>"));
+        assert!(page.contains("href=\"/releases/com/example/lib/2.0/lib-2.0%20%231.jar\""));
+    }
+
+    #[test]
+    fn test_dependency_snippets() {
+        let coordinates = Coordinates::from_version_path(Path::new("com/example/lib/1.0")).unwrap();
+
+        let [(_, maven), (_, kotlin), _, (_, sbt), (_, ivy)] = dependency_snippets(&coordinates, "aar");
+        assert!(maven.contains("<type>aar</type>"));
+        assert_eq!(kotlin, "implementation(\"com.example:lib:1.0@aar\")");
+        assert!(sbt.ends_with("artifacts Artifact(\"lib\", \"aar\", \"aar\")"));
+        assert!(ivy.contains("<artifact name=\"lib\" type=\"aar\" ext=\"aar\" />"));
+
+        for packaging in ["jar", "bundle", "maven-plugin", "ejb"] {
+            let [(_, maven), (_, kotlin), ..] = dependency_snippets(&coordinates, packaging);
+            assert!(!maven.contains("<type>"), "{}", packaging);
+            assert_eq!(kotlin, "implementation(\"com.example:lib:1.0\")");
+        }
+    }
+}
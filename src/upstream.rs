@@ -0,0 +1,257 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use log::warn;
+use metrics::counter;
+use rand::Rng;
+use reqwest::{Client, ClientBuilder, Method, Proxy, RequestBuilder, Response, StatusCode};
+use reqwest::header::RETRY_AFTER;
+use tokio::time::Instant;
+
+use crate::trace::send;
+
+/// How requests to backends are timed out and retried.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UpstreamConfiguration {
+    /// How long connecting to a backend may take.
+    pub connect_timeout: Duration,
+    /// How long a response may go without receiving any data.
+    pub read_timeout: Duration,
+    /// How long a request may take in all, including its retries.
+    pub request_timeout: Duration,
+    /// How many times a failed GET or HEAD request is retried, 0 disables retries.
+    pub max_retries: u32,
+    /// The backoff before the first retry, doubled with every retry after.
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// How many idle connections are kept open to each host.
+    pub pool_max_idle_per_host: usize,
+    /// How long an idle connection is kept open.
+    pub pool_idle_timeout: Duration,
+    /// Whether HTTP/2 is negotiated with backends that support it, HTTP/1.1 is used otherwise.
+    pub http2: bool,
+    /// How often TCP keepalive probes are sent on idle connections, `None` disables them.
+    pub tcp_keepalive: Option<Duration>,
+    /// The proxy every request is sent through, eg. `http://proxy:3128`. The `HTTPS_PROXY`
+    /// environmental variable is used when not set.
+    pub proxy: Option<String>,
+    /// How many consecutive failures open a backend's circuit, 0 never opens it.
+    pub breaker_threshold: u32,
+    /// How long an open circuit fails requests before letting a trial through.
+    pub breaker_cooldown: Duration,
+}
+
+impl Default for UpstreamConfiguration {
+    fn default() -> Self {
+        UpstreamConfiguration {
+            connect_timeout: Duration::from_secs(10),
+            read_timeout: Duration::from_secs(60),
+            request_timeout: Duration::from_secs(600),
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(200),
+            max_backoff: Duration::from_secs(10),
+            pool_max_idle_per_host: 32,
+            pool_idle_timeout: Duration::from_secs(90),
+            http2: true,
+            tcp_keepalive: Some(Duration::from_secs(60)),
+            proxy: None,
+            breaker_threshold: 5,
+            breaker_cooldown: Duration::from_secs(30),
+        }
+    }
+}
+
+impl UpstreamConfiguration {
+    /// A client applying the settings. Its connections are pooled, so it should be built once and
+    /// shared by every request to the backend.
+    pub fn client(&self) -> Result<Client, reqwest::Error> {
+        self.client_builder()?.build()
+    }
+
+    /// The builder `client` builds with, for adding settings (eg. a root certificate) on top.
+    pub fn client_builder(&self) -> Result<ClientBuilder, reqwest::Error> {
+        let mut builder = Client::builder()
+            .connect_timeout(self.connect_timeout)
+            .read_timeout(self.read_timeout)
+            .timeout(self.request_timeout)
+            .pool_max_idle_per_host(self.pool_max_idle_per_host)
+            .pool_idle_timeout(self.pool_idle_timeout)
+            .tcp_keepalive(self.tcp_keepalive);
+
+        if !self.http2 {
+            builder = builder.http1_only();
+        }
+
+        if let Some(proxy) = &self.proxy {
+            builder = builder.proxy(Proxy::all(proxy)?);
+        }
+
+        Ok(builder)
+    }
+
+    /// A random delay of up to `initial_backoff * 2^retry`, capped at `max_backoff`.
+    fn backoff(&self, retry: u32) -> Duration {
+        let ceiling = self.initial_backoff
+            .saturating_mul(2u32.saturating_pow(retry))
+            .min(self.max_backoff);
+
+        rand::thread_rng().gen_range(Duration::ZERO..=ceiling)
+    }
+}
+
+/// Whether the request only reads, so sending it again can't change what a failed (but
+/// possibly applied) attempt did. A retried PUT could answer a conflict, or a DELETE not found.
+fn is_retriable_method(method: &Method) -> bool {
+    matches!(*method, Method::GET | Method::HEAD)
+}
+
+fn is_retriable(status: StatusCode) -> bool {
+    matches!(status.as_u16(), 408 | 429 | 500 | 502 | 503 | 504)
+}
+
+/// The delay asked for by a `Retry-After` header, either a number of seconds or an HTTP date.
+fn parse_retry_after(value: &str, now: DateTime<Utc>) -> Option<Duration> {
+    let value = value.trim();
+
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+
+    Some((date.with_timezone(&Utc) - now).to_std().unwrap_or(Duration::ZERO))
+}
+
+/// Sends the request, retrying it when it fails to connect, times out or is answered with a
+/// retriable status (408, 429, 500, 502, 503 or 504). Retries wait for a jittered exponential
+/// backoff, or for as long as the backend's `Retry-After` asks. When that is longer than the
+/// maximum backoff the response is returned as is. Only GET and HEAD requests are retried, and
+/// once `request_timeout` has passed the last failure is returned.
+pub async fn send_with_retries(request: RequestBuilder, configuration: &UpstreamConfiguration) -> Result<Response, reqwest::Error> {
+    let (client, request) = request.build_split();
+    let request = request?;
+    let deadline = Instant::now() + configuration.request_timeout;
+    let mut retry = 0;
+
+    loop {
+        let attempt = match request.try_clone() {
+            Some(attempt) if retry < configuration.max_retries && is_retriable_method(request.method()) => attempt,
+            _ => return send(RequestBuilder::from_parts(client, request)).await
+        };
+
+        let result = send(RequestBuilder::from_parts(client.clone(), attempt)).await;
+
+        let (delay, reason) = match &result {
+            Ok(response) if is_retriable(response.status()) => {
+                let retry_after = response.headers().get(RETRY_AFTER)
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(|value| parse_retry_after(value, Utc::now()));
+
+                match retry_after {
+                    Some(delay) if delay > configuration.max_backoff => return result,
+                    Some(delay) => (delay, response.status().as_str().to_string()),
+                    None => (configuration.backoff(retry), response.status().as_str().to_string())
+                }
+            }
+            Err(err) if err.is_timeout() => (configuration.backoff(retry), "timeout".to_string()),
+            Err(err) if err.is_connect() || err.is_request() => (configuration.backoff(retry), "connection".to_string()),
+            _ => return result
+        };
+
+        if Instant::now() + delay >= deadline {
+            return result;
+        }
+
+        warn!(
+            "Retrying: '{} {}' in {}ms after: '{}' (retry {} of {})",
+            request.method(), request.url(), delay.as_millis(), reason, retry + 1, configuration.max_retries
+        );
+        counter!("arp_upstream_retries_total", "reason" => reason).increment(1);
+
+        tokio::time::sleep(delay).await;
+        retry += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use chrono::{TimeZone, Utc};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    use crate::upstream::{parse_retry_after, send_with_retries, UpstreamConfiguration};
+
+    #[test]
+    fn test_parse_retry_after() {
+        let now = Utc.with_ymd_and_hms(2015, 10, 21, 7, 28, 0).unwrap();
+
+        assert_eq!(parse_retry_after("120", now), Some(Duration::from_secs(120)));
+        assert_eq!(parse_retry_after("Wed, 21 Oct 2015 07:28:30 GMT", now), Some(Duration::from_secs(30)));
+        assert_eq!(parse_retry_after("Wed, 21 Oct 2015 07:27:00 GMT", now), Some(Duration::ZERO));
+        assert_eq!(parse_retry_after("soon", now), None);
+
+        let configuration = UpstreamConfiguration::default();
+
+        for retry in 0..10 {
+            assert!(configuration.backoff(retry) <= configuration.max_backoff);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_send_with_retries() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/resource", listener.local_addr().unwrap());
+
+        let server = tokio::spawn(async move {
+            for response in [
+                "HTTP/1.1 503 Service Unavailable\r\nRetry-After: 0\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                "HTTP/1.1 502 Bad Gateway\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                "HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok",
+            ] {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut buffer = [0; 1024];
+                let _ = stream.read(&mut buffer).await.unwrap();
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+
+        let configuration = UpstreamConfiguration {
+            initial_backoff: Duration::from_millis(1),
+            ..UpstreamConfiguration::default()
+        };
+
+        let response = send_with_retries(configuration.client().unwrap().get(&url), &configuration).await.unwrap();
+
+        assert_eq!(response.status().as_u16(), 200);
+        assert_eq!(response.text().await.unwrap(), "ok");
+
+        server.await.unwrap();
+
+        // A PUT isn't retried, and neither is a GET which would outlast the request timeout, so
+        // the failure is returned as is.
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/resource", listener.local_addr().unwrap());
+
+        tokio::spawn(async move {
+            for _ in 0..2 {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut buffer = [0; 1024];
+                let _ = stream.read(&mut buffer).await.unwrap();
+                stream.write_all(b"HTTP/1.1 503 Service Unavailable\r\nRetry-After: 1\r\nContent-Length: 0\r\nConnection: close\r\n\r\n").await.unwrap();
+            }
+        });
+
+        let response = send_with_retries(configuration.client().unwrap().put(&url), &configuration).await.unwrap();
+        assert_eq!(response.status().as_u16(), 503);
+
+        let configuration = UpstreamConfiguration {
+            request_timeout: Duration::from_millis(500),
+            ..configuration
+        };
+
+        let response = send_with_retries(configuration.client().unwrap().get(&url), &configuration).await.unwrap();
+        assert_eq!(response.status().as_u16(), 503);
+    }
+}